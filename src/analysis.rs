@@ -0,0 +1,99 @@
+use crate::{
+    piece::Colour,
+    search::{PvLine, Score, SearchInfo},
+};
+
+// What the engine's found so far analysing the position on the board, kept
+// up to date from its search info
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Analysis {
+    pub depth: u32,
+    pub nodes_per_second: u64,
+    // The best lines, best first, scored for the side to move
+    pub lines: Vec<PvLine>,
+}
+
+impl Analysis {
+    pub fn update(&mut self, info: &SearchInfo) {
+        let millis = info.time.as_millis().max(1) as u64;
+        self.depth = info.depth;
+        self.nodes_per_second = info.nodes * 1000 / millis;
+
+        let line = PvLine {
+            score: info.score,
+            pv: info.pv.clone(),
+        };
+        match self.lines.get_mut(info.multipv - 1) {
+            Some(old) => *old = line,
+            None => self.lines.push(line),
+        }
+    }
+}
+
+// The score from white's side, the way it's usually shown
+pub fn white_score(score: Score, side_to_move: Colour) -> Score {
+    match (side_to_move, score) {
+        (Colour::White, _) => score,
+        (Colour::Black, Score::Centipawns(centipawns)) => Score::Centipawns(-centipawns),
+        (Colour::Black, Score::Mate(moves)) => Score::Mate(-moves),
+    }
+}
+
+// Pawns with a sign, or moves to mate: +0.35, -1.20, #3, #-2
+pub fn format_eval(score: Score) -> String {
+    match score {
+        Score::Centipawns(centipawns) => format!("{:+.2}", centipawns as f64 / 100.0),
+        Score::Mate(moves) => format!("#{}", moves),
+    }
+}
+
+// How much of the eval bar is white's, from 0 to 1. A pawn up is about 64%.
+pub fn white_share(score: Score) -> f64 {
+    match score {
+        Score::Centipawns(centipawns) => 1.0 / (1.0 + 10f64.powf(-centipawns as f64 / 400.0)),
+        Score::Mate(moves) if moves > 0 => 1.0,
+        Score::Mate(_) => 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn info(depth: u32, multipv: usize, centipawns: i32) -> SearchInfo {
+        SearchInfo {
+            depth,
+            multipv,
+            score: Score::Centipawns(centipawns),
+            nodes: 5000,
+            time: Duration::from_millis(10),
+            pv: Vec::new(),
+            hashfull: 0,
+            tbhits: 0,
+        }
+    }
+
+    #[test]
+    fn test_update() {
+        let mut analysis = Analysis::default();
+        analysis.update(&info(1, 1, 30));
+        analysis.update(&info(1, 2, 10));
+        analysis.update(&info(2, 1, 25));
+        assert_eq!(analysis.depth, 2);
+        assert_eq!(analysis.nodes_per_second, 500_000);
+        let scores: Vec<Score> = analysis.lines.iter().map(|line| line.score).collect();
+        assert_eq!(scores, [Score::Centipawns(25), Score::Centipawns(10)]);
+    }
+
+    #[test]
+    fn test_eval() {
+        assert_eq!(white_score(Score::Mate(2), Colour::Black), Score::Mate(-2));
+        assert_eq!(format_eval(Score::Centipawns(35)), "+0.35");
+        assert_eq!(format_eval(Score::Centipawns(-120)), "-1.20");
+        assert_eq!(format_eval(Score::Mate(-2)), "#-2");
+        assert_eq!(white_share(Score::Centipawns(0)), 0.5);
+        assert!(white_share(Score::Centipawns(100)) > 0.6);
+        assert_eq!(white_share(Score::Mate(-1)), 0.0);
+    }
+}
@@ -0,0 +1,462 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+
+use crate::{
+    analysis::white_score,
+    chess_board::{ChessBoard, START_FEN},
+    chess_move::ChessMove,
+    game_result::GameResult,
+    pgn::{parse_pgn, write_pgn, PgnGame},
+    piece::Colour,
+    san::{format_line, parse_san, to_san},
+    search::{search, Score, SearchLimits, SearchParams, SearchResult},
+    syzygy::Tablebase,
+    tt::TranspositionTable,
+};
+
+pub const DEFAULT_DEPTH: u32 = 10;
+
+// Centipawns lost by a move, from the side that played it
+const INACCURACY: i32 = 50;
+const MISTAKE: i32 = 100;
+const BLUNDER: i32 = 300;
+// Beyond this it's won either way, so going from +15 to +10 isn't a mistake
+const MAX_EVAL: i32 = 1000;
+// Plies of the better line to give as a variation
+const VARIATION_LENGTH: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Judgement {
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+impl Judgement {
+    pub fn from_loss(centipawns: i32) -> Option<Judgement> {
+        match centipawns {
+            _ if centipawns >= BLUNDER => Some(Judgement::Blunder),
+            _ if centipawns >= MISTAKE => Some(Judgement::Mistake),
+            _ if centipawns >= INACCURACY => Some(Judgement::Inaccuracy),
+            _ => None,
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Judgement::Inaccuracy => "?!",
+            Judgement::Mistake => "?",
+            Judgement::Blunder => "??",
+        }
+    }
+
+    // The PGN numeric annotation glyph for the symbol
+    pub fn nag(self) -> &'static str {
+        match self {
+            Judgement::Inaccuracy => "$6",
+            Judgement::Mistake => "$2",
+            Judgement::Blunder => "$4",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Judgement::Inaccuracy => "Inaccuracy",
+            Judgement::Mistake => "Mistake",
+            Judgement::Blunder => "Blunder",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AnnotatedMove {
+    pub colour: Colour,
+    pub fullmove_number: u32,
+    pub san: String,
+    // The eval after the move, from white's side
+    pub eval: Score,
+    pub judgement: Option<Judgement>,
+    // What the engine would have played instead, and its line from there
+    pub best_move: Option<String>,
+    pub best_line: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Annotation {
+    pub tags: Vec<(String, String)>,
+    pub moves: Vec<AnnotatedMove>,
+    pub result: String,
+}
+
+// Won, lost or in between, from the side to move, with mates at the ends
+fn capped(score: Score) -> i32 {
+    match score {
+        Score::Centipawns(centipawns) => centipawns.clamp(-MAX_EVAL, MAX_EVAL),
+        Score::Mate(moves) if moves > 0 => MAX_EVAL,
+        Score::Mate(_) => -MAX_EVAL,
+    }
+}
+
+fn search_depth(
+    board: &mut ChessBoard,
+    depth: u32,
+    tt: &TranspositionTable,
+    tablebase: Option<&Tablebase>,
+) -> SearchResult {
+    let limits = SearchLimits {
+        depth: Some(depth),
+        ..SearchLimits::default()
+    };
+    let stop = AtomicBool::new(false);
+    search(
+        board,
+        &[],
+        &limits,
+        &SearchParams::default(),
+        tt,
+        tablebase,
+        &stop,
+        |_| {},
+    )
+}
+
+// Searches the position before and after every move of the game, and marks
+// the moves that threw away too much of the eval. `on_ply` is told each time
+// a move's done, with how many there are.
+pub fn annotate<F>(
+    game: &PgnGame,
+    depth: u32,
+    tablebase: Option<&Tablebase>,
+    mut on_ply: F,
+) -> Result<Annotation, String>
+where
+    F: FnMut(usize, usize),
+{
+    let mut board = ChessBoard::from_fen(game.tag("FEN").unwrap_or(START_FEN));
+    let tt = TranspositionTable::new(16);
+    let mut before = search_depth(&mut board, depth, &tt, tablebase);
+    let mut moves = Vec::new();
+
+    for (ply, san) in game.moves.iter().enumerate() {
+        let chess_move =
+            parse_san(&mut board, san).ok_or_else(|| format!("illegal move {}", san))?;
+        let colour = board.side_to_move();
+        let fullmove_number = board.fullmove_number();
+        let played = to_san(&mut board, chess_move);
+
+        let (mut best_move, mut best_line) = (None, String::new());
+        if let Some(best) = before.best_move.filter(|&best| best != chess_move) {
+            best_move = Some(to_san(&mut board, best));
+            let line = &before.pv[..before.pv.len().min(VARIATION_LENGTH)];
+            best_line = format_line(&board, line);
+        }
+
+        board.make_move(chess_move);
+        // Only a move other than the engine's choice can lose anything. Its
+        // reply is searched a ply shallower, so both lines look as far ahead
+        // from the position before the move and the eval's odd and even
+        // depth swings don't count against it.
+        let mut judgement = None;
+        if best_move.is_some() {
+            let reply = search_depth(&mut board, depth.max(2) - 1, &tt, tablebase);
+            judgement = Judgement::from_loss(capped(before.score) + capped(reply.score));
+        }
+        if judgement.is_none() {
+            best_move = None;
+            best_line.clear();
+        }
+        let after = search_depth(&mut board, depth, &tt, tablebase);
+
+        moves.push(AnnotatedMove {
+            colour,
+            fullmove_number,
+            san: played,
+            eval: white_score(after.score, board.side_to_move()),
+            judgement,
+            best_move,
+            best_line,
+        });
+        on_ply(ply + 1, game.moves.len());
+        before = after;
+    }
+
+    let mut tags: Vec<(String, String)> = game
+        .tags
+        .iter()
+        .filter(|(name, _)| name != "Annotator")
+        .cloned()
+        .collect();
+    tags.push((
+        "Annotator".to_string(),
+        format!("{} depth {}", env!("CARGO_PKG_NAME"), depth),
+    ));
+
+    Ok(Annotation {
+        tags,
+        moves,
+        result: game.result.clone(),
+    })
+}
+
+// Evals the way [%eval] comments have them: pawns without a plus, or #n
+fn pgn_eval(score: Score) -> String {
+    match score {
+        Score::Centipawns(centipawns) => format!("{:.2}", centipawns as f64 / 100.0),
+        Score::Mate(moves) => format!("#{}", moves),
+    }
+}
+
+impl Annotation {
+    pub fn to_pgn(&self) -> String {
+        let mut movetext = Vec::new();
+        for annotated in &self.moves {
+            // Every move has a comment after it, so black's always need
+            // their number again
+            movetext.push(match annotated.colour {
+                Colour::White => format!("{}.", annotated.fullmove_number),
+                Colour::Black => format!("{}...", annotated.fullmove_number),
+            });
+            movetext.push(annotated.san.clone());
+            if let Some(judgement) = annotated.judgement {
+                movetext.push(judgement.nag().to_string());
+            }
+
+            // The eval stays on one line for the programs that read it.
+            // There's nothing to say after mate.
+            let mut comment = Vec::new();
+            if annotated.eval != Score::Mate(0) {
+                comment.push(format!("[%eval {}]", pgn_eval(annotated.eval)));
+            }
+            if let (Some(judgement), Some(best_move)) = (annotated.judgement, &annotated.best_move)
+            {
+                let text = format!("{}. {} was best.", judgement.name(), best_move);
+                comment.extend(text.split(' ').map(str::to_string));
+            }
+            if !comment.is_empty() {
+                movetext.push("{".to_string());
+                movetext.append(&mut comment);
+                movetext.push("}".to_string());
+            }
+
+            if !annotated.best_line.is_empty() {
+                movetext.push("(".to_string());
+                movetext.extend(annotated.best_line.split(' ').map(str::to_string));
+                movetext.push(")".to_string());
+            }
+        }
+
+        let result = if self.result.is_empty() {
+            "*"
+        } else {
+            &self.result
+        };
+        write_pgn(&self.tags, &movetext, result)
+    }
+
+    // How many of each mistake each side made and where, e.g. "Black: 0
+    // inaccuracies, 1 mistake, 0 blunders (12... Nf6?)"
+    pub fn summary(&self) -> String {
+        [Colour::White, Colour::Black]
+            .iter()
+            .map(|&colour| {
+                let marked: Vec<&AnnotatedMove> = self
+                    .moves
+                    .iter()
+                    .filter(|annotated| annotated.colour == colour && annotated.judgement.is_some())
+                    .collect();
+                let counts: Vec<String> = [
+                    (Judgement::Inaccuracy, "inaccuracy", "inaccuracies"),
+                    (Judgement::Mistake, "mistake", "mistakes"),
+                    (Judgement::Blunder, "blunder", "blunders"),
+                ]
+                .iter()
+                .map(|&(judgement, one, many)| {
+                    let count = marked
+                        .iter()
+                        .filter(|annotated| annotated.judgement == Some(judgement))
+                        .count();
+                    format!("{} {}", count, if count == 1 { one } else { many })
+                })
+                .collect();
+                let side = match colour {
+                    Colour::White => "White",
+                    Colour::Black => "Black",
+                };
+
+                let mut line = format!("{}: {}", side, counts.join(", "));
+                if !marked.is_empty() {
+                    let moves: Vec<String> = marked
+                        .iter()
+                        .map(|annotated| {
+                            let number = match colour {
+                                Colour::White => format!("{}.", annotated.fullmove_number),
+                                Colour::Black => format!("{}...", annotated.fullmove_number),
+                            };
+                            let symbol = annotated.judgement.map_or("", Judgement::symbol);
+                            format!("{} {}{}", number, annotated.san, symbol)
+                        })
+                        .collect();
+                    line.push_str(&format!(" ({})", moves.join(", ")));
+                }
+                line
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+// The game played on the board as PGN, to be annotated. Without a result
+// from the clock, mate and stalemate end it; otherwise it's unfinished.
+pub fn game_record(fen: &str, moves: &[ChessMove], result: Option<GameResult>) -> PgnGame {
+    let mut board = ChessBoard::from_fen(fen);
+    let mut sans = Vec::new();
+    for &chess_move in moves {
+        sans.push(to_san(&mut board, chess_move));
+        board.make_move(chess_move);
+    }
+
+    let result = match result {
+        Some(result) => result.to_pgn(),
+        None if !board.legal_moves().is_empty() => "*",
+        None if !board.is_in_check(board.side_to_move()) => "1/2-1/2",
+        None if board.side_to_move() == Colour::White => "0-1",
+        None => "1-0",
+    };
+    let mut tags = vec![
+        (
+            "Event".to_string(),
+            format!("{} game", env!("CARGO_PKG_NAME")),
+        ),
+        ("Result".to_string(), result.to_string()),
+    ];
+    if fen != START_FEN {
+        tags.push(("SetUp".to_string(), "1".to_string()));
+        tags.push(("FEN".to_string(), fen.to_string()));
+    }
+    PgnGame {
+        tags,
+        moves: sans,
+        result: result.to_string(),
+    }
+}
+
+// Annotates every game in a PGN file for the `annotate` command, writing them
+// to the output file or stdout
+pub fn run(path: &str, output: Option<&Path>, depth: u32) -> Result<(), String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("can't read {}: {}", path, e))?;
+    let mut annotated = String::new();
+    for (i, game) in parse_pgn(&text).iter().enumerate() {
+        let annotation = annotate(game, depth, None, |ply, plies| {
+            eprint!("\rGame {}: {}/{} moves", i + 1, ply, plies);
+            let _ = std::io::stderr().flush();
+        })
+        .map_err(|error| format!("{} game {}: {}", path, i + 1, error))?;
+        eprintln!();
+        eprintln!("{}", annotation.summary());
+        annotated.push_str(&annotation.to_pgn());
+    }
+
+    match output {
+        Some(output) => fs::write(output, annotated)
+            .map_err(|e| format!("can't write {}: {}", output.display(), e)),
+        None => {
+            print!("{}", annotated);
+            Ok(())
+        }
+    }
+}
+
+// Annotates a game played on the board and writes it to a file, for the
+// `annotate` command in the terminal. Gives back the summary.
+pub fn save<F>(
+    game: &PgnGame,
+    output: &Path,
+    tablebase: Option<&Tablebase>,
+    on_ply: F,
+) -> Result<String, String>
+where
+    F: FnMut(usize, usize),
+{
+    let annotation = annotate(game, DEFAULT_DEPTH, tablebase, on_ply)?;
+    fs::write(output, annotation.to_pgn())
+        .map_err(|e| format!("can't write {}: {}", output.display(), e))?;
+    Ok(annotation.summary())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_judgement() {
+        assert_eq!(Judgement::from_loss(20), None);
+        assert_eq!(Judgement::from_loss(60), Some(Judgement::Inaccuracy));
+        assert_eq!(Judgement::from_loss(150), Some(Judgement::Mistake));
+        assert_eq!(Judgement::from_loss(900), Some(Judgement::Blunder));
+        assert_eq!(capped(Score::Centipawns(2500)), MAX_EVAL);
+        assert_eq!(capped(Score::Mate(-3)), -MAX_EVAL);
+        assert_eq!(pgn_eval(Score::Centipawns(-120)), "-1.20");
+        assert_eq!(pgn_eval(Score::Mate(2)), "#2");
+    }
+
+    #[test]
+    fn test_annotate() {
+        let text = "[Event \"Scholar's mate\"]\n[Result \"1-0\"]\n\n\
+                    1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7# 1-0";
+        let game = &parse_pgn(text)[0];
+        let annotation = annotate(game, 4, None, |_, _| {}).unwrap();
+        assert_eq!(annotation.moves.len(), 7);
+
+        // Nf6 walks into mate, and anything defending f7 was better
+        let blunder = &annotation.moves[5];
+        assert_eq!(blunder.san, "Nf6");
+        assert_eq!(blunder.judgement, Some(Judgement::Blunder));
+        assert!(blunder.best_move.is_some());
+        assert!(blunder.best_line.starts_with("3... "));
+        assert_eq!(annotation.moves[6].san, "Qxf7#");
+        assert_eq!(annotation.moves[6].judgement, None);
+        assert_eq!(annotation.moves[5].eval, Score::Mate(1));
+
+        let pgn = annotation.to_pgn();
+        assert!(pgn.contains("[Annotator \"chess-rust depth 4\"]"));
+        // Ignoring where the lines wrap
+        let movetext = pgn.split_whitespace().collect::<Vec<&str>>().join(" ");
+        assert!(movetext.contains("3... Nf6 $4 { [%eval #1] Blunder."));
+        assert!(movetext.ends_with("4. Qxf7# 1-0"));
+        assert_eq!(
+            annotation.summary(),
+            "White: 0 inaccuracies, 0 mistakes, 0 blunders\n\
+             Black: 0 inaccuracies, 0 mistakes, 1 blunder (3... Nf6??)"
+        );
+
+        // It reads back as the same game
+        let games = parse_pgn(&pgn);
+        assert_eq!(games.len(), 1);
+        assert_eq!(games[0].moves, game.moves);
+        assert_eq!(games[0].result, "1-0");
+
+        // The same game played on the board ends in mate
+        let mut board = ChessBoard::from_fen(START_FEN);
+        let played: Vec<ChessMove> = game
+            .moves
+            .iter()
+            .map(|san| {
+                let chess_move = parse_san(&mut board, san).unwrap();
+                board.make_move(chess_move);
+                chess_move
+            })
+            .collect();
+        let record = game_record(START_FEN, &played, None);
+        assert_eq!(record.moves, game.moves);
+        assert_eq!(record.result, "1-0");
+        assert_eq!(game_record(START_FEN, &played[..2], None).result, "*");
+
+        let illegal = &parse_pgn("1. e4 e4 *")[0];
+        assert_eq!(
+            annotate(illegal, 1, None, |_, _| {}),
+            Err("illegal move e4".to_string())
+        );
+    }
+}
@@ -1,9 +1,53 @@
-use crate::{chess_board::SquareIndex, chess_move::ChessMove};
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::{
+    analysis::Analysis,
+    book::Book,
+    chess_board::{ChessBoard, MoveRecord, SquareIndex},
+    chess_move::ChessMove,
+    clock::ChessClock,
+    game_result::{GameResult, Termination},
+    piece::Colour,
+    syzygy::Tablebase,
+    theme::Theme,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Highlight {
+    Selected,
+    Move,
+    Capture,
+    LastMove,
+    Check,
+}
 
 pub struct App {
     pub ui_buffer: String,
     available_moves: Vec<ChessMove>,
     selected_square: Option<SquareIndex>,
+    history: Vec<MoveRecord>,
+    pub theme: Theme,
+    pub ascii_pieces: bool,
+    pub large_board: bool,
+    pub clock: Option<ChessClock>,
+    pub result: Option<GameResult>,
+    // The side the engine plays, if it's playing
+    pub engine_colour: Option<Colour>,
+    pub thinking: bool,
+    // Opening book to show moves from, which the engine plays from too
+    pub book: Option<Arc<Book>>,
+    // Endgame tables to show the result from, which the engine probes too
+    pub tablebase: Option<Arc<Tablebase>>,
+    // The engine analyses whatever's on the board instead of playing
+    pub analysing: bool,
+    // How many of the best lines to show
+    pub multi_pv: usize,
+    pub analysis: Analysis,
+    // How annotating the game is going, once it's been asked for
+    pub annotation_status: Option<String>,
+    // Why the engine couldn't take up the position, if it couldn't
+    pub engine_error: Option<String>,
 }
 
 impl App {
@@ -12,6 +56,21 @@ impl App {
             ui_buffer: String::new(),
             available_moves: Vec::new(),
             selected_square: None,
+            history: Vec::new(),
+            theme: Theme::default(),
+            ascii_pieces: false,
+            large_board: false,
+            clock: None,
+            result: None,
+            engine_colour: None,
+            thinking: false,
+            book: None,
+            tablebase: None,
+            analysing: false,
+            multi_pv: 1,
+            analysis: Analysis::default(),
+            annotation_status: None,
+            engine_error: None,
         }
     }
 
@@ -23,6 +82,13 @@ impl App {
         &self.available_moves
     }
 
+    pub fn find_available_move(&self, destination: SquareIndex) -> Option<ChessMove> {
+        self.available_moves
+            .iter()
+            .find(|m| m.destination == destination)
+            .copied()
+    }
+
     pub fn clear_input(&mut self) {
         self.ui_buffer.clear();
         self.available_moves.clear();
@@ -36,4 +102,182 @@ impl App {
     pub fn get_selected_square(&self) -> Option<SquareIndex> {
         self.selected_square
     }
+
+    // Plays a move and presses the clock. Mate, stalemate and the draws by
+    // rule end the game there, stopping the clock.
+    pub fn play_move(&mut self, chessboard: &mut ChessBoard, chess_move: ChessMove, now: Instant) {
+        self.history.push(chessboard.make_move(chess_move));
+        if let Some(clock) = self.clock.as_mut() {
+            clock.press(now);
+        }
+
+        let hashes: Vec<u64> = self
+            .history
+            .iter()
+            .map(|record| record.hash)
+            .chain([chessboard.hash()])
+            .collect();
+        if let Some(result) = GameResult::from_position(chessboard, &hashes) {
+            self.result = Some(result);
+            if let Some(clock) = self.clock.as_mut() {
+                clock.stop(now);
+            }
+        }
+    }
+
+    // Taking a move back reopens a game that ended on the board, but a loss
+    // on time stands
+    pub fn undo_move(&mut self, chessboard: &mut ChessBoard) -> bool {
+        let timed_out = self.result.is_some_and(|result| {
+            matches!(
+                result.termination,
+                Termination::Timeout | Termination::TimeoutVsInsufficientMaterial
+            )
+        });
+        if timed_out {
+            return false;
+        }
+        match self.history.pop() {
+            Some(record) => {
+                chessboard.unmake_move(record);
+                self.result = None;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn history(&self) -> &[MoveRecord] {
+        &self.history
+    }
+
+    // The moves of the game so far in UCI notation
+    pub fn moves_played(&self) -> Vec<String> {
+        self.history
+            .iter()
+            .map(|record| record.chess_move.to_string())
+            .collect()
+    }
+
+    pub fn is_engine_turn(&self, chessboard: &ChessBoard) -> bool {
+        self.result.is_none() && self.engine_colour == Some(chessboard.side_to_move())
+    }
+
+    pub fn get_last_move(&self) -> Option<ChessMove> {
+        self.history.last().map(|record| record.chess_move)
+    }
+
+    pub fn check_clock(&mut self, chessboard: &ChessBoard, now: Instant) {
+        if self.result.is_some() {
+            return;
+        }
+
+        if let Some(flagged) = self.clock.as_mut().and_then(|clock| clock.check_flag(now)) {
+            self.result = Some(GameResult::on_timeout(flagged, chessboard));
+        }
+    }
+
+    // Layers are checked from most to least important, so a capture target
+    // shows over the last move and the selected piece shows over everything.
+    pub fn get_highlight(&self, chessboard: &ChessBoard, square: SquareIndex) -> Option<Highlight> {
+        if self.selected_square == Some(square) {
+            return Some(Highlight::Selected);
+        }
+
+        if self.available_moves.iter().any(|m| m.destination == square) {
+            return if chessboard.get_piece(square).is_some() {
+                Some(Highlight::Capture)
+            } else {
+                Some(Highlight::Move)
+            };
+        }
+
+        if let Some(piece) = chessboard.get_piece(square) {
+            if piece.is_king() && chessboard.is_in_check(piece.colour()) {
+                return Some(Highlight::Check);
+            }
+        }
+
+        match self.get_last_move() {
+            Some(m) if m.source == square || m.destination == square => Some(Highlight::LastMove),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{annotate::game_record, chess_board::START_FEN, clock::TimeControl};
+    use std::time::Duration;
+
+    #[test]
+    fn test_stalemate_ends_the_game() {
+        let fen = "k7/2Q5/8/8/8/8/8/7K w - - 0 1";
+        let mut chessboard = ChessBoard::from_fen(fen);
+        let mut app = App::new();
+        let now = Instant::now();
+        let mut clock = ChessClock::new(TimeControl::parse("1+0").unwrap());
+        clock.start(Colour::White, now);
+        app.clock = Some(clock);
+
+        let king_move = chessboard.parse_move("h1g1").unwrap();
+        app.play_move(&mut chessboard, king_move, now);
+        let stalemate = Some(GameResult {
+            winner: None,
+            termination: Termination::Stalemate,
+        });
+        assert_eq!(app.result, stalemate);
+        assert_eq!(app.clock.as_ref().unwrap().running_side(), None);
+
+        // Black's clock was never started, so it can't flag
+        app.check_clock(&chessboard, now + Duration::from_secs(3600));
+        assert_eq!(app.result, stalemate);
+
+        let moves: Vec<ChessMove> = app
+            .history()
+            .iter()
+            .map(|record| record.chess_move)
+            .collect();
+        assert_eq!(game_record(fen, &moves, app.result).result, "1/2-1/2");
+    }
+
+    #[test]
+    fn test_checkmate_ends_the_game() {
+        let mut chessboard = ChessBoard::from_fen("k7/8/1K6/8/8/8/8/7Q w - - 0 1");
+        let mut app = App::new();
+        let mate = chessboard.parse_move("h1h8").unwrap();
+        app.play_move(&mut chessboard, mate, Instant::now());
+        assert_eq!(
+            app.result,
+            Some(GameResult {
+                winner: Some(Colour::White),
+                termination: Termination::Checkmate,
+            })
+        );
+
+        // Taking the mate back carries on the game
+        assert!(app.undo_move(&mut chessboard));
+        assert_eq!(app.result, None);
+        assert_eq!(chessboard.to_fen(), "k7/8/1K6/8/8/8/8/7Q w - - 0 1");
+    }
+
+    #[test]
+    fn test_undo_after_timeout() {
+        let mut chessboard = ChessBoard::from_fen(START_FEN);
+        let mut app = App::new();
+        let now = Instant::now();
+        let mut clock = ChessClock::new(TimeControl::parse("1+0").unwrap());
+        clock.start(Colour::White, now);
+        app.clock = Some(clock);
+
+        let pawn_move = chessboard.parse_move("e2e4").unwrap();
+        app.play_move(&mut chessboard, pawn_move, now);
+        app.check_clock(&chessboard, now + Duration::from_secs(120));
+        assert_eq!(app.result.unwrap().termination, Termination::Timeout);
+
+        // Losing on time is final
+        assert!(!app.undo_move(&mut chessboard));
+        assert_eq!(app.history().len(), 1);
+    }
 }
@@ -0,0 +1,117 @@
+use std::sync::atomic::AtomicBool;
+use std::time::{Duration, Instant};
+
+use crate::{
+    chess_board::ChessBoard,
+    search::{search, SearchLimits, SearchParams},
+    tt::TranspositionTable,
+};
+
+pub const DEFAULT_DEPTH: u32 = 5;
+
+// A spread of openings, middlegames and endgames. The total node count over
+// them is a signature of the search: any change that alters it changes how
+// the engine plays.
+const POSITIONS: [&str; 8] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+    "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3",
+    "8/5pk1/6p1/8/3R4/6P1/5PK1/r7 w - - 0 1",
+];
+
+pub struct BenchResult {
+    pub nodes: Vec<u64>,
+    pub time: Duration,
+}
+
+impl BenchResult {
+    pub fn total_nodes(&self) -> u64 {
+        self.nodes.iter().sum()
+    }
+}
+
+// Searches every position to a fixed depth from a clean table, so the node
+// counts only depend on the search itself
+pub fn bench(depth: u32, params: &SearchParams) -> BenchResult {
+    let limits = SearchLimits {
+        depth: Some(depth),
+        ..SearchLimits::default()
+    };
+    let tt = TranspositionTable::new(16);
+    let stop = AtomicBool::new(false);
+    let start = Instant::now();
+
+    let nodes = POSITIONS
+        .iter()
+        .map(|fen| {
+            tt.clear();
+            let mut board = ChessBoard::from_fen(fen);
+            search(&mut board, &[], &limits, params, &tt, None, &stop, |_| {}).nodes
+        })
+        .collect();
+
+    BenchResult {
+        nodes,
+        time: start.elapsed(),
+    }
+}
+
+pub fn run(depth: u32, threads: usize) {
+    let params = SearchParams {
+        threads,
+        ..SearchParams::default()
+    };
+    let result = bench(depth, &params);
+
+    for (i, (fen, nodes)) in POSITIONS.iter().zip(&result.nodes).enumerate() {
+        println!("Position {}: {} nodes  {}", i + 1, nodes, fen);
+    }
+
+    let millis = result.time.as_millis().max(1) as u64;
+    println!();
+    println!("Depth:      {}", depth);
+    println!("Threads:    {}", threads);
+    println!("Nodes:      {}", result.total_nodes());
+    println!("Time:       {} ms", millis);
+    println!("Nodes/sec:  {}", result.total_nodes() * 1000 / millis);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::TUNABLES;
+
+    #[test]
+    fn test_bench_is_deterministic() {
+        let params = SearchParams::default();
+        let first = bench(2, &params);
+        let second = bench(2, &params);
+        assert_eq!(first.nodes, second.nodes);
+        assert!(first.nodes.iter().all(|&nodes| nodes > 0));
+    }
+
+    // Update this when a change to the search is meant to change how it plays
+    #[test]
+    fn test_signature() {
+        assert_eq!(bench(4, &SearchParams::default()).total_nodes(), 27800);
+    }
+
+    #[test]
+    fn test_every_tunable_changes_the_signature() {
+        let signature = bench(4, &SearchParams::default()).total_nodes();
+        for tunable in &TUNABLES {
+            let mut params = SearchParams::default();
+            assert!(params.set_tunable(tunable.name, tunable.min));
+            assert_ne!(
+                bench(4, &params).total_nodes(),
+                signature,
+                "{}",
+                tunable.name
+            );
+        }
+    }
+}
@@ -1,5 +1,11 @@
-use crate::{chess_move::ChessMove, piece::Piece};
+use crate::{
+    chess_move::ChessMove,
+    nnue::{Accumulators, Network},
+    piece::{Colour, Piece},
+    zobrist,
+};
 use std::str;
+use std::sync::Arc;
 
 pub type SquareIndex = u8;
 pub type FileIndex = u8;
@@ -9,9 +15,106 @@ pub const TOTAL_RANKS: RankIndex = 8;
 pub const TOTAL_FILES: FileIndex = 8;
 pub const TOTAL_SQUARES: SquareIndex = TOTAL_RANKS * TOTAL_FILES;
 
+pub const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct CastlingRights {
+    pub white_king_side: bool,
+    pub white_queen_side: bool,
+    pub black_king_side: bool,
+    pub black_queen_side: bool,
+}
+
+impl CastlingRights {
+    pub fn from_fen(castling: &str) -> CastlingRights {
+        CastlingRights {
+            white_king_side: castling.contains('K'),
+            white_queen_side: castling.contains('Q'),
+            black_king_side: castling.contains('k'),
+            black_queen_side: castling.contains('q'),
+        }
+    }
+
+    pub fn to_fen(self) -> String {
+        let fen: String = [
+            (self.white_king_side, 'K'),
+            (self.white_queen_side, 'Q'),
+            (self.black_king_side, 'k'),
+            (self.black_queen_side, 'q'),
+        ]
+        .iter()
+        .filter(|(allowed, _)| *allowed)
+        .map(|(_, symbol)| *symbol)
+        .collect();
+
+        if fen.is_empty() {
+            "-".to_string()
+        } else {
+            fen
+        }
+    }
+
+    pub fn can_castle(self, colour: Colour, king_side: bool) -> bool {
+        match (colour, king_side) {
+            (Colour::White, true) => self.white_king_side,
+            (Colour::White, false) => self.white_queen_side,
+            (Colour::Black, true) => self.black_king_side,
+            (Colour::Black, false) => self.black_queen_side,
+        }
+    }
+
+    // Anything moving from or to a king or rook home square loses the rights
+    // that depend on it.
+    fn touch_square(&mut self, square: SquareIndex) {
+        match square {
+            0 => self.white_queen_side = false,
+            4 => {
+                self.white_king_side = false;
+                self.white_queen_side = false;
+            }
+            7 => self.white_king_side = false,
+            56 => self.black_queen_side = false,
+            60 => {
+                self.black_king_side = false;
+                self.black_queen_side = false;
+            }
+            63 => self.black_king_side = false,
+            _ => {}
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct ChessBoard {
     board: [Option<Piece>; TOTAL_SQUARES as usize],
-    highlit: [bool; TOTAL_SQUARES as usize],
+    side_to_move: Colour,
+    castling_rights: CastlingRights,
+    en_passant: Option<SquareIndex>,
+    halfmove_clock: u32,
+    fullmove_number: u32,
+    // Zobrist hash of the position, kept up to date as moves are made
+    hash: u64,
+    // The neural network's inputs, when it's doing the evaluation
+    nnue: Option<Accumulators>,
+}
+
+pub struct MoveRecord {
+    pub chess_move: ChessMove,
+    pub mover: Colour,
+    pub captured: Option<Piece>,
+    // Differs from the destination for en passant
+    pub captured_square: SquareIndex,
+    castling_rights: CastlingRights,
+    en_passant: Option<SquareIndex>,
+    halfmove_clock: u32,
+    // Of the position before the move
+    pub hash: u64,
+}
+
+// What's needed to take back passing the turn
+pub struct NullMoveRecord {
+    en_passant: Option<SquareIndex>,
+    hash: u64,
 }
 
 impl ChessBoard {
@@ -19,7 +122,13 @@ impl ChessBoard {
         const INIT: Option<Piece> = None;
         ChessBoard {
             board: [INIT; TOTAL_SQUARES as usize],
-            highlit: [false; TOTAL_SQUARES as usize],
+            side_to_move: Colour::White,
+            castling_rights: CastlingRights::default(),
+            en_passant: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            hash: 0,
+            nnue: None,
         }
     }
     pub fn from_fen(fen: &str) -> ChessBoard {
@@ -33,21 +142,114 @@ impl ChessBoard {
         let piece_placements = fen_sections.next().unwrap_or("");
 
         for piece_placement in piece_placements.chars() {
-            if piece_placement.is_digit(10) {
+            if piece_placement.is_ascii_digit() {
                 file += piece_placement.to_digit(10).unwrap_or_default() as u8
             } else if piece_placement == '/' {
-                rank -= 1;
+                rank = rank.saturating_sub(1);
                 file = 0;
             } else {
-                let ix = ChessBoard::square_from_file_and_rank(file, rank).unwrap();
-                let piece = Piece::new(piece_placement);
-                board.board[ix as usize] = Some(piece);
+                if file < TOTAL_FILES {
+                    let ix = ChessBoard::square_from_file_and_rank(file, rank).unwrap();
+                    let piece = Piece::new(piece_placement);
+                    board.board[ix as usize] = Some(piece);
+                }
                 file += 1;
             }
         }
 
+        if fen_sections.next() == Some("b") {
+            board.side_to_move = Colour::Black;
+        }
+
+        board.castling_rights = CastlingRights::from_fen(fen_sections.next().unwrap_or("-"));
+        board.en_passant = fen_sections
+            .next()
+            .and_then(ChessBoard::square_from_notation);
+        board.halfmove_clock = fen_sections
+            .next()
+            .and_then(|clock| clock.parse().ok())
+            .unwrap_or(0);
+        board.fullmove_number = fen_sections
+            .next()
+            .and_then(|number| number.parse().ok())
+            .unwrap_or(1);
+        board.hash = board.compute_hash();
+
         board
     }
+
+    // Checks what from_fen needs to set up a playable board: eight ranks of
+    // eight squares, one king each and whose move it is
+    pub fn validate_fen(fen: &str) -> Result<(), String> {
+        let mut fields = fen.split_whitespace();
+        let placements = fields.next().unwrap_or_default();
+        let ranks: Vec<&str> = placements.split('/').collect();
+        let files_per_rank = ranks.iter().map(|rank| {
+            rank.chars().try_fold(0, |files, c| match c {
+                '1'..='8' => Some(files + c.to_digit(10).unwrap_or_default()),
+                _ if "PNBRQKpnbrqk".contains(c) => Some(files + 1),
+                _ => None,
+            })
+        });
+        if ranks.len() != TOTAL_RANKS as usize
+            || !files_per_rank
+                .into_iter()
+                .all(|files| files == Some(TOTAL_FILES as u32))
+        {
+            return Err("bad piece placement".to_string());
+        }
+        for king in ['K', 'k'] {
+            if placements.matches(king).count() != 1 {
+                return Err("needs one king each".to_string());
+            }
+        }
+        if !matches!(fields.next(), Some("w" | "b")) {
+            return Err("bad side to move".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn to_fen(&self) -> String {
+        let mut placements = String::new();
+
+        for rank in (1..=TOTAL_RANKS as usize).rev() {
+            let mut empty = 0;
+            for piece in self.get_pieces_on_rank(rank) {
+                match piece {
+                    Some(piece) => {
+                        if empty > 0 {
+                            placements.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        placements.push(piece.get_symbol());
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                placements.push_str(&empty.to_string());
+            }
+            if rank > 1 {
+                placements.push('/');
+            }
+        }
+
+        format!(
+            "{} {} {} {} {} {}",
+            placements,
+            if self.side_to_move == Colour::White {
+                "w"
+            } else {
+                "b"
+            },
+            self.castling_rights.to_fen(),
+            self.en_passant
+                .and_then(ChessBoard::square_to_notation)
+                .unwrap_or_else(|| "-".to_string()),
+            self.halfmove_clock,
+            self.fullmove_number
+        )
+    }
     pub fn get_piece(&self, square_index: SquareIndex) -> &Option<Piece> {
         &self.board[square_index as usize]
     }
@@ -125,8 +327,8 @@ impl ChessBoard {
 
         let (file_ix, rank_ix) = ChessBoard::square_to_file_and_rank(index);
 
-        let file = files.chars().nth(file_ix.try_into().unwrap());
-        let rank = ranks.chars().nth(rank_ix.try_into().unwrap());
+        let file = files.chars().nth(file_ix.into());
+        let rank = ranks.chars().nth(rank_ix.into());
 
         Some(format!("{}{}", file?, rank?))
     }
@@ -156,16 +358,445 @@ impl ChessBoard {
         }
     }
 
-    pub fn reset_highlights(&mut self) {
-        self.highlit = [false; TOTAL_SQUARES as usize];
+    pub fn generate_legal_moves(&mut self, index: SquareIndex) -> Vec<ChessMove> {
+        let colour = match self.get_piece(index) {
+            Some(piece) => piece.colour(),
+            None => return vec![],
+        };
+
+        self.generate_moves(index)
+            .into_iter()
+            .filter(|m| self.is_legal(*m, colour))
+            .collect()
+    }
+
+    fn is_legal(&mut self, chess_move: ChessMove, colour: Colour) -> bool {
+        // The network needn't see moves only made to be checked
+        let nnue = self.nnue.take();
+        let record = self.make_move(chess_move);
+        let in_check = self.is_in_check(colour);
+        self.unmake_move(record);
+        self.nnue = nnue;
+        !in_check
+    }
+
+    // Every legal move for the side to move
+    pub fn legal_moves(&mut self) -> Vec<ChessMove> {
+        let mut moves = Vec::new();
+        for square in 0..TOTAL_SQUARES {
+            if let Some(piece) = self.get_piece(square) {
+                if piece.colour() == self.side_to_move {
+                    moves.append(&mut self.generate_legal_moves(square));
+                }
+            }
+        }
+        moves
+    }
+
+    // The legal moves that change the material: captures and promotions
+    pub fn legal_captures(&mut self) -> Vec<ChessMove> {
+        let colour = self.side_to_move;
+        let mut moves = Vec::new();
+        for square in 0..TOTAL_SQUARES {
+            if self.get_piece(square).as_ref().map(|piece| piece.colour()) == Some(colour) {
+                moves.extend(self.generate_moves(square).into_iter().filter(|m| {
+                    (self.is_capture(*m) || m.promotion.is_some()) && self.is_legal(*m, colour)
+                }));
+            }
+        }
+        moves
+    }
+
+    pub fn is_capture(&self, chess_move: ChessMove) -> bool {
+        self.get_piece(chess_move.destination).is_some()
+            || (self.is_piece(chess_move.source, 'P')
+                && self.en_passant == Some(chess_move.destination))
+    }
+
+    pub fn perft(&mut self, depth: u32) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+
+        let moves = self.legal_moves();
+        if depth == 1 {
+            return moves.len() as u64;
+        }
+
+        moves
+            .into_iter()
+            .map(|m| {
+                let record = self.make_move(m);
+                let nodes = self.perft(depth - 1);
+                self.unmake_move(record);
+                nodes
+            })
+            .sum()
     }
 
-    pub fn highlight_square(&mut self, index: SquareIndex, highlight: bool) {
-        self.highlit[index as usize] = highlight;
+    // Finds the legal move for long algebraic notation like e2e4 or e7e8q
+    pub fn parse_move(&mut self, notation: &str) -> Option<ChessMove> {
+        self.legal_moves()
+            .into_iter()
+            .find(|m| m.to_string() == notation)
     }
 
-    pub fn is_highlit(&self, index: SquareIndex) -> bool {
-        self.highlit[index as usize]
+    pub fn side_to_move(&self) -> Colour {
+        self.side_to_move
+    }
+
+    pub fn castling_rights(&self) -> CastlingRights {
+        self.castling_rights
+    }
+
+    pub fn en_passant(&self) -> Option<SquareIndex> {
+        self.en_passant
+    }
+
+    pub fn halfmove_clock(&self) -> u32 {
+        self.halfmove_clock
+    }
+
+    pub fn fullmove_number(&self) -> u32 {
+        self.fullmove_number
+    }
+
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    pub fn nnue(&self) -> Option<&Accumulators> {
+        self.nnue.as_ref()
+    }
+
+    // Evaluates with the network from here on, or the handcrafted evaluation
+    // for None
+    pub fn set_network(&mut self, network: Option<Arc<Network>>) {
+        self.nnue = network.map(|network| Accumulators::new(network, self));
+    }
+
+    fn compute_hash(&self) -> u64 {
+        let pieces = (0..TOTAL_SQUARES)
+            .filter_map(|square| {
+                self.get_piece(square)
+                    .as_ref()
+                    .map(|piece| zobrist::piece_key(piece.get_symbol(), square))
+            })
+            .fold(0, |hash, key| hash ^ key);
+
+        pieces
+            ^ zobrist::castling_key(self.castling_rights)
+            ^ zobrist::en_passant_key(self.en_passant)
+            ^ zobrist::side_key(self.side_to_move)
+    }
+
+    fn is_piece(&self, square: SquareIndex, symbol: char) -> bool {
+        match self.get_piece(square) {
+            Some(piece) => piece.get_symbol().eq_ignore_ascii_case(&symbol),
+            None => false,
+        }
+    }
+
+    // Takes a piece off a square, or puts one on, in the hash and the
+    // network's inputs
+    fn lift(&mut self, symbol: char, square: SquareIndex) {
+        self.hash ^= zobrist::piece_key(symbol, square);
+        if let Some(nnue) = &mut self.nnue {
+            nnue.remove(symbol, square);
+        }
+    }
+
+    fn place(&mut self, symbol: char, square: SquareIndex) {
+        self.hash ^= zobrist::piece_key(symbol, square);
+        if let Some(nnue) = &mut self.nnue {
+            nnue.add(symbol, square);
+        }
+    }
+
+    pub fn make_move(&mut self, chess_move: ChessMove) -> MoveRecord {
+        let mover = self.side_to_move;
+        let source = chess_move.source;
+        let destination = chess_move.destination;
+        let is_pawn = self.is_piece(source, 'P');
+        let is_king = self.is_piece(source, 'K');
+        let (source_file, source_rank) = ChessBoard::square_to_file_and_rank(source);
+        let (destination_file, destination_rank) = ChessBoard::square_to_file_and_rank(destination);
+
+        // A pawn moving diagonally onto the en passant square takes the pawn
+        // beside it rather than anything on the destination
+        let captured_square = if is_pawn
+            && Some(destination) == self.en_passant
+            && source_file != destination_file
+            && self.board[destination as usize].is_none()
+        {
+            ChessBoard::square_from_file_and_rank(destination_file, source_rank).unwrap()
+        } else {
+            destination
+        };
+
+        let previous_hash = self.hash;
+        if let Some(nnue) = &mut self.nnue {
+            nnue.push();
+        }
+        let mut piece = self.board[source as usize].take();
+        let captured = self.board[captured_square as usize].take();
+
+        if let Some(piece) = &piece {
+            self.lift(piece.get_symbol(), source);
+        }
+        if let Some(captured) = &captured {
+            self.lift(captured.get_symbol(), captured_square);
+        }
+
+        if let Some(promotion) = chess_move.promotion {
+            piece = Some(Piece::new(mover.piece_symbol(promotion)));
+        }
+
+        if let Some(piece) = &piece {
+            self.place(piece.get_symbol(), destination);
+        }
+        self.board[destination as usize] = piece;
+
+        // Castling is a king move of two files, the rook jumps over it
+        if is_king && source_file.abs_diff(destination_file) == 2 {
+            let (rook_from, rook_to) = if destination_file > source_file {
+                (7, 5)
+            } else {
+                (0, 3)
+            };
+            let rook_from = ChessBoard::square_from_file_and_rank(rook_from, source_rank).unwrap();
+            let rook_to = ChessBoard::square_from_file_and_rank(rook_to, source_rank).unwrap();
+            self.board[rook_to as usize] = self.board[rook_from as usize].take();
+            let rook = mover.piece_symbol('R');
+            self.lift(rook, rook_from);
+            self.place(rook, rook_to);
+        }
+
+        let record = MoveRecord {
+            chess_move,
+            mover,
+            captured,
+            captured_square,
+            castling_rights: self.castling_rights,
+            en_passant: self.en_passant,
+            halfmove_clock: self.halfmove_clock,
+            hash: previous_hash,
+        };
+
+        self.castling_rights.touch_square(source);
+        self.castling_rights.touch_square(destination);
+
+        self.en_passant = if is_pawn && source_rank.abs_diff(destination_rank) == 2 {
+            ChessBoard::square_from_file_and_rank(source_file, (source_rank + destination_rank) / 2)
+        } else {
+            None
+        };
+
+        if is_pawn || record.captured.is_some() {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+
+        if mover == Colour::Black {
+            self.fullmove_number += 1;
+        }
+        self.side_to_move = mover.opposite();
+
+        self.hash ^= zobrist::castling_key(record.castling_rights)
+            ^ zobrist::castling_key(self.castling_rights)
+            ^ zobrist::en_passant_key(record.en_passant)
+            ^ zobrist::en_passant_key(self.en_passant)
+            ^ zobrist::side_key(mover)
+            ^ zobrist::side_key(self.side_to_move);
+
+        record
+    }
+
+    pub fn unmake_move(&mut self, record: MoveRecord) {
+        let chess_move = record.chess_move;
+        let mut piece = self.board[chess_move.destination as usize].take();
+
+        if chess_move.promotion.is_some() {
+            piece = Some(Piece::new(record.mover.piece_symbol('P')));
+        }
+
+        let is_king = match &piece {
+            Some(piece) => piece.is_king(),
+            None => false,
+        };
+        self.board[chess_move.source as usize] = piece;
+        self.board[record.captured_square as usize] = record.captured;
+
+        let (source_file, source_rank) = ChessBoard::square_to_file_and_rank(chess_move.source);
+        let (destination_file, _) = ChessBoard::square_to_file_and_rank(chess_move.destination);
+        if is_king && source_file.abs_diff(destination_file) == 2 {
+            let (rook_from, rook_to) = if destination_file > source_file {
+                (7, 5)
+            } else {
+                (0, 3)
+            };
+            let rook_from = ChessBoard::square_from_file_and_rank(rook_from, source_rank).unwrap();
+            let rook_to = ChessBoard::square_from_file_and_rank(rook_to, source_rank).unwrap();
+            self.board[rook_from as usize] = self.board[rook_to as usize].take();
+        }
+
+        self.castling_rights = record.castling_rights;
+        self.en_passant = record.en_passant;
+        self.halfmove_clock = record.halfmove_clock;
+        self.hash = record.hash;
+        if record.mover == Colour::Black {
+            self.fullmove_number -= 1;
+        }
+        self.side_to_move = record.mover;
+
+        // Moves made before the network was set have no accumulator to go
+        // back to
+        if let Some(mut nnue) = self.nnue.take() {
+            if !nnue.pop() {
+                nnue.refresh(self);
+            }
+            self.nnue = Some(nnue);
+        }
+    }
+
+    // Hands the turn over without moving anything, for null move pruning
+    pub fn make_null_move(&mut self) -> NullMoveRecord {
+        let record = NullMoveRecord {
+            en_passant: self.en_passant,
+            hash: self.hash,
+        };
+        let mover = self.side_to_move;
+        self.en_passant = None;
+        self.side_to_move = mover.opposite();
+        self.hash ^= zobrist::en_passant_key(record.en_passant)
+            ^ zobrist::side_key(mover)
+            ^ zobrist::side_key(self.side_to_move);
+        record
+    }
+
+    pub fn unmake_null_move(&mut self, record: NullMoveRecord) {
+        self.en_passant = record.en_passant;
+        self.side_to_move = self.side_to_move.opposite();
+        self.hash = record.hash;
+    }
+
+    pub fn find_king(&self, colour: Colour) -> Option<SquareIndex> {
+        (0..TOTAL_SQUARES).find(|&ix| match self.get_piece(ix) {
+            Some(piece) => piece.is_king() && piece.colour() == colour,
+            None => false,
+        })
+    }
+
+    pub fn has_only_king(&self, colour: Colour) -> bool {
+        self.board
+            .iter()
+            .flatten()
+            .all(|piece| piece.colour() != colour || piece.is_king())
+    }
+
+    // Anything besides pawns and the king. Without it a side is the most
+    // likely to be in zugzwang.
+    pub fn has_non_pawn_material(&self, colour: Colour) -> bool {
+        self.board.iter().flatten().any(|piece| {
+            piece.colour() == colour
+                && !piece.is_king()
+                && !piece.get_symbol().eq_ignore_ascii_case(&'P')
+        })
+    }
+
+    // Neither side can mate: bare kings, or a lone bishop or knight
+    pub fn has_insufficient_material(&self) -> bool {
+        let others: Vec<&Piece> = self
+            .board
+            .iter()
+            .flatten()
+            .filter(|piece| !piece.is_king())
+            .collect();
+        match others.as_slice() {
+            [] => true,
+            [piece] => "BN".contains(piece.get_symbol().to_ascii_uppercase()),
+            _ => false,
+        }
+    }
+
+    pub fn is_in_check(&self, colour: Colour) -> bool {
+        match self.find_king(colour) {
+            Some(king) => self.is_square_attacked(king, colour.opposite()),
+            None => false,
+        }
+    }
+
+    pub fn is_square_attacked(&self, square: SquareIndex, by: Colour) -> bool {
+        static STRAIGHT: [(i8, i8); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+        static DIAGONAL: [(i8, i8); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+        static KNIGHT: [(i8, i8); 8] = [
+            (1, 2),
+            (-1, -2),
+            (2, 1),
+            (-2, -1),
+            (1, -2),
+            (-1, 2),
+            (2, -1),
+            (-2, 1),
+        ];
+
+        let (file_ix, rank_ix) = ChessBoard::square_to_file_and_rank(square);
+        let attacker_at = |df: i8, dr: i8| -> Option<char> {
+            let file = file_ix as i8 + df;
+            let rank = rank_ix as i8 + dr;
+            if !(0..TOTAL_FILES as i8).contains(&file) || !(0..TOTAL_RANKS as i8).contains(&rank) {
+                return None;
+            }
+            let ix = ChessBoard::square_from_file_and_rank(file as u8, rank as u8)?;
+            match self.get_piece(ix) {
+                Some(piece) if piece.colour() == by => {
+                    Some(piece.get_symbol().to_ascii_uppercase())
+                }
+                _ => None,
+            }
+        };
+
+        let pawn_rank = if by == Colour::White { -1 } else { 1 };
+        if attacker_at(-1, pawn_rank) == Some('P') || attacker_at(1, pawn_rank) == Some('P') {
+            return true;
+        }
+
+        if KNIGHT
+            .iter()
+            .any(|(df, dr)| attacker_at(*df, *dr) == Some('N'))
+        {
+            return true;
+        }
+
+        for (directions, slider) in [(&STRAIGHT, 'R'), (&DIAGONAL, 'B')] {
+            for (df, dr) in directions.iter() {
+                let mut distance = 1;
+                loop {
+                    let file = file_ix as i8 + df * distance;
+                    let rank = rank_ix as i8 + dr * distance;
+                    if !(0..TOTAL_FILES as i8).contains(&file)
+                        || !(0..TOTAL_RANKS as i8).contains(&rank)
+                    {
+                        break;
+                    }
+                    let ix = ChessBoard::square_from_file_and_rank(file as u8, rank as u8).unwrap();
+                    if let Some(piece) = self.get_piece(ix) {
+                        if piece.colour() == by {
+                            let symbol = piece.get_symbol().to_ascii_uppercase();
+                            if symbol == slider || symbol == 'Q' || (symbol == 'K' && distance == 1)
+                            {
+                                return true;
+                            }
+                        }
+                        break;
+                    }
+                    distance += 1;
+                }
+            }
+        }
+
+        false
     }
 }
 
@@ -200,4 +831,318 @@ mod tests {
         let square = ChessBoard::square_from_file_and_rank(1, 1).unwrap();
         assert_eq!("b2", ChessBoard::square_to_notation(square).unwrap());
     }
+
+    #[test]
+    fn test_side_to_move_from_fen() {
+        let chess_board = ChessBoard::from_fen("8/8/8/8/8/8/8/8 b - - 0 1");
+        assert_eq!(chess_board.side_to_move(), Colour::Black);
+
+        let chess_board = ChessBoard::from_fen("8/8/8/8/8/8/8/8 w - - 0 1");
+        assert_eq!(chess_board.side_to_move(), Colour::White);
+    }
+
+    #[test]
+    fn test_make_and_unmake_move() {
+        let mut chess_board = ChessBoard::from_fen("8/8/8/3p4/4P3/8/8/8 w - - 0 1");
+        let source = ChessBoard::square_from_notation("e4").unwrap();
+        let destination = ChessBoard::square_from_notation("d5").unwrap();
+
+        let record = chess_board.make_move(ChessMove::new(source, destination));
+        assert!(chess_board.get_piece(source).is_none());
+        assert!(chess_board
+            .get_piece(destination)
+            .as_ref()
+            .unwrap()
+            .is_white());
+        assert!(record.captured.unwrap().is_black());
+        assert_eq!(chess_board.side_to_move(), Colour::Black);
+
+        let mut chess_board = ChessBoard::from_fen("8/8/8/3p4/4P3/8/8/8 w - - 0 1");
+        let record = chess_board.make_move(ChessMove::new(source, destination));
+        chess_board.unmake_move(record);
+        assert!(chess_board.get_piece(source).as_ref().unwrap().is_white());
+        assert!(chess_board
+            .get_piece(destination)
+            .as_ref()
+            .unwrap()
+            .is_black());
+        assert_eq!(chess_board.side_to_move(), Colour::White);
+    }
+
+    #[test]
+    fn test_make_and_unmake_promotion() {
+        let mut chess_board = ChessBoard::from_fen("8/8/8/8/8/8/p7/8 b - - 0 1");
+        let source = ChessBoard::square_from_notation("a2").unwrap();
+        let destination = ChessBoard::square_from_notation("a1").unwrap();
+
+        let record = chess_board.make_move(ChessMove {
+            promotion: Some('N'),
+            ..ChessMove::new(source, destination)
+        });
+        assert_eq!(
+            chess_board
+                .get_piece(destination)
+                .as_ref()
+                .unwrap()
+                .get_symbol(),
+            'n'
+        );
+        assert_eq!(record.mover, Colour::Black);
+
+        chess_board.unmake_move(record);
+        assert_eq!(
+            chess_board.get_piece(source).as_ref().unwrap().get_symbol(),
+            'p'
+        );
+        assert!(chess_board.get_piece(destination).is_none());
+    }
+
+    #[test]
+    fn test_is_in_check() {
+        let chess_board = ChessBoard::from_fen("4k3/8/8/8/8/8/8/4R2K b - - 0 1");
+        assert!(chess_board.is_in_check(Colour::Black));
+        assert!(!chess_board.is_in_check(Colour::White));
+
+        let chess_board = ChessBoard::from_fen("4k3/4p3/8/8/8/8/8/4R2K b - - 0 1");
+        assert!(!chess_board.is_in_check(Colour::Black));
+
+        let chess_board = ChessBoard::from_fen("4k3/3P4/8/8/8/8/8/7K b - - 0 1");
+        assert!(chess_board.is_in_check(Colour::Black));
+
+        let chess_board = ChessBoard::from_fen("4k3/8/5N2/8/8/8/8/7K b - - 0 1");
+        assert!(chess_board.is_in_check(Colour::Black));
+    }
+
+    #[test]
+    fn test_legal_moves_do_not_leave_king_in_check() {
+        let mut chess_board = ChessBoard::from_fen("4k3/4r3/8/8/8/8/4B3/4K3 w - - 0 1");
+        let square = ChessBoard::square_from_notation("e2").unwrap();
+        assert!(chess_board.generate_legal_moves(square).is_empty());
+
+        let square = ChessBoard::square_from_notation("e1").unwrap();
+        let mut notationed_moves: Vec<String> = chess_board
+            .generate_legal_moves(square)
+            .iter()
+            .map(|m| ChessBoard::square_to_notation(m.destination).unwrap())
+            .collect();
+        notationed_moves.sort();
+        assert_eq!(notationed_moves, vec!["d1", "d2", "f1", "f2"]);
+    }
+
+    #[test]
+    fn test_validate_fen() {
+        assert_eq!(ChessBoard::validate_fen(START_FEN), Ok(()));
+        assert_eq!(ChessBoard::validate_fen("4k3/8/8/8/8/8/8/4K3 b"), Ok(()));
+        for (fen, error) in [
+            ("", "bad piece placement"),
+            ("hello world", "bad piece placement"),
+            ("4k3/8/8/8/8/8/4K3 w - - 0 1", "bad piece placement"),
+            ("4k3/8/8/8/8/8/8/4K4 w - - 0 1", "bad piece placement"),
+            ("4k3/8/8/8/8/8/8/4X3 w - - 0 1", "bad piece placement"),
+            ("4k3/8/8/8/8/8/8/8 w - - 0 1", "needs one king each"),
+            ("4k3/8/8/8/8/8/8/3KK3 w - - 0 1", "needs one king each"),
+            ("4k3/8/8/8/8/8/8/4K3", "bad side to move"),
+            ("4k3/8/8/8/8/8/8/4K3 x - - 0 1", "bad side to move"),
+        ] {
+            assert_eq!(
+                ChessBoard::validate_fen(fen),
+                Err(error.to_string()),
+                "{}",
+                fen
+            );
+        }
+    }
+
+    #[test]
+    fn test_fen_round_trip() {
+        for fen in [
+            START_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "8/8/8/8/8/8/8/4K2k b - - 12 40",
+        ] {
+            assert_eq!(ChessBoard::from_fen(fen).to_fen(), fen);
+        }
+    }
+
+    #[test]
+    fn test_make_move_updates_fen_state() {
+        let mut chess_board = ChessBoard::from_fen(START_FEN);
+        let e4 = chess_board.parse_move("e2e4").unwrap();
+        chess_board.make_move(e4);
+        assert_eq!(
+            chess_board.to_fen(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+        );
+
+        let nf6 = chess_board.parse_move("g8f6").unwrap();
+        let record = chess_board.make_move(nf6);
+        assert_eq!(
+            chess_board.to_fen(),
+            "rnbqkb1r/pppppppp/5n2/8/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 1 2"
+        );
+
+        chess_board.unmake_move(record);
+        assert_eq!(
+            chess_board.to_fen(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+        );
+    }
+
+    #[test]
+    fn test_castling() {
+        let mut chess_board = ChessBoard::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+        let castle = chess_board.parse_move("e1g1").unwrap();
+        let record = chess_board.make_move(castle);
+        assert_eq!(chess_board.to_fen(), "r3k2r/8/8/8/8/8/8/R4RK1 b kq - 1 1");
+
+        chess_board.unmake_move(record);
+        let castle = chess_board.parse_move("e1c1").unwrap();
+        chess_board.make_move(castle);
+        // The rook on d1 now covers d8
+        assert!(chess_board.parse_move("e8c8").is_none());
+        let castle = chess_board.parse_move("e8g8").unwrap();
+        chess_board.make_move(castle);
+        assert_eq!(chess_board.to_fen(), "r4rk1/8/8/8/8/8/8/2KR3R w - - 2 2");
+    }
+
+    #[test]
+    fn test_no_castling_through_check() {
+        let mut chess_board = ChessBoard::from_fen("4kr2/8/8/8/8/8/8/R3K2R w KQ - 0 1");
+        assert!(chess_board.parse_move("e1g1").is_none());
+        assert!(chess_board.parse_move("e1c1").is_some());
+    }
+
+    #[test]
+    fn test_en_passant() {
+        let mut chess_board =
+            ChessBoard::from_fen("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3");
+        assert!(chess_board.parse_move("e5d6").is_none());
+
+        let en_passant = chess_board.parse_move("e5f6").unwrap();
+        let record = chess_board.make_move(en_passant);
+        assert_eq!(
+            chess_board.to_fen(),
+            "rnbqkbnr/ppp1p1pp/5P2/3p4/8/8/PPPP1PPP/RNBQKBNR b KQkq - 0 3"
+        );
+        assert_eq!(record.captured.as_ref().unwrap().get_symbol(), 'p');
+
+        chess_board.unmake_move(record);
+        assert_eq!(
+            chess_board.to_fen(),
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3"
+        );
+    }
+
+    // Walks the tree checking the incremental hash against one from scratch
+    fn check_hashes(board: &mut ChessBoard, depth: u32) {
+        assert_eq!(board.hash(), board.compute_hash(), "{}", board.to_fen());
+        if depth == 0 {
+            return;
+        }
+        for chess_move in board.legal_moves() {
+            let record = board.make_move(chess_move);
+            check_hashes(board, depth - 1);
+            board.unmake_move(record);
+        }
+    }
+
+    #[test]
+    fn test_incremental_hash() {
+        let mut board = ChessBoard::from_fen(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        );
+        check_hashes(&mut board, 2);
+        check_hashes(
+            &mut ChessBoard::from_fen("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1"),
+            3,
+        );
+    }
+
+    #[test]
+    fn test_null_move() {
+        let mut board =
+            ChessBoard::from_fen("rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2");
+        let fen = board.to_fen();
+        let hash = board.hash();
+
+        let record = board.make_null_move();
+        assert_eq!(board.side_to_move(), Colour::Black);
+        assert_eq!(board.en_passant(), None);
+        assert_eq!(board.hash(), board.compute_hash());
+
+        board.unmake_null_move(record);
+        assert_eq!(board.to_fen(), fen);
+        assert_eq!(board.hash(), hash);
+    }
+
+    #[test]
+    fn test_non_pawn_material() {
+        let board = ChessBoard::from_fen("4k3/4p3/8/8/8/8/4P3/4KN2 w - - 0 1");
+        assert!(board.has_non_pawn_material(Colour::White));
+        assert!(!board.has_non_pawn_material(Colour::Black));
+    }
+
+    #[test]
+    fn test_hash_transpositions() {
+        let mut first = ChessBoard::from_fen(START_FEN);
+        let mut second = ChessBoard::from_fen(START_FEN);
+        for notation in ["g1f3", "g8f6", "b1c3"] {
+            let chess_move = first.parse_move(notation).unwrap();
+            first.make_move(chess_move);
+        }
+        for notation in ["b1c3", "g8f6", "g1f3"] {
+            let chess_move = second.parse_move(notation).unwrap();
+            second.make_move(chess_move);
+        }
+        assert_eq!(first.hash(), second.hash());
+        assert_ne!(first.hash(), ChessBoard::from_fen(START_FEN).hash());
+    }
+
+    #[test]
+    fn test_perft_start_position() {
+        let mut chess_board = ChessBoard::from_fen(START_FEN);
+        assert_eq!(chess_board.perft(1), 20);
+        assert_eq!(chess_board.perft(2), 400);
+        assert_eq!(chess_board.perft(3), 8902);
+    }
+
+    // Reference positions and counts from https://www.chessprogramming.org/Perft_Results
+    #[test]
+    fn test_perft_kiwipete() {
+        let mut chess_board = ChessBoard::from_fen(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        );
+        assert_eq!(chess_board.perft(1), 48);
+        assert_eq!(chess_board.perft(2), 2039);
+        assert_eq!(chess_board.perft(3), 97862);
+    }
+
+    #[test]
+    fn test_perft_position_3() {
+        let mut chess_board = ChessBoard::from_fen("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1");
+        assert_eq!(chess_board.perft(1), 14);
+        assert_eq!(chess_board.perft(2), 191);
+        assert_eq!(chess_board.perft(3), 2812);
+        assert_eq!(chess_board.perft(4), 43238);
+    }
+
+    #[test]
+    fn test_perft_position_4() {
+        let mut chess_board = ChessBoard::from_fen(
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        );
+        assert_eq!(chess_board.perft(1), 6);
+        assert_eq!(chess_board.perft(2), 264);
+        assert_eq!(chess_board.perft(3), 9467);
+    }
+
+    #[test]
+    fn test_perft_position_5() {
+        let mut chess_board =
+            ChessBoard::from_fen("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8");
+        assert_eq!(chess_board.perft(1), 44);
+        assert_eq!(chess_board.perft(2), 1486);
+        assert_eq!(chess_board.perft(3), 62379);
+    }
 }
@@ -1,13 +1,45 @@
-use crate::chess_board::{ChessBoard, SquareIndex, TOTAL_FILES, TOTAL_RANKS};
+use std::fmt;
 
-#[derive(Clone, Copy)]
+use crate::chess_board::{ChessBoard, SquareIndex, TOTAL_FILES, TOTAL_RANKS};
+use crate::piece::Colour;
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub struct ChessMove {
     pub source: SquareIndex,
     pub destination: SquareIndex,
+    // Upper case symbol of the piece a pawn becomes
+    pub promotion: Option<char>,
+}
+
+pub const PROMOTION_PIECES: [char; 4] = ['Q', 'R', 'B', 'N'];
+
+impl ChessMove {
+    pub fn new(source: SquareIndex, destination: SquareIndex) -> Self {
+        ChessMove {
+            source,
+            destination,
+            promotion: None,
+        }
+    }
+}
+
+// Long algebraic notation as used by UCI, e.g. e2e4 or e7e8q
+impl fmt::Display for ChessMove {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}{}",
+            ChessBoard::square_to_notation(self.source).unwrap_or_default(),
+            ChessBoard::square_to_notation(self.destination).unwrap_or_default()
+        )?;
+        if let Some(promotion) = self.promotion {
+            write!(f, "{}", promotion.to_ascii_lowercase())?;
+        }
+        Ok(())
+    }
 }
 
-pub trait MoveGenerator {
+pub trait MoveGenerator: Send + Sync {
     fn generate_moves(&self, chess_board: &ChessBoard, source: SquareIndex) -> Vec<ChessMove>;
 }
 
@@ -67,9 +99,37 @@ impl MoveGenerator for PawnMoves {
 
         let take_directions: [(i8, i8); 2] = [(-1, self.file_direction), (1, self.file_direction)];
 
-        let taking_moves = generate_moves(chess_board, source, 1, &take_directions, true, true);
+        let mut taking_moves = generate_moves(chess_board, source, 1, &take_directions, true, true);
+
+        if let Some(en_passant) = chess_board.en_passant() {
+            let (file_ix, rank_ix) = ChessBoard::square_to_file_and_rank(source);
+            let (ep_file_ix, ep_rank_ix) = ChessBoard::square_to_file_and_rank(en_passant);
+            let is_our_turn = match chess_board.get_piece(source) {
+                Some(piece) => piece.colour() == chess_board.side_to_move(),
+                None => false,
+            };
+
+            if is_our_turn
+                && file_ix.abs_diff(ep_file_ix) == 1
+                && ep_rank_ix as i8 == rank_ix as i8 + self.file_direction
+            {
+                taking_moves.push(ChessMove::new(source, en_passant));
+            }
+        }
 
-        [forward_moves, taking_moves].concat()
+        let mut moves = Vec::new();
+        for chess_move in [forward_moves, taking_moves].concat() {
+            let (_, rank_ix) = ChessBoard::square_to_file_and_rank(chess_move.destination);
+            if rank_ix == 0 || rank_ix == TOTAL_RANKS - 1 {
+                moves.extend(PROMOTION_PIECES.iter().map(|&piece| ChessMove {
+                    promotion: Some(piece),
+                    ..chess_move
+                }));
+            } else {
+                moves.push(chess_move);
+            }
+        }
+        moves
     }
 }
 
@@ -128,6 +188,56 @@ impl MoveGenerator for JumpingMoves {
     }
 }
 
+pub struct CastlingMoves {}
+impl CastlingMoves {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+impl MoveGenerator for CastlingMoves {
+    fn generate_moves(&self, chess_board: &ChessBoard, source: SquareIndex) -> Vec<ChessMove> {
+        let colour = match chess_board.get_piece(source) {
+            Some(piece) => piece.colour(),
+            None => return vec![],
+        };
+        let home_rank = match colour {
+            Colour::White => 0,
+            Colour::Black => TOTAL_RANKS - 1,
+        };
+        let square = |file| ChessBoard::square_from_file_and_rank(file, home_rank).unwrap();
+
+        if source != square(4) || chess_board.is_in_check(colour) {
+            return vec![];
+        }
+
+        let mut moves = Vec::new();
+        // (king side, rook file, squares that must be empty, squares the king crosses)
+        let sides: [(bool, u8, &[u8], [u8; 2]); 2] =
+            [(true, 7, &[5, 6], [5, 6]), (false, 0, &[1, 2, 3], [3, 2])];
+
+        for (king_side, rook_file, empty_files, king_path) in sides {
+            let has_rook = match chess_board.get_piece(square(rook_file)) {
+                Some(piece) => piece.get_symbol() == colour.piece_symbol('R'),
+                None => false,
+            };
+
+            if chess_board.castling_rights().can_castle(colour, king_side)
+                && has_rook
+                && empty_files
+                    .iter()
+                    .all(|&file| chess_board.get_piece(square(file)).is_none())
+                && king_path
+                    .iter()
+                    .all(|&file| !chess_board.is_square_attacked(square(file), colour.opposite()))
+            {
+                moves.push(ChessMove::new(source, square(king_path[1])));
+            }
+        }
+
+        moves
+    }
+}
+
 fn generate_moves(
     chess_board: &ChessBoard,
     source: u8,
@@ -152,10 +262,7 @@ fn generate_moves(
             let destination = ChessBoard::square_from_file_and_rank(file_ix as u8, rank_ix as u8);
 
             if let Some(destination) = destination {
-                let chess_move = ChessMove {
-                    source,
-                    destination,
-                };
+                let chess_move = ChessMove::new(source, destination);
 
                 if let Some(piece) = chess_board.get_piece(destination) {
                     if takes_enemy
@@ -165,10 +272,8 @@ fn generate_moves(
                     }
 
                     break;
-                } else {
-                    if !must_take_enemy {
-                        moves.push(chess_move);
-                    }
+                } else if !must_take_enemy {
+                    moves.push(chess_move);
                 }
             }
 
@@ -573,7 +678,31 @@ mod tests {
             .collect();
 
         notationed_moves.sort();
-        let mut expected_moves = vec!["a8"];
+        let mut expected_moves = vec!["a8", "a8", "a8", "a8"];
+        expected_moves.sort();
+
+        assert_eq!(notationed_moves, expected_moves)
+    }
+
+    #[test]
+    fn test_pawn_promotions() {
+        let chess_board = ChessBoard::from_fen("1n6/P7/8/8/8/8/8/8 w KQkq - 0 1");
+        let square = ChessBoard::square_from_notation("a7").unwrap();
+        let moves = chess_board.generate_moves(square);
+
+        let mut notationed_moves: Vec<String> = moves
+            .iter()
+            .map(|m| {
+                format!(
+                    "{}{}",
+                    ChessBoard::square_to_notation(m.destination).unwrap(),
+                    m.promotion.unwrap()
+                )
+            })
+            .collect();
+
+        notationed_moves.sort();
+        let mut expected_moves = vec!["a8B", "a8N", "a8Q", "a8R", "b8B", "b8N", "b8Q", "b8R"];
         expected_moves.sort();
 
         assert_eq!(notationed_moves, expected_moves)
@@ -609,7 +738,7 @@ mod tests {
             .collect();
 
         notationed_moves.sort();
-        let mut expected_moves = vec!["a1"];
+        let mut expected_moves = vec!["a1", "a1", "a1", "a1"];
         expected_moves.sort();
 
         assert_eq!(notationed_moves, expected_moves)
@@ -0,0 +1,263 @@
+use std::time::{Duration, Instant};
+
+use crate::{piece::Colour, search::SearchLimits};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Bonus {
+    // Added to the mover's clock after every move
+    Fischer(Duration),
+    // The first part of every move is free, up to the delay
+    Bronstein(Duration),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TimeControl {
+    pub base: Duration,
+    pub bonus: Bonus,
+}
+
+impl TimeControl {
+    // Reads "minutes+seconds" for a Fischer increment, or "minutes d seconds"
+    // (e.g. 5d3) for a Bronstein delay. Minutes may be fractional, so 0.5+0
+    // is a 30 second game.
+    pub fn parse(time_control: &str) -> Option<TimeControl> {
+        let (base, bonus, is_delay) = if let Some((base, bonus)) = time_control.split_once('+') {
+            (base, bonus, false)
+        } else if let Some((base, bonus)) = time_control.split_once('d') {
+            (base, bonus, true)
+        } else {
+            (time_control, "0", false)
+        };
+
+        let base: f64 = base.trim().parse().ok()?;
+        let bonus: f64 = bonus.trim().parse().ok()?;
+        if !base.is_finite() || !bonus.is_finite() || base <= 0.0 || bonus < 0.0 {
+            return None;
+        }
+
+        let bonus_duration = Duration::from_secs_f64(bonus);
+        Some(TimeControl {
+            base: Duration::from_secs_f64(base * 60.0),
+            bonus: if is_delay {
+                Bonus::Bronstein(bonus_duration)
+            } else {
+                Bonus::Fischer(bonus_duration)
+            },
+        })
+    }
+}
+
+pub struct ChessClock {
+    time_control: TimeControl,
+    remaining: [Duration; 2],
+    running: Option<(Colour, Instant)>,
+    flagged: Option<Colour>,
+}
+
+fn clock_index(colour: Colour) -> usize {
+    match colour {
+        Colour::White => 0,
+        Colour::Black => 1,
+    }
+}
+
+impl ChessClock {
+    pub fn new(time_control: TimeControl) -> Self {
+        ChessClock {
+            time_control,
+            remaining: [time_control.base; 2],
+            running: None,
+            flagged: None,
+        }
+    }
+
+    pub fn start(&mut self, colour: Colour, now: Instant) {
+        if self.flagged.is_none() {
+            self.running = Some((colour, now));
+        }
+    }
+
+    pub fn stop(&mut self, now: Instant) {
+        if let Some((colour, started)) = self.running.take() {
+            let index = clock_index(colour);
+            self.remaining[index] = self.remaining[index].saturating_sub(now - started);
+        }
+    }
+
+    // Called when the side on move completes their move. Charges them for the
+    // time used, applies the bonus and starts the opponent's clock.
+    pub fn press(&mut self, now: Instant) {
+        if self.check_flag(now).is_some() {
+            return;
+        }
+
+        if let Some((colour, started)) = self.running.take() {
+            let index = clock_index(colour);
+            let used = now - started;
+            self.remaining[index] = self.remaining[index].saturating_sub(used);
+
+            match self.time_control.bonus {
+                Bonus::Fischer(increment) => self.remaining[index] += increment,
+                Bonus::Bronstein(delay) => self.remaining[index] += used.min(delay),
+            }
+
+            self.running = Some((colour.opposite(), now));
+        }
+    }
+
+    pub fn remaining(&self, colour: Colour, now: Instant) -> Duration {
+        let remaining = self.remaining[clock_index(colour)];
+        match self.running {
+            Some((running, started)) if running == colour => {
+                remaining.saturating_sub(now - started)
+            }
+            _ => remaining,
+        }
+    }
+
+    pub fn running_side(&self) -> Option<Colour> {
+        self.running.map(|(colour, _)| colour)
+    }
+
+    // Returns the side that has run out of time, stopping the clock if so.
+    pub fn check_flag(&mut self, now: Instant) -> Option<Colour> {
+        if self.flagged.is_none() {
+            if let Some((colour, _)) = self.running {
+                if self.remaining(colour, now).is_zero() {
+                    self.stop(now);
+                    self.flagged = Some(colour);
+                }
+            }
+        }
+        self.flagged
+    }
+
+    // The clocks as the engine sees them. A delay is as good as an increment
+    // for planning, since time used up to it comes back.
+    pub fn search_limits(&self, now: Instant) -> SearchLimits {
+        let bonus = match self.time_control.bonus {
+            Bonus::Fischer(increment) => increment,
+            Bonus::Bronstein(delay) => delay,
+        };
+        SearchLimits {
+            wtime: Some(self.remaining(Colour::White, now)),
+            btime: Some(self.remaining(Colour::Black, now)),
+            winc: Some(bonus),
+            binc: Some(bonus),
+            ..SearchLimits::default()
+        }
+    }
+
+    pub fn format(duration: Duration) -> String {
+        let total_secs = duration.as_secs();
+        if total_secs < 10 {
+            format!("00:{:02}.{}", total_secs, duration.subsec_millis() / 100)
+        } else {
+            format!("{:02}:{:02}", total_secs / 60, total_secs % 60)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_time_control() {
+        assert_eq!(
+            TimeControl::parse("5+3"),
+            Some(TimeControl {
+                base: Duration::from_secs(300),
+                bonus: Bonus::Fischer(Duration::from_secs(3)),
+            })
+        );
+        assert_eq!(
+            TimeControl::parse("0.5d2"),
+            Some(TimeControl {
+                base: Duration::from_secs(30),
+                bonus: Bonus::Bronstein(Duration::from_secs(2)),
+            })
+        );
+        assert_eq!(
+            TimeControl::parse("10"),
+            Some(TimeControl {
+                base: Duration::from_secs(600),
+                bonus: Bonus::Fischer(Duration::ZERO),
+            })
+        );
+        assert_eq!(TimeControl::parse("fast"), None);
+        assert_eq!(TimeControl::parse("0+1"), None);
+        assert_eq!(TimeControl::parse("5+-1"), None);
+        assert_eq!(TimeControl::parse("NaN+1"), None);
+    }
+
+    #[test]
+    fn test_fischer_increment() {
+        let mut clock = ChessClock::new(TimeControl::parse("1+2").unwrap());
+        let start = Instant::now();
+        clock.start(Colour::White, start);
+        clock.press(start + Duration::from_secs(10));
+
+        assert_eq!(
+            clock.remaining(Colour::White, start),
+            Duration::from_secs(52)
+        );
+        assert_eq!(clock.running_side(), Some(Colour::Black));
+        assert_eq!(
+            clock.remaining(Colour::Black, start + Duration::from_secs(15)),
+            Duration::from_secs(55)
+        );
+    }
+
+    #[test]
+    fn test_bronstein_delay() {
+        let mut clock = ChessClock::new(TimeControl::parse("1d2").unwrap());
+        let start = Instant::now();
+        clock.start(Colour::White, start);
+        clock.press(start + Duration::from_secs(1));
+        assert_eq!(
+            clock.remaining(Colour::White, start),
+            Duration::from_secs(60)
+        );
+
+        clock.press(start + Duration::from_secs(11));
+        assert_eq!(
+            clock.remaining(Colour::Black, start),
+            Duration::from_secs(52)
+        );
+    }
+
+    #[test]
+    fn test_flag() {
+        let mut clock = ChessClock::new(TimeControl::parse("0.5+0").unwrap());
+        let start = Instant::now();
+        clock.start(Colour::White, start);
+
+        assert_eq!(clock.check_flag(start + Duration::from_secs(29)), None);
+        assert_eq!(
+            clock.check_flag(start + Duration::from_secs(31)),
+            Some(Colour::White)
+        );
+        assert_eq!(clock.running_side(), None);
+        assert_eq!(clock.remaining(Colour::White, start), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_search_limits() {
+        let mut clock = ChessClock::new(TimeControl::parse("1d2").unwrap());
+        let start = Instant::now();
+        clock.start(Colour::White, start);
+
+        let limits = clock.search_limits(start + Duration::from_secs(5));
+        assert_eq!(limits.wtime, Some(Duration::from_secs(55)));
+        assert_eq!(limits.btime, Some(Duration::from_secs(60)));
+        assert_eq!(limits.binc, Some(Duration::from_secs(2)));
+        assert_eq!(limits.depth, None);
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(ChessClock::format(Duration::from_secs(305)), "05:05");
+        assert_eq!(ChessClock::format(Duration::from_millis(9_450)), "00:09.4");
+    }
+}
@@ -0,0 +1,92 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::skill::MAX_SKILL_LEVEL;
+
+// Settings read from a plain `key = value` file. Anything given on the
+// command line wins over the file.
+#[derive(Default, Debug, PartialEq, Eq)]
+pub struct Config {
+    pub theme: Option<String>,
+    pub ascii: Option<bool>,
+    pub skill: Option<u32>,
+}
+
+impl Config {
+    pub fn parse(contents: &str) -> Result<Config, String> {
+        let mut config = Config::default();
+
+        for (line_number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("line {}: expected key = value", line_number + 1))?;
+            let value = value.trim().trim_matches('"');
+
+            match key.trim() {
+                "theme" => config.theme = Some(value.to_string()),
+                "ascii" => {
+                    config.ascii = Some(value.parse().map_err(|_| {
+                        format!("line {}: ascii must be true or false", line_number + 1)
+                    })?)
+                }
+                "skill" => {
+                    config.skill = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|&level| level <= MAX_SKILL_LEVEL)
+                            .ok_or_else(|| {
+                                format!("line {}: skill must be 0 to 20", line_number + 1)
+                            })?,
+                    )
+                }
+                other => return Err(format!("line {}: unknown key {}", line_number + 1, other)),
+            }
+        }
+
+        Ok(config)
+    }
+
+    pub fn load(path: &Path) -> Result<Config, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+        Config::parse(&contents).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn default_path() -> Option<PathBuf> {
+        let home = std::env::var_os("HOME")?;
+        Some(Path::new(&home).join(".config/chess-rust/config"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let config =
+            Config::parse("# my settings\ntheme = \"green\"\n\nascii=true\nskill = 8\n").unwrap();
+        assert_eq!(
+            config,
+            Config {
+                theme: Some("green".to_string()),
+                ascii: Some(true),
+                skill: Some(8),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_config_errors() {
+        assert!(Config::parse("theme green").is_err());
+        assert!(Config::parse("ascii = maybe").is_err());
+        assert!(Config::parse("colour = red").is_err());
+        assert!(Config::parse("skill = 21").is_err());
+    }
+}
@@ -0,0 +1,230 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+use crate::{
+    analysis::white_score,
+    chess_board::{ChessBoard, START_FEN},
+    game_result::GameResult,
+    nnue::Network,
+    piece::Colour,
+    random::Random,
+    search::{self, Score, SearchLimits, SearchParams},
+    tt::TranspositionTable,
+};
+
+pub const DEFAULT_GAMES: usize = 100;
+pub const DEFAULT_DEPTH: u32 = 6;
+pub const DEFAULT_RANDOM_PLIES: usize = 8;
+
+// Games still going after this many plies are called drawn
+const MAX_PLIES: usize = 400;
+// Each game gets its own table, so it needn't be big
+const HASH_MB: usize = 16;
+
+// A position from a game and the search's score for it, from white's side
+struct Sample {
+    fen: String,
+    score: i32,
+}
+
+struct FinishedGame {
+    number: usize,
+    samples: Vec<Sample>,
+    winner: Option<Colour>,
+}
+
+// Self-play games for training the network: every quiet position the engine
+// reaches is written out with its score and how the game ended
+pub struct SelfPlay {
+    pub games: usize,
+    // Games played at once
+    pub concurrency: usize,
+    pub limits: SearchLimits,
+    // Random moves at the start of each game, so no two are the same
+    pub random_plies: usize,
+    pub network: Option<Arc<Network>>,
+    pub seed: u64,
+    pub output: PathBuf,
+}
+
+impl SelfPlay {
+    // A position a few random moves in that isn't already over
+    fn opening(&self, random: &mut Random) -> ChessBoard {
+        loop {
+            let mut board = ChessBoard::from_fen(START_FEN);
+            for _ in 0..self.random_plies {
+                let moves = board.legal_moves();
+                if moves.is_empty() {
+                    break;
+                }
+                board.make_move(moves[random.below(moves.len() as u64) as usize]);
+            }
+            let hashes = [board.hash()];
+            if GameResult::from_position(&mut board, &hashes).is_none() {
+                return board;
+            }
+        }
+    }
+
+    // Plays a game out, ending it early once the engine sees a mate
+    fn play(&self, random: &mut Random) -> (Vec<Sample>, Option<Colour>) {
+        let mut board = self.opening(random);
+        board.set_network(self.network.clone());
+        let params = SearchParams::default();
+        let tt = TranspositionTable::new(HASH_MB);
+        let stop = AtomicBool::new(false);
+        let mut hashes = vec![board.hash()];
+        let mut samples = Vec::new();
+
+        loop {
+            if let Some(result) = GameResult::from_position(&mut board, &hashes) {
+                return (samples, result.winner);
+            }
+            if hashes.len() > MAX_PLIES {
+                return (samples, None);
+            }
+
+            let side = board.side_to_move();
+            let history = &hashes[..hashes.len() - 1];
+            let result = search::search(
+                &mut board,
+                history,
+                &self.limits,
+                &params,
+                &tt,
+                None,
+                &stop,
+                |_| {},
+            );
+            let Some(best_move) = result.best_move else {
+                return (samples, None);
+            };
+            match white_score(result.score, side) {
+                Score::Mate(moves) if moves > 0 => return (samples, Some(Colour::White)),
+                Score::Mate(_) => return (samples, Some(Colour::Black)),
+                Score::Centipawns(score) => {
+                    // Scores in check or ahead of a capture are down to
+                    // tactics the evaluation can't see
+                    if !board.is_in_check(side) && !board.is_capture(best_move) {
+                        samples.push(Sample {
+                            fen: board.to_fen(),
+                            score,
+                        });
+                    }
+                }
+            }
+            board.make_move(best_move);
+            hashes.push(board.hash());
+        }
+    }
+
+    // Plays the games, writing `<fen> | <score> | <result>` lines as they
+    // finish. Returns how many positions were written.
+    pub fn run(&self) -> Result<usize, String> {
+        let file = File::create(&self.output)
+            .map_err(|e| format!("can't write {}: {}", self.output.display(), e))?;
+        let mut output = BufWriter::new(file);
+        let next = AtomicUsize::new(0);
+        let mut positions = 0;
+
+        let written: Result<(), String> = thread::scope(|scope| {
+            let (tx, rx) = mpsc::channel();
+            for _ in 0..self.concurrency.max(1) {
+                let tx = tx.clone();
+                let next = &next;
+                scope.spawn(move || loop {
+                    let number = next.fetch_add(1, Ordering::SeqCst);
+                    if number >= self.games {
+                        break;
+                    }
+                    let mut random = Random::new(self.seed ^ ((number as u64 + 1) << 32));
+                    let (samples, winner) = self.play(&mut random);
+                    let finished = FinishedGame {
+                        number,
+                        samples,
+                        winner,
+                    };
+                    if tx.send(finished).is_err() {
+                        break;
+                    }
+                });
+            }
+            drop(tx);
+
+            for game in rx {
+                let result = match game.winner {
+                    Some(Colour::White) => "1.0",
+                    Some(Colour::Black) => "0.0",
+                    None => "0.5",
+                };
+                for sample in &game.samples {
+                    writeln!(output, "{} | {} | {}", sample.fen, sample.score, result)
+                        .map_err(|e| format!("can't write positions: {}", e))?;
+                }
+                positions += game.samples.len();
+                eprintln!(
+                    "Game {}: {}, {} positions ({} in all)",
+                    game.number + 1,
+                    result,
+                    game.samples.len(),
+                    positions
+                );
+            }
+            Ok(())
+        });
+        written?;
+
+        output
+            .flush()
+            .map_err(|e| format!("can't write positions: {}", e))?;
+        Ok(positions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_self_play() {
+        let path = std::env::temp_dir().join(format!("datagen-{}.txt", std::process::id()));
+        let self_play = SelfPlay {
+            games: 2,
+            concurrency: 2,
+            limits: SearchLimits {
+                depth: Some(1),
+                ..SearchLimits::default()
+            },
+            random_plies: DEFAULT_RANDOM_PLIES,
+            network: None,
+            seed: 1,
+            output: path.clone(),
+        };
+        let positions = self_play.run().unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(positions > 0);
+        assert_eq!(text.lines().count(), positions);
+        for line in text.lines() {
+            let fields: Vec<&str> = line.split(" | ").collect();
+            assert_eq!(fields.len(), 3, "{}", line);
+            let mut board = ChessBoard::from_fen(fields[0]);
+            assert_eq!(board.to_fen(), fields[0]);
+            assert!(!board.legal_moves().is_empty());
+            assert!(fields[1].parse::<i32>().is_ok(), "{}", line);
+            assert!(["1.0", "0.5", "0.0"].contains(&fields[2]), "{}", line);
+        }
+
+        // The random moves make the games differ
+        let mut random = Random::new(1);
+        let first = self_play.opening(&mut random).to_fen();
+        assert_ne!(self_play.opening(&mut random).to_fen(), first);
+        assert_ne!(first, START_FEN);
+    }
+}
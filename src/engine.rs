@@ -0,0 +1,381 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::{
+    book::{Book, DEFAULT_BOOK_DEPTH},
+    chess_board::{ChessBoard, MoveRecord, START_FEN},
+    chess_move::ChessMove,
+    nnue::Network,
+    random::Random,
+    search::{self, SearchInfo, SearchLimits, SearchParams},
+    skill::Skill,
+    syzygy::Tablebase,
+    time_manager::DEFAULT_MOVE_OVERHEAD_MS,
+    tt::{TranspositionTable, DEFAULT_HASH_MB},
+};
+
+pub enum Report {
+    Info(SearchInfo),
+    BestMove(Option<ChessMove>),
+}
+
+// The game and search core shared by the protocol front ends. Searches run on
+// their own thread so the front end can keep reading commands.
+pub struct Engine {
+    board: ChessBoard,
+    history: Vec<MoveRecord>,
+    params: SearchParams,
+    move_overhead: Duration,
+    skill: Skill,
+    book: Option<Arc<Book>>,
+    // Book moves are only played up to this move number
+    book_depth: u32,
+    tablebase: Option<Arc<Tablebase>>,
+    network: Option<Arc<Network>>,
+    tt: Arc<TranspositionTable>,
+    stop: Arc<AtomicBool>,
+    search: Option<JoinHandle<()>>,
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Engine {
+            board: ChessBoard::from_fen(START_FEN),
+            history: Vec::new(),
+            params: SearchParams::default(),
+            move_overhead: Duration::from_millis(DEFAULT_MOVE_OVERHEAD_MS),
+            skill: Skill::default(),
+            book: None,
+            book_depth: DEFAULT_BOOK_DEPTH,
+            tablebase: None,
+            network: None,
+            tt: Arc::new(TranspositionTable::new(DEFAULT_HASH_MB)),
+            stop: Arc::new(AtomicBool::new(false)),
+            search: None,
+        }
+    }
+
+    pub fn new_game(&mut self) {
+        self.stop();
+        self.board = ChessBoard::from_fen(START_FEN);
+        self.board.set_network(self.network.clone());
+        self.history.clear();
+        self.tt.clear();
+    }
+
+    pub fn set_hash_size(&mut self, megabytes: usize) {
+        self.stop();
+        self.tt = Arc::new(TranspositionTable::new(megabytes));
+    }
+
+    pub fn clear_hash(&mut self) {
+        self.stop();
+        self.tt.clear();
+    }
+
+    // Sets one of the search tunables by name, false if there's no such
+    // tunable or the value is out of range
+    pub fn set_param(&mut self, name: &str, value: i32) -> bool {
+        self.stop();
+        self.params.set_tunable(name, value)
+    }
+
+    pub fn set_threads(&mut self, threads: usize) {
+        self.stop();
+        self.params.threads = threads;
+    }
+
+    pub fn set_multi_pv(&mut self, lines: usize) {
+        self.stop();
+        self.params.multi_pv = lines;
+    }
+
+    pub fn skill(&self) -> Skill {
+        self.skill
+    }
+
+    pub fn set_skill(&mut self, skill: Skill) {
+        self.stop();
+        self.skill = skill;
+    }
+
+    pub fn set_move_overhead(&mut self, move_overhead: Duration) {
+        self.move_overhead = move_overhead;
+    }
+
+    pub fn set_book(&mut self, book: Option<Arc<Book>>) {
+        self.stop();
+        self.book = book;
+    }
+
+    pub fn set_book_depth(&mut self, book_depth: u32) {
+        self.book_depth = book_depth;
+    }
+
+    pub fn set_tablebase(&mut self, tablebase: Option<Arc<Tablebase>>) {
+        self.stop();
+        self.tablebase = tablebase;
+    }
+
+    // Evaluates with the network, or the handcrafted evaluation for None
+    pub fn set_network(&mut self, network: Option<Arc<Network>>) {
+        self.stop();
+        self.network = network;
+        self.board.set_network(self.network.clone());
+    }
+
+    pub fn set_position(&mut self, fen: &str, moves: &[&str]) -> Result<(), String> {
+        self.stop();
+        ChessBoard::validate_fen(fen)
+            .map_err(|error| format!("illegal position {}: {}", fen, error))?;
+        self.board = ChessBoard::from_fen(fen);
+        self.board.set_network(self.network.clone());
+        self.history.clear();
+
+        for notation in moves {
+            self.play(notation)?;
+        }
+        Ok(())
+    }
+
+    pub fn play(&mut self, notation: &str) -> Result<ChessMove, String> {
+        let chess_move = self
+            .board
+            .parse_move(notation)
+            .ok_or_else(|| format!("illegal move {}", notation))?;
+        self.history.push(self.board.make_move(chess_move));
+        Ok(chess_move)
+    }
+
+    pub fn undo(&mut self) -> bool {
+        match self.history.pop() {
+            Some(record) => {
+                self.board.unmake_move(record);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn board(&self) -> &ChessBoard {
+        &self.board
+    }
+
+    // Starts thinking about the current position. Progress and the chosen
+    // move are passed to `report` from the search thread.
+    pub fn go<F>(&mut self, mut limits: SearchLimits, report: F)
+    where
+        F: Fn(Report) + Send + 'static,
+    {
+        self.stop();
+        self.stop.store(false, Ordering::SeqCst);
+
+        // Play straight from the book when it has the position, unless asked
+        // to analyse
+        let book_move = match &self.book {
+            Some(book) if !limits.infinite && self.board.fullmove_number() <= self.book_depth => {
+                book.pick(&self.board, &mut Random::from_time())
+            }
+            _ => None,
+        };
+        if let Some(book_move) = book_move {
+            self.search = Some(thread::spawn(move || {
+                report(Report::BestMove(Some(book_move)))
+            }));
+            return;
+        }
+
+        limits.move_overhead = self.move_overhead;
+        let mut board = self.board.clone();
+        let history: Vec<u64> = self.history.iter().map(|record| record.hash).collect();
+        let mut params = self.params;
+        let skill = self.skill;
+        skill.limit(&mut limits, &mut params);
+        let tt = self.tt.clone();
+        let tablebase = self.tablebase.clone();
+        let stop = self.stop.clone();
+
+        self.search = Some(thread::spawn(move || {
+            let result = search::search(
+                &mut board,
+                &history,
+                &limits,
+                &params,
+                &tt,
+                tablebase.as_deref(),
+                &stop,
+                |info| report(Report::Info(info.clone())),
+            );
+
+            // An infinite search mustn't give its answer before it's asked
+            while limits.infinite && !stop.load(Ordering::SeqCst) {
+                thread::sleep(Duration::from_millis(1));
+            }
+            let best_move = skill
+                .pick(&result.lines, &mut Random::from_time())
+                .or(result.best_move);
+            report(Report::BestMove(best_move));
+        }));
+    }
+
+    // Stops any search and waits for it to report its move
+    pub fn stop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(search) = self.search.take() {
+            search.join().expect("search thread finished");
+        }
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_set_position() {
+        let mut engine = Engine::new();
+        engine.set_position(START_FEN, &["e2e4", "e7e5"]).unwrap();
+        assert_eq!(
+            engine.board().to_fen(),
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2"
+        );
+
+        assert!(engine.set_position(START_FEN, &["e2e5"]).is_err());
+
+        // A bad FEN leaves the position as it was
+        assert_eq!(
+            engine.set_position("8/8/8/8/8/8/8/8 w - - 0 1", &[]),
+            Err("illegal position 8/8/8/8/8/8/8/8 w - - 0 1: needs one king each".to_string())
+        );
+        assert_eq!(engine.board().to_fen(), START_FEN);
+    }
+
+    #[test]
+    fn test_go_takes_free_material() {
+        let mut engine = Engine::new();
+        engine
+            .set_position("4k3/8/8/3q4/4P3/8/8/4K3 w - - 0 1", &[])
+            .unwrap();
+
+        let limits = SearchLimits {
+            depth: Some(2),
+            ..SearchLimits::default()
+        };
+        let (tx, rx) = mpsc::channel();
+        engine.go(limits, move |report| {
+            if let Report::BestMove(best_move) = report {
+                tx.send(best_move).unwrap();
+            }
+        });
+
+        let best_move = rx.recv().unwrap().unwrap();
+        assert_eq!(best_move.to_string(), "e4d5");
+    }
+
+    #[test]
+    fn test_go_on_the_clock() {
+        let mut engine = Engine::new();
+        engine.set_move_overhead(Duration::from_millis(100));
+        let limits = SearchLimits {
+            wtime: Some(Duration::from_millis(1000)),
+            btime: Some(Duration::from_millis(1000)),
+            ..SearchLimits::default()
+        };
+        let (tx, rx) = mpsc::channel();
+        let start = std::time::Instant::now();
+        engine.go(limits, move |report| {
+            if let Report::BestMove(best_move) = report {
+                tx.send(best_move).unwrap();
+            }
+        });
+
+        assert!(rx.recv().unwrap().is_some());
+        assert!(start.elapsed() < Duration::from_millis(900));
+    }
+
+    #[test]
+    fn test_go_plays_from_the_book() {
+        // One entry for the start position, 1. d4 and 1. Nf3 both weighted
+        let mut bytes = Vec::new();
+        for packed_move in [11 << 6 | 27, 6 << 6 | 21_u16] {
+            bytes.extend(0x463b96181691fc9c_u64.to_be_bytes());
+            bytes.extend(packed_move.to_be_bytes());
+            bytes.extend([0, 1, 0, 0, 0, 0]);
+        }
+        let mut engine = Engine::new();
+        engine.set_book(Some(Arc::new(Book::from_bytes(&bytes).unwrap())));
+
+        let best_move = |engine: &mut Engine| {
+            let (tx, rx) = mpsc::channel();
+            let limits = SearchLimits {
+                depth: Some(1),
+                ..SearchLimits::default()
+            };
+            engine.go(limits, move |report| {
+                if let Report::BestMove(best_move) = report {
+                    tx.send(best_move.unwrap().to_string()).unwrap();
+                }
+            });
+            rx.recv().unwrap()
+        };
+        assert!(["d2d4", "g1f3"].contains(&best_move(&mut engine).as_str()));
+
+        // Past the book depth it searches, and picks something else
+        engine.set_book_depth(0);
+        assert!(!["d2d4", "g1f3"].contains(&best_move(&mut engine).as_str()));
+    }
+
+    #[test]
+    fn test_go_finds_mate() {
+        let mut engine = Engine::new();
+        engine
+            .set_position("6k1/5ppp/8/8/8/8/q7/1R4K1 w - - 0 1", &[])
+            .unwrap();
+
+        let limits = SearchLimits {
+            depth: Some(3),
+            ..SearchLimits::default()
+        };
+        let (tx, rx) = mpsc::channel();
+        engine.go(limits, move |report| {
+            tx.send(match report {
+                Report::Info(info) => format!("{:?}", info.score),
+                Report::BestMove(best_move) => best_move.unwrap().to_string(),
+            })
+            .unwrap();
+        });
+
+        let reports: Vec<String> = rx.iter().collect();
+        assert_eq!(reports[reports.len() - 2..], ["Mate(1)", "b1b8"]);
+    }
+
+    #[test]
+    fn test_infinite_search_waits_for_stop() {
+        let mut engine = Engine::new();
+        let (tx, rx) = mpsc::channel();
+        engine.go(
+            SearchLimits {
+                infinite: true,
+                ..SearchLimits::default()
+            },
+            move |report| {
+                if let Report::BestMove(best_move) = report {
+                    tx.send(best_move).unwrap();
+                }
+            },
+        );
+
+        assert!(rx.recv_timeout(Duration::from_millis(50)).is_err());
+        engine.stop();
+        assert!(rx.recv().unwrap().is_some());
+    }
+}
@@ -0,0 +1,769 @@
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{
+    chess_board::{ChessBoard, START_FEN},
+    chess_move::ChessMove,
+    clock::{ChessClock, TimeControl},
+    game_result::GameResult,
+    pgn::{parse_pgn, write_pgn},
+    piece::Colour,
+    san::{format_line, parse_san},
+    search::SearchLimits,
+    uci::{format_go, Uci},
+};
+
+pub const DEFAULT_GAMES: usize = 100;
+// Per move, without a clock or any other limit
+pub const DEFAULT_MOVETIME_MS: u64 = 100;
+
+// How long an engine has to start up or get ready
+const READY_TIMEOUT: Duration = Duration::from_secs(10);
+// How long past its clock an engine's move is waited for, before it's taken
+// as lost on time
+const FLAG_GRACE: Duration = Duration::from_secs(1);
+// How long to wait for a move without a clock before giving up on the engine
+const MOVE_TIMEOUT: Duration = Duration::from_secs(60);
+
+// One side of the match: our own engine with some options set, or another
+// UCI engine
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EngineSpec {
+    pub name: String,
+    pub command: Option<String>,
+    pub options: Vec<(String, String)>,
+}
+
+impl EngineSpec {
+    // Reads comma separated settings, e.g. "cmd=./stockfish,Hash=64" or
+    // "name=no-null,NullMoveReduction=0". `cmd` is the program to run with
+    // any arguments, our engine if there's none, `name` what to call it in
+    // the results, and the rest are UCI options to set.
+    pub fn parse(spec: &str) -> Result<EngineSpec, String> {
+        let mut name = None;
+        let mut command = None;
+        let mut options = Vec::new();
+        for setting in spec.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let (key, value) = setting
+                .split_once('=')
+                .ok_or_else(|| format!("bad engine setting {}", setting))?;
+            let (key, value) = (key.trim(), value.trim().to_string());
+            match key {
+                "name" => name = Some(value),
+                "cmd" => command = Some(value),
+                _ => options.push((key.to_string(), value)),
+            }
+        }
+
+        let name = name.unwrap_or_else(|| match &command {
+            Some(command) => {
+                let program = command.split_whitespace().next().unwrap_or_default();
+                Path::new(program)
+                    .file_stem()
+                    .map_or(command.clone(), |stem| stem.to_string_lossy().to_string())
+            }
+            None if options.is_empty() => env!("CARGO_PKG_NAME").to_string(),
+            None => {
+                let options: Vec<String> = options
+                    .iter()
+                    .map(|(key, value)| format!("{}={}", key, value))
+                    .collect();
+                format!("{} {}", env!("CARGO_PKG_NAME"), options.join(","))
+            }
+        });
+        Ok(EngineSpec {
+            name,
+            command,
+            options,
+        })
+    }
+}
+
+// Turns what our engine prints into lines, the way reading another engine's
+// output gives them
+struct ReplyWriter {
+    buffer: Vec<u8>,
+    lines: Sender<String>,
+}
+
+impl Write for ReplyWriter {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(bytes);
+        while let Some(end) = self.buffer.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            let _ = self
+                .lines
+                .send(String::from_utf8_lossy(&line).trim_end().to_string());
+        }
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// A running engine, spoken to over UCI whether it's another program or ours
+// on a thread of its own
+struct Player {
+    name: String,
+    commands: Sender<String>,
+    replies: Receiver<String>,
+    child: Option<Child>,
+}
+
+impl Player {
+    fn start(spec: &EngineSpec) -> Result<Player, String> {
+        let (commands, command_rx) = mpsc::channel::<String>();
+        let (reply_tx, replies) = mpsc::channel();
+
+        let child = match &spec.command {
+            Some(command) => {
+                let mut words = command.split_whitespace();
+                let mut child = Command::new(words.next().unwrap_or_default())
+                    .args(words)
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::null())
+                    .spawn()
+                    .map_err(|e| format!("can't run {}: {}", command, e))?;
+                let mut stdin = child.stdin.take().expect("piped stdin");
+                let stdout = child.stdout.take().expect("piped stdout");
+                thread::spawn(move || {
+                    for command in command_rx {
+                        if writeln!(stdin, "{}", command)
+                            .and_then(|_| stdin.flush())
+                            .is_err()
+                        {
+                            break;
+                        }
+                    }
+                });
+                thread::spawn(move || {
+                    for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                        if reply_tx.send(line).is_err() {
+                            break;
+                        }
+                    }
+                });
+                Some(child)
+            }
+            None => {
+                thread::spawn(move || {
+                    let mut uci = Uci::new(ReplyWriter {
+                        buffer: Vec::new(),
+                        lines: reply_tx,
+                    });
+                    for command in command_rx {
+                        if !uci.handle_command(&command) {
+                            break;
+                        }
+                    }
+                });
+                None
+            }
+        };
+
+        let player = Player {
+            name: spec.name.clone(),
+            commands,
+            replies,
+            child,
+        };
+        player.send("uci");
+        player
+            .wait_for("uciok", Instant::now() + READY_TIMEOUT)
+            .map_err(|_| format!("{} didn't start", player.name))?;
+        for (name, value) in &spec.options {
+            player.send(&format!("setoption name {} value {}", name, value));
+        }
+        player.ready()?;
+        Ok(player)
+    }
+
+    fn send(&self, command: &str) {
+        let _ = self.commands.send(command.to_string());
+    }
+
+    // Reads up to the first line starting with `expected`, giving the lines
+    // along the way
+    fn wait_for(&self, expected: &str, deadline: Instant) -> Result<Vec<String>, RecvTimeoutError> {
+        let mut lines = Vec::new();
+        loop {
+            let line = self
+                .replies
+                .recv_timeout(deadline.saturating_duration_since(Instant::now()))?;
+            let found = line.starts_with(expected);
+            lines.push(line);
+            if found {
+                return Ok(lines);
+            }
+        }
+    }
+
+    // Waits for the engine to catch up, failing if it complained about any
+    // options on the way
+    fn ready(&self) -> Result<(), String> {
+        self.send("isready");
+        let lines = self
+            .wait_for("readyok", Instant::now() + READY_TIMEOUT)
+            .map_err(|_| format!("{} isn't responding", self.name))?;
+        match lines.iter().find(|line| {
+            line.starts_with("info string bad") || line.starts_with("info string unknown option")
+        }) {
+            Some(line) => Err(format!("{}: {}", self.name, &line["info string ".len()..])),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Player {
+    fn drop(&mut self) {
+        self.send("quit");
+        if let Some(child) = self.child.as_mut() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+fn elo_from_score(score: f64) -> f64 {
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+fn score_from_elo(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+// Results from the first engine's side
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MatchStats {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl MatchStats {
+    pub fn add(&mut self, result: GameResult, first_colour: Colour) {
+        match result.winner {
+            Some(winner) if winner == first_colour => self.wins += 1,
+            Some(_) => self.losses += 1,
+            None => self.draws += 1,
+        }
+    }
+
+    pub fn games(self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    // Points per game
+    pub fn score(self) -> f64 {
+        (self.wins as f64 + self.draws as f64 / 2.0) / self.games().max(1) as f64
+    }
+
+    // Of a single game's points
+    fn variance(self) -> f64 {
+        let score = self.score();
+        let spread = self.wins as f64 * (1.0 - score).powi(2)
+            + self.draws as f64 * (0.5 - score).powi(2)
+            + self.losses as f64 * score.powi(2);
+        spread / self.games().max(1) as f64
+    }
+
+    // The Elo difference the score suggests, with its 95% error margin. There
+    // isn't one until both sides have some points.
+    pub fn elo(self) -> Option<(f64, f64)> {
+        let score = self.score();
+        if self.games() == 0 || score <= 0.0 || score >= 1.0 {
+            return None;
+        }
+        let margin = 1.96 * (self.variance() / self.games() as f64).sqrt();
+        let (low, high) = ((score - margin).max(1e-6), (score + margin).min(1.0 - 1e-6));
+        Some((
+            elo_from_score(score),
+            (elo_from_score(high) - elo_from_score(low)) / 2.0,
+        ))
+    }
+
+    // The log-likelihood ratio of the first engine being elo1 better rather
+    // than elo0, taking the score to be normally distributed
+    pub fn llr(self, elo0: f64, elo1: f64) -> f64 {
+        let variance = self.variance();
+        if variance == 0.0 {
+            return 0.0;
+        }
+        let (score0, score1) = (score_from_elo(elo0), score_from_elo(elo1));
+        self.games() as f64 * (score1 - score0) * (2.0 * self.score() - score0 - score1)
+            / (2.0 * variance)
+    }
+}
+
+// A sequential probability ratio test of whether the first engine is elo1
+// stronger (H1) or only elo0 (H0), with alpha and beta the chances of
+// wrongly accepting either
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+impl Sprt {
+    pub fn bounds(self) -> (f64, f64) {
+        (
+            (self.beta / (1.0 - self.alpha)).ln(),
+            ((1.0 - self.beta) / self.alpha).ln(),
+        )
+    }
+
+    // Some(true) when H1's accepted, Some(false) for H0 and None while it
+    // needs more games
+    pub fn verdict(self, stats: MatchStats) -> Option<bool> {
+        let llr = stats.llr(self.elo0, self.elo1);
+        let (lower, upper) = self.bounds();
+        if llr >= upper {
+            Some(true)
+        } else if llr <= lower {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    pub fn describe(self, stats: MatchStats) -> String {
+        let (lower, upper) = self.bounds();
+        let verdict = match self.verdict(stats) {
+            Some(true) => "H1 accepted",
+            Some(false) => "H0 accepted",
+            None => "no verdict yet",
+        };
+        format!(
+            "SPRT: llr {:.2} ({:.2}, {:.2}) [{}, {}], {}",
+            stats.llr(self.elo0, self.elo1),
+            lower,
+            upper,
+            self.elo0,
+            self.elo1,
+            verdict
+        )
+    }
+}
+
+// EPD has no move counters, so they're put back for a FEN
+pub fn epd_to_fen(line: &str) -> String {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let counters = fields
+        .get(4..6)
+        .filter(|counters| counters.iter().all(|c| c.parse::<u32>().is_ok()));
+    match counters {
+        Some(counters) => format!("{} {}", fields[..4].join(" "), counters.join(" ")),
+        None => format!("{} 0 1", fields[..fields.len().min(4)].join(" ")),
+    }
+}
+
+// Starting positions from a file of FEN or EPD lines, or from the ends of
+// the games in a PGN file
+pub fn load_openings(path: &Path) -> Result<Vec<String>, String> {
+    let text =
+        fs::read_to_string(path).map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+    let openings = if path.extension().is_some_and(|extension| extension == "pgn") {
+        parse_pgn(&text)
+            .iter()
+            .map(|game| {
+                let mut board = ChessBoard::from_fen(game.tag("FEN").unwrap_or(START_FEN));
+                for san in &game.moves {
+                    let chess_move = parse_san(&mut board, san)
+                        .ok_or_else(|| format!("illegal move {}", san))?;
+                    board.make_move(chess_move);
+                }
+                Ok(board.to_fen())
+            })
+            .collect::<Result<Vec<String>, String>>()?
+    } else {
+        text.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(epd_to_fen)
+            .collect()
+    };
+
+    if openings.is_empty() {
+        return Err(format!("no openings in {}", path.display()));
+    }
+    Ok(openings)
+}
+
+struct FinishedGame {
+    number: usize,
+    fen: String,
+    first_colour: Colour,
+    moves: Vec<ChessMove>,
+    result: GameResult,
+}
+
+pub struct Match {
+    pub first: EngineSpec,
+    pub second: EngineSpec,
+    // Each is played twice, the engines swapping colours
+    pub openings: Vec<String>,
+    pub games: usize,
+    // Games played at once
+    pub concurrency: usize,
+    pub time_control: Option<TimeControl>,
+    // Per move, when there's no clock
+    pub limits: SearchLimits,
+    // Stops the match once it's decided
+    pub sprt: Option<Sprt>,
+    pub pgn: Option<PathBuf>,
+}
+
+impl Match {
+    fn play(&self, white: &Player, black: &Player, fen: &str) -> (Vec<ChessMove>, GameResult) {
+        let mut board = ChessBoard::from_fen(fen);
+        let mut hashes = vec![board.hash()];
+        let mut moves: Vec<ChessMove> = Vec::new();
+        for (player, colour) in [(white, Colour::White), (black, Colour::Black)] {
+            player.send("ucinewgame");
+            if player.ready().is_err() {
+                return (moves, GameResult::forfeit(colour));
+            }
+        }
+
+        let mut clock = self.time_control.map(ChessClock::new);
+        if let Some(clock) = clock.as_mut() {
+            clock.start(board.side_to_move(), Instant::now());
+        }
+
+        loop {
+            if let Some(result) = GameResult::from_position(&mut board, &hashes) {
+                return (moves, result);
+            }
+
+            let side = board.side_to_move();
+            let player = match side {
+                Colour::White => white,
+                Colour::Black => black,
+            };
+            let now = Instant::now();
+            let (limits, timeout) = match &clock {
+                Some(clock) => (
+                    clock.search_limits(now),
+                    clock.remaining(side, now) + FLAG_GRACE,
+                ),
+                None => (self.limits, MOVE_TIMEOUT),
+            };
+
+            let mut position = format!("position fen {}", fen);
+            if !moves.is_empty() {
+                let played: Vec<String> = moves.iter().map(ChessMove::to_string).collect();
+                position.push_str(&format!(" moves {}", played.join(" ")));
+            }
+            player.send(&position);
+            player.send(&format_go(&limits));
+            let reply = player.wait_for("bestmove", now + timeout);
+
+            if let Some(clock) = clock.as_mut() {
+                let now = Instant::now();
+                if let Some(flagged) = clock.check_flag(now) {
+                    player.send("stop");
+                    return (moves, GameResult::on_timeout(flagged, &board));
+                }
+                clock.press(now);
+            }
+
+            let chess_move = reply.ok().and_then(|lines| {
+                let line = lines.last()?;
+                let notation = line.split_whitespace().nth(1)?;
+                board.parse_move(notation)
+            });
+            match chess_move {
+                Some(chess_move) => {
+                    board.make_move(chess_move);
+                    hashes.push(board.hash());
+                    moves.push(chess_move);
+                }
+                None => {
+                    player.send("stop");
+                    return (moves, GameResult::forfeit(side));
+                }
+            }
+        }
+    }
+
+    fn to_pgn(&self, game: &FinishedGame) -> String {
+        let (white, black) = match game.first_colour {
+            Colour::White => (&self.first.name, &self.second.name),
+            Colour::Black => (&self.second.name, &self.first.name),
+        };
+        let result = game.result.to_pgn();
+        let mut tags = vec![
+            (
+                "Event".to_string(),
+                format!("{} match", env!("CARGO_PKG_NAME")),
+            ),
+            ("Round".to_string(), (game.number + 1).to_string()),
+            ("White".to_string(), white.clone()),
+            ("Black".to_string(), black.clone()),
+            ("Result".to_string(), result.to_string()),
+        ];
+        if game.fen != START_FEN {
+            tags.push(("SetUp".to_string(), "1".to_string()));
+            tags.push(("FEN".to_string(), game.fen.clone()));
+        }
+        tags.push(("Termination".to_string(), game.result.describe()));
+
+        let board = ChessBoard::from_fen(&game.fen);
+        let movetext: Vec<String> = format_line(&board, &game.moves)
+            .split_whitespace()
+            .map(str::to_string)
+            .collect();
+        write_pgn(&tags, &movetext, result)
+    }
+
+    // Plays the games, printing the score as they finish, until they're all
+    // done or the SPRT has a verdict
+    pub fn run(&self) -> Result<MatchStats, String> {
+        let mut pgn = match &self.pgn {
+            Some(path) => Some(
+                File::create(path).map_err(|e| format!("can't write {}: {}", path.display(), e))?,
+            ),
+            None => None,
+        };
+        let next = AtomicUsize::new(0);
+        let stop = AtomicBool::new(false);
+        let mut stats = MatchStats::default();
+
+        thread::scope(|scope| {
+            let (tx, rx) = mpsc::channel();
+            for _ in 0..self.concurrency.max(1) {
+                let tx = tx.clone();
+                let (next, stop) = (&next, &stop);
+                scope.spawn(move || {
+                    let players = match (Player::start(&self.first), Player::start(&self.second)) {
+                        (Ok(first), Ok(second)) => [first, second],
+                        (Err(error), _) | (_, Err(error)) => {
+                            let _ = tx.send(Err(error));
+                            return;
+                        }
+                    };
+                    loop {
+                        let number = next.fetch_add(1, Ordering::SeqCst);
+                        if number >= self.games || stop.load(Ordering::SeqCst) {
+                            break;
+                        }
+                        let fen = &self.openings[number / 2 % self.openings.len()];
+                        // The first engine has white in the first game of
+                        // each pair
+                        let first_colour = if number % 2 == 0 {
+                            Colour::White
+                        } else {
+                            Colour::Black
+                        };
+                        let (white, black) = match first_colour {
+                            Colour::White => (&players[0], &players[1]),
+                            Colour::Black => (&players[1], &players[0]),
+                        };
+                        let (moves, result) = self.play(white, black, fen);
+                        let finished = FinishedGame {
+                            number,
+                            fen: fen.clone(),
+                            first_colour,
+                            moves,
+                            result,
+                        };
+                        if tx.send(Ok(finished)).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            drop(tx);
+
+            for finished in rx {
+                let game = match finished {
+                    Ok(game) => game,
+                    Err(error) => {
+                        stop.store(true, Ordering::SeqCst);
+                        return Err(error);
+                    }
+                };
+                stats.add(game.result, game.first_colour);
+                let (white, black) = match game.first_colour {
+                    Colour::White => (&self.first.name, &self.second.name),
+                    Colour::Black => (&self.second.name, &self.first.name),
+                };
+                println!(
+                    "Game {} ({} vs {}): {}",
+                    game.number + 1,
+                    white,
+                    black,
+                    game.result.describe()
+                );
+                println!(
+                    "Score of {} vs {}: {} - {} - {} [{:.3}] {}",
+                    self.first.name,
+                    self.second.name,
+                    stats.wins,
+                    stats.losses,
+                    stats.draws,
+                    stats.score(),
+                    stats.games()
+                );
+                if let Some(file) = pgn.as_mut() {
+                    file.write_all(self.to_pgn(&game).as_bytes())
+                        .map_err(|e| format!("can't write games: {}", e))?;
+                }
+                if let Some(sprt) = self.sprt {
+                    if sprt.verdict(stats).is_some() {
+                        stop.store(true, Ordering::SeqCst);
+                    }
+                }
+            }
+            Ok(())
+        })?;
+
+        match stats.elo() {
+            Some((elo, margin)) => println!("Elo difference: {:.1} +/- {:.1}", elo, margin),
+            None => println!("Elo difference: unknown until both sides score"),
+        }
+        if let Some(sprt) = self.sprt {
+            println!("{}", sprt.describe(stats));
+        }
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_engine_spec() {
+        let spec = EngineSpec::parse(
+            "cmd=/usr/bin/fairy-stockfish load variants.ini, Hash=64,Skill Level=3",
+        )
+        .unwrap();
+        assert_eq!(spec.name, "fairy-stockfish");
+        assert_eq!(
+            spec.command.as_deref(),
+            Some("/usr/bin/fairy-stockfish load variants.ini")
+        );
+        assert_eq!(
+            spec.options,
+            [
+                ("Hash".to_string(), "64".to_string()),
+                ("Skill Level".to_string(), "3".to_string())
+            ]
+        );
+
+        let spec = EngineSpec::parse("NullMoveReduction=0").unwrap();
+        assert_eq!(spec.name, "chess-rust NullMoveReduction=0");
+        assert_eq!(spec.command, None);
+        assert_eq!(EngineSpec::parse("").unwrap().name, "chess-rust");
+        assert_eq!(EngineSpec::parse("name=new").unwrap().name, "new");
+        assert!(EngineSpec::parse("Hash").is_err());
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut stats = MatchStats::default();
+        assert_eq!(stats.elo(), None);
+        let win = GameResult::forfeit(Colour::Black);
+        stats.add(win, Colour::White);
+        stats.add(win, Colour::Black);
+        assert_eq!((stats.wins, stats.losses), (1, 1));
+
+        let stats = MatchStats {
+            wins: 60,
+            draws: 20,
+            losses: 20,
+        };
+        assert_eq!(stats.score(), 0.7);
+        let (elo, margin) = stats.elo().unwrap();
+        assert!((elo - 147.2).abs() < 0.1);
+        assert!(margin > 50.0 && margin < 100.0);
+        assert!((stats.llr(0.0, 5.0) - 0.88).abs() < 0.01);
+
+        let sprt = Sprt {
+            elo0: 0.0,
+            elo1: 5.0,
+            alpha: 0.05,
+            beta: 0.05,
+        };
+        let (lower, upper) = sprt.bounds();
+        assert!((upper - 2.94).abs() < 0.01);
+        assert_eq!(lower, -upper);
+        assert_eq!(sprt.verdict(stats), None);
+        let stats = MatchStats {
+            wins: 600,
+            draws: 200,
+            losses: 200,
+        };
+        assert_eq!(sprt.verdict(stats), Some(true));
+        let stats = MatchStats {
+            wins: 200,
+            draws: 200,
+            losses: 600,
+        };
+        assert_eq!(sprt.verdict(stats), Some(false));
+    }
+
+    #[test]
+    fn test_openings() {
+        assert_eq!(
+            epd_to_fen("4k3/8/8/8/8/8/4P3/4K3 w - - bm e4; id \"pawn\";"),
+            "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1"
+        );
+        assert_eq!(epd_to_fen(START_FEN), START_FEN);
+
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("chess-rust-openings-{}.pgn", std::process::id()));
+        fs::write(&path, "1. e4 e5 *\n\n1. d4 *\n").unwrap();
+        let openings = load_openings(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(openings.len(), 2);
+        assert_eq!(
+            openings[1],
+            "rnbqkbnr/pppppppp/8/8/3P4/8/PPP1PPPP/RNBQKBNR b KQkq d3 0 1"
+        );
+    }
+
+    #[test]
+    fn test_player_options() {
+        let spec = EngineSpec::parse("Hash=lots").unwrap();
+        assert_eq!(
+            Player::start(&spec).err(),
+            Some("chess-rust Hash=lots: bad Hash size lots".to_string())
+        );
+        let spec = EngineSpec::parse("cmd=/no/such/engine").unwrap();
+        assert!(Player::start(&spec).is_err());
+    }
+
+    #[test]
+    fn test_match() {
+        let engine_match = Match {
+            first: EngineSpec::parse("").unwrap(),
+            second: EngineSpec::parse("Skill Level=0").unwrap(),
+            // Taking the pawn leaves bare kings
+            openings: vec!["4k3/8/8/8/8/8/3p4/4K3 w - - 0 1".to_string()],
+            games: 2,
+            concurrency: 2,
+            time_control: None,
+            limits: SearchLimits {
+                depth: Some(2),
+                ..SearchLimits::default()
+            },
+            sprt: None,
+            pgn: None,
+        };
+        let stats = engine_match.run().unwrap();
+        assert_eq!(stats.games(), 2);
+    }
+}
@@ -0,0 +1,565 @@
+use std::ops::{Add, AddAssign, Neg, Sub};
+use std::slice;
+
+use crate::{
+    chess_board::{ChessBoard, SquareIndex, TOTAL_FILES, TOTAL_SQUARES},
+    piece::Colour,
+    weights::WEIGHTS,
+};
+
+// Scores with separate midgame and endgame values, blended by how much
+// material is left on the board
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct Tapered {
+    pub midgame: i32,
+    pub endgame: i32,
+}
+
+pub const fn tapered(midgame: i32, endgame: i32) -> Tapered {
+    Tapered { midgame, endgame }
+}
+
+impl Tapered {
+    fn blend(self, phase: i32) -> i32 {
+        (self.midgame * phase + self.endgame * (TOTAL_PHASE - phase)) / TOTAL_PHASE
+    }
+
+    fn times(self, count: i32) -> Tapered {
+        tapered(self.midgame * count, self.endgame * count)
+    }
+}
+
+impl Add for Tapered {
+    type Output = Tapered;
+    fn add(self, other: Tapered) -> Tapered {
+        tapered(self.midgame + other.midgame, self.endgame + other.endgame)
+    }
+}
+
+impl AddAssign for Tapered {
+    fn add_assign(&mut self, other: Tapered) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Tapered {
+    type Output = Tapered;
+    fn sub(self, other: Tapered) -> Tapered {
+        self + -other
+    }
+}
+
+impl Neg for Tapered {
+    type Output = Tapered;
+    fn neg(self) -> Tapered {
+        tapered(-self.midgame, -self.endgame)
+    }
+}
+
+const TOTAL_PHASE: i32 = 24;
+
+// The weights of everything but the piece-square tables, which `tune` can
+// change. The values are in weights.rs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Weights {
+    pub pawn: Tapered,
+    pub knight: Tapered,
+    pub bishop: Tapered,
+    pub rook: Tapered,
+    pub queen: Tapered,
+    // Per move over what a piece usually has
+    pub knight_mobility: Tapered,
+    pub bishop_mobility: Tapered,
+    pub rook_mobility: Tapered,
+    pub queen_mobility: Tapered,
+    pub doubled_pawn: Tapered,
+    pub isolated_pawn: Tapered,
+    // By how far up the board the pawn is
+    pub passed_pawn: [Tapered; 8],
+    pub pawn_shield: Tapered,
+    pub open_file_by_king: Tapered,
+    // Per move a piece has into the squares around the enemy king
+    pub king_attack: Tapered,
+    pub bishop_pair: Tapered,
+}
+
+impl Weights {
+    // Every weight by name, in the order they're written out
+    pub fn fields(&mut self) -> [(&'static str, &mut [Tapered]); 16] {
+        [
+            ("pawn", slice::from_mut(&mut self.pawn)),
+            ("knight", slice::from_mut(&mut self.knight)),
+            ("bishop", slice::from_mut(&mut self.bishop)),
+            ("rook", slice::from_mut(&mut self.rook)),
+            ("queen", slice::from_mut(&mut self.queen)),
+            (
+                "knight_mobility",
+                slice::from_mut(&mut self.knight_mobility),
+            ),
+            (
+                "bishop_mobility",
+                slice::from_mut(&mut self.bishop_mobility),
+            ),
+            ("rook_mobility", slice::from_mut(&mut self.rook_mobility)),
+            ("queen_mobility", slice::from_mut(&mut self.queen_mobility)),
+            ("doubled_pawn", slice::from_mut(&mut self.doubled_pawn)),
+            ("isolated_pawn", slice::from_mut(&mut self.isolated_pawn)),
+            ("passed_pawn", &mut self.passed_pawn),
+            ("pawn_shield", slice::from_mut(&mut self.pawn_shield)),
+            (
+                "open_file_by_king",
+                slice::from_mut(&mut self.open_file_by_king),
+            ),
+            ("king_attack", slice::from_mut(&mut self.king_attack)),
+            ("bishop_pair", slice::from_mut(&mut self.bishop_pair)),
+        ]
+    }
+}
+
+// Piece-square tables from white's side, a8 first so they look like the board
+#[rustfmt::skip]
+const PAWN_TABLE: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+    50, 50, 50, 50, 50, 50, 50, 50,
+    10, 10, 20, 30, 30, 20, 10, 10,
+     5,  5, 10, 25, 25, 10,  5,  5,
+     0,  0,  0, 20, 20,  0,  0,  0,
+     5, -5,-10,  0,  0,-10, -5,  5,
+     5, 10, 10,-20,-20, 10, 10,  5,
+     0,  0,  0,  0,  0,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const PAWN_ENDGAME_TABLE: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+    60, 60, 60, 60, 60, 60, 60, 60,
+    40, 40, 40, 40, 40, 40, 40, 40,
+    25, 25, 25, 25, 25, 25, 25, 25,
+    15, 15, 15, 15, 15, 15, 15, 15,
+     5,  5,  5,  5,  5,  5,  5,  5,
+     0,  0,  0,  0,  0,  0,  0,  0,
+     0,  0,  0,  0,  0,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const KNIGHT_TABLE: [i32; 64] = [
+   -50,-40,-30,-30,-30,-30,-40,-50,
+   -40,-20,  0,  0,  0,  0,-20,-40,
+   -30,  0, 10, 15, 15, 10,  0,-30,
+   -30,  5, 15, 20, 20, 15,  5,-30,
+   -30,  0, 15, 20, 20, 15,  0,-30,
+   -30,  5, 10, 15, 15, 10,  5,-30,
+   -40,-20,  0,  5,  5,  0,-20,-40,
+   -50,-40,-30,-30,-30,-30,-40,-50,
+];
+
+#[rustfmt::skip]
+const BISHOP_TABLE: [i32; 64] = [
+   -20,-10,-10,-10,-10,-10,-10,-20,
+   -10,  0,  0,  0,  0,  0,  0,-10,
+   -10,  0,  5, 10, 10,  5,  0,-10,
+   -10,  5,  5, 10, 10,  5,  5,-10,
+   -10,  0, 10, 10, 10, 10,  0,-10,
+   -10, 10, 10, 10, 10, 10, 10,-10,
+   -10,  5,  0,  0,  0,  0,  5,-10,
+   -20,-10,-10,-10,-10,-10,-10,-20,
+];
+
+#[rustfmt::skip]
+const ROOK_TABLE: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+     5, 10, 10, 10, 10, 10, 10,  5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+     0,  0,  0,  5,  5,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const QUEEN_TABLE: [i32; 64] = [
+   -20,-10,-10, -5, -5,-10,-10,-20,
+   -10,  0,  0,  0,  0,  0,  0,-10,
+   -10,  0,  5,  5,  5,  5,  0,-10,
+    -5,  0,  5,  5,  5,  5,  0, -5,
+     0,  0,  5,  5,  5,  5,  0, -5,
+   -10,  5,  5,  5,  5,  5,  0,-10,
+   -10,  0,  5,  0,  0,  0,  0,-10,
+   -20,-10,-10, -5, -5,-10,-10,-20,
+];
+
+#[rustfmt::skip]
+const KING_TABLE: [i32; 64] = [
+   -30,-40,-40,-50,-50,-40,-40,-30,
+   -30,-40,-40,-50,-50,-40,-40,-30,
+   -30,-40,-40,-50,-50,-40,-40,-30,
+   -30,-40,-40,-50,-50,-40,-40,-30,
+   -20,-30,-30,-40,-40,-30,-30,-20,
+   -10,-20,-20,-20,-20,-20,-20,-10,
+    20, 20,  0,  0,  0,  0, 20, 20,
+    20, 30, 10,  0,  0, 10, 30, 20,
+];
+
+// The king should come out and fight once the queens are off
+#[rustfmt::skip]
+const KING_ENDGAME_TABLE: [i32; 64] = [
+   -50,-40,-30,-20,-20,-30,-40,-50,
+   -30,-20,-10,  0,  0,-10,-20,-30,
+   -30,-10, 20, 30, 30, 20,-10,-30,
+   -30,-10, 30, 40, 40, 30,-10,-30,
+   -30,-10, 30, 40, 40, 30,-10,-30,
+   -30,-10, 20, 30, 30, 20,-10,-30,
+   -30,-30,  0,  0,  0,  0,-30,-30,
+   -50,-30,-30,-30,-30,-30,-30,-50,
+];
+
+// The breakdown of a position's score. Each term is in centipawns from
+// white's point of view, already blended between midgame and endgame.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct Evaluation {
+    pub material: i32,
+    pub piece_squares: i32,
+    pub mobility: i32,
+    pub pawn_structure: i32,
+    pub king_safety: i32,
+    pub bishop_pair: i32,
+    // From 24 with every piece on the board down to 0 with just kings and pawns
+    pub phase: i32,
+}
+
+impl Evaluation {
+    pub fn total(&self) -> i32 {
+        self.material
+            + self.piece_squares
+            + self.mobility
+            + self.pawn_structure
+            + self.king_safety
+            + self.bishop_pair
+    }
+}
+
+fn material(weights: &Weights, symbol: char) -> Tapered {
+    match symbol.to_ascii_uppercase() {
+        'P' => weights.pawn,
+        'N' => weights.knight,
+        'B' => weights.bishop,
+        'R' => weights.rook,
+        'Q' => weights.queen,
+        _ => Tapered::default(),
+    }
+}
+
+fn phase_weight(symbol: char) -> i32 {
+    match symbol.to_ascii_uppercase() {
+        'N' | 'B' => 1,
+        'R' => 2,
+        'Q' => 4,
+        _ => 0,
+    }
+}
+
+// Ranks counted from the colour's own side of the board
+fn relative_rank(square: SquareIndex, colour: Colour) -> u8 {
+    let (_, rank) = ChessBoard::square_to_file_and_rank(square);
+    match colour {
+        Colour::White => rank,
+        Colour::Black => 7 - rank,
+    }
+}
+
+fn piece_square(symbol: char, square: SquareIndex, colour: Colour) -> Tapered {
+    let (file, _) = ChessBoard::square_to_file_and_rank(square);
+    let index = ((7 - relative_rank(square, colour)) * TOTAL_FILES + file) as usize;
+    let (midgame, endgame) = match symbol.to_ascii_uppercase() {
+        'P' => (&PAWN_TABLE, &PAWN_ENDGAME_TABLE),
+        'N' => (&KNIGHT_TABLE, &KNIGHT_TABLE),
+        'B' => (&BISHOP_TABLE, &BISHOP_TABLE),
+        'R' => (&ROOK_TABLE, &ROOK_TABLE),
+        'Q' => (&QUEEN_TABLE, &QUEEN_TABLE),
+        _ => (&KING_TABLE, &KING_ENDGAME_TABLE),
+    };
+    tapered(midgame[index], endgame[index])
+}
+
+// Weight per move and how many moves the piece would usually have
+fn mobility_weight(weights: &Weights, symbol: char) -> Option<(Tapered, i32)> {
+    match symbol.to_ascii_uppercase() {
+        'N' => Some((weights.knight_mobility, 4)),
+        'B' => Some((weights.bishop_mobility, 7)),
+        'R' => Some((weights.rook_mobility, 7)),
+        'Q' => Some((weights.queen_mobility, 14)),
+        _ => None,
+    }
+}
+
+fn king_attack_units(symbol: char) -> i32 {
+    match symbol.to_ascii_uppercase() {
+        'N' | 'B' => 2,
+        'R' => 3,
+        'Q' => 5,
+        _ => 0,
+    }
+}
+
+fn is_next_to(square: SquareIndex, other: SquareIndex) -> bool {
+    let (file, rank) = ChessBoard::square_to_file_and_rank(square);
+    let (other_file, other_rank) = ChessBoard::square_to_file_and_rank(other);
+    file.abs_diff(other_file) <= 1 && rank.abs_diff(other_rank) <= 1
+}
+
+// Pawns of each colour on each file, by rank
+struct Pawns {
+    ranks: [[Vec<u8>; TOTAL_FILES as usize]; 2],
+}
+
+impl Pawns {
+    fn on_file(&self, colour: Colour, file: i8) -> &[u8] {
+        if !(0..TOTAL_FILES as i8).contains(&file) {
+            return &[];
+        }
+        &self.ranks[colour as usize][file as usize]
+    }
+}
+
+fn pawn_structure(weights: &Weights, pawns: &Pawns, colour: Colour) -> Tapered {
+    let mut score = Tapered::default();
+
+    for file in 0..TOTAL_FILES as i8 {
+        let own = pawns.on_file(colour, file);
+        if own.is_empty() {
+            continue;
+        }
+
+        score += weights.doubled_pawn.times(own.len() as i32 - 1);
+
+        if pawns.on_file(colour, file - 1).is_empty() && pawns.on_file(colour, file + 1).is_empty()
+        {
+            score += weights.isolated_pawn.times(own.len() as i32);
+        }
+
+        for &rank in own {
+            // Nothing can stop it on its own file or either side
+            let is_passed = (file - 1..=file + 1).all(|enemy_file| {
+                pawns.on_file(colour.opposite(), enemy_file).iter().all(
+                    |&enemy_rank| match colour {
+                        Colour::White => enemy_rank <= rank,
+                        Colour::Black => enemy_rank >= rank,
+                    },
+                )
+            });
+            if is_passed {
+                let relative = match colour {
+                    Colour::White => rank,
+                    Colour::Black => 7 - rank,
+                };
+                score += weights.passed_pawn[relative as usize];
+            }
+        }
+    }
+
+    score
+}
+
+// Pawns in front of the king and open files beside it
+fn king_shelter(weights: &Weights, board: &ChessBoard, pawns: &Pawns, colour: Colour) -> Tapered {
+    let king = match board.find_king(colour) {
+        Some(king) => king,
+        None => return Tapered::default(),
+    };
+    let (king_file, _) = ChessBoard::square_to_file_and_rank(king);
+    let king_rank = relative_rank(king, colour);
+    let mut score = Tapered::default();
+
+    for file in king_file as i8 - 1..=king_file as i8 + 1 {
+        if !(0..TOTAL_FILES as i8).contains(&file) {
+            continue;
+        }
+        let own = pawns.on_file(colour, file);
+        if own.is_empty() {
+            score += weights.open_file_by_king;
+        }
+        let shield = own
+            .iter()
+            .filter(|&&rank| {
+                let relative = match colour {
+                    Colour::White => rank,
+                    Colour::Black => 7 - rank,
+                };
+                relative > king_rank && relative <= king_rank + 2
+            })
+            .count();
+        score += weights.pawn_shield.times(shield as i32);
+    }
+
+    score
+}
+
+pub fn evaluate(board: &ChessBoard) -> Evaluation {
+    evaluate_with(board, &WEIGHTS)
+}
+
+pub fn evaluate_with(board: &ChessBoard, weights: &Weights) -> Evaluation {
+    let mut material_score = Tapered::default();
+    let mut piece_squares = Tapered::default();
+    let mut mobility = Tapered::default();
+    let mut king_attacks = Tapered::default();
+    let mut bishops = [0; 2];
+    let mut phase = 0;
+    let mut pawns = Pawns {
+        ranks: Default::default(),
+    };
+
+    let kings = [
+        board.find_king(Colour::White),
+        board.find_king(Colour::Black),
+    ];
+
+    for square in 0..TOTAL_SQUARES {
+        let piece = match board.get_piece(square) {
+            Some(piece) => piece,
+            None => continue,
+        };
+        let symbol = piece.get_symbol();
+        let colour = piece.colour();
+        let sign = if colour == Colour::White { 1 } else { -1 };
+
+        material_score += material(weights, symbol).times(sign);
+        piece_squares += piece_square(symbol, square, colour).times(sign);
+        phase += phase_weight(symbol);
+
+        match symbol.to_ascii_uppercase() {
+            'P' => {
+                let (file, rank) = ChessBoard::square_to_file_and_rank(square);
+                pawns.ranks[colour as usize][file as usize].push(rank);
+            }
+            'B' => bishops[colour as usize] += 1,
+            _ => {}
+        }
+
+        if let Some((weight, usual)) = mobility_weight(weights, symbol) {
+            let moves = piece.generate_moves(board, square);
+            mobility += weight.times(sign * (moves.len() as i32 - usual));
+
+            if let Some(enemy_king) = kings[colour.opposite() as usize] {
+                let attacks = moves
+                    .iter()
+                    .filter(|m| is_next_to(m.destination, enemy_king))
+                    .count() as i32;
+                // Counts against the side being attacked
+                king_attacks += weights
+                    .king_attack
+                    .times(-sign * attacks * king_attack_units(symbol));
+            }
+        }
+    }
+
+    let pawn_score = pawn_structure(weights, &pawns, Colour::White)
+        - pawn_structure(weights, &pawns, Colour::Black);
+    let king_safety = king_attacks + king_shelter(weights, board, &pawns, Colour::White)
+        - king_shelter(weights, board, &pawns, Colour::Black);
+
+    let mut bishop_pair = Tapered::default();
+    if bishops[Colour::White as usize] >= 2 {
+        bishop_pair += weights.bishop_pair;
+    }
+    if bishops[Colour::Black as usize] >= 2 {
+        bishop_pair = bishop_pair - weights.bishop_pair;
+    }
+
+    let phase = phase.min(TOTAL_PHASE);
+    Evaluation {
+        material: material_score.blend(phase),
+        piece_squares: piece_squares.blend(phase),
+        mobility: mobility.blend(phase),
+        pawn_structure: pawn_score.blend(phase),
+        king_safety: king_safety.blend(phase),
+        bishop_pair: bishop_pair.blend(phase),
+        phase,
+    }
+}
+
+// The score from the point of view of the side to move, as search wants it,
+// from the network when the board has one
+pub fn evaluate_relative(board: &ChessBoard) -> i32 {
+    if let Some(nnue) = board.nnue() {
+        return nnue.evaluate(board.side_to_move());
+    }
+    let total = evaluate(board).total();
+    match board.side_to_move() {
+        Colour::White => total,
+        Colour::Black => -total,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chess_board::START_FEN;
+
+    #[test]
+    fn test_start_position_is_level() {
+        let evaluation = evaluate(&ChessBoard::from_fen(START_FEN));
+        assert_eq!(evaluation.total(), 0);
+        assert_eq!(evaluation.phase, TOTAL_PHASE);
+    }
+
+    #[test]
+    fn test_symmetry() {
+        // The same position with the colours swapped scores the same for the
+        // other side
+        let white = evaluate(&ChessBoard::from_fen(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        ));
+        let black = evaluate(&ChessBoard::from_fen(
+            "r3k2r/pppbbppp/2n2q1P/1P2p3/3pn3/BN2PNP1/P1PPQPB1/R3K2R b KQkq - 0 1",
+        ));
+        assert_eq!(white.total(), -black.total());
+        assert_eq!(white.mobility, -black.mobility);
+        assert_eq!(white.king_safety, -black.king_safety);
+    }
+
+    #[test]
+    fn test_material_and_phase() {
+        let evaluation = evaluate(&ChessBoard::from_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1"));
+        assert_eq!(evaluation.material, WEIGHTS.pawn.endgame);
+        assert_eq!(evaluation.phase, 0);
+    }
+
+    #[test]
+    fn test_pawn_structure() {
+        // Doubled and isolated c pawns against a healthy pair
+        let evaluation = evaluate(&ChessBoard::from_fen(
+            "4k3/5pp1/8/8/8/2P5/2P5/4K3 w - - 0 1",
+        ));
+        assert!(evaluation.pawn_structure < 0);
+
+        // Nothing in the way of the a pawn
+        let evaluation = evaluate(&ChessBoard::from_fen("4k3/7p/8/P7/8/8/7P/4K3 w - - 0 1"));
+        assert!(evaluation.pawn_structure > 0);
+    }
+
+    #[test]
+    fn test_bishop_pair() {
+        let evaluation = evaluate(&ChessBoard::from_fen("2n1kn2/8/8/8/8/8/8/2B1KB2 w - - 0 1"));
+        assert_eq!(evaluation.phase, 4);
+        assert_eq!(evaluation.bishop_pair, WEIGHTS.bishop_pair.blend(4));
+    }
+
+    #[test]
+    fn test_king_safety() {
+        let sheltered = evaluate(&ChessBoard::from_fen(
+            "r5k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1",
+        ));
+        let exposed = evaluate(&ChessBoard::from_fen("r5k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1"));
+        assert!(exposed.king_safety < sheltered.king_safety);
+    }
+
+    #[test]
+    fn test_mobility() {
+        // A knight in the corner does less than one in the middle
+        let corner = evaluate(&ChessBoard::from_fen("4k3/8/8/8/8/8/8/N3K3 w - - 0 1"));
+        let centre = evaluate(&ChessBoard::from_fen("4k3/8/8/8/3N4/8/8/4K3 w - - 0 1"));
+        assert!(centre.mobility > corner.mobility);
+    }
+}
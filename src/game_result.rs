@@ -0,0 +1,166 @@
+use crate::{chess_board::ChessBoard, piece::Colour};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Termination {
+    Timeout,
+    TimeoutVsInsufficientMaterial,
+    Checkmate,
+    Stalemate,
+    Repetition,
+    FiftyMoves,
+    InsufficientMaterial,
+    // An engine crashed or played an illegal move
+    Forfeit,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct GameResult {
+    pub winner: Option<Colour>,
+    pub termination: Termination,
+}
+
+impl GameResult {
+    // A flag only loses if the opponent still has something to mate with.
+    pub fn on_timeout(flagged: Colour, chessboard: &ChessBoard) -> GameResult {
+        if chessboard.has_only_king(flagged.opposite()) {
+            GameResult {
+                winner: None,
+                termination: Termination::TimeoutVsInsufficientMaterial,
+            }
+        } else {
+            GameResult {
+                winner: Some(flagged.opposite()),
+                termination: Termination::Timeout,
+            }
+        }
+    }
+
+    // Checkmate, stalemate and the draws that end a game by themselves.
+    // `hashes` are of every position so far, this one included.
+    pub fn from_position(chessboard: &mut ChessBoard, hashes: &[u64]) -> Option<GameResult> {
+        let side_to_move = chessboard.side_to_move();
+        let (winner, termination) = if chessboard.legal_moves().is_empty() {
+            if chessboard.is_in_check(side_to_move) {
+                (Some(side_to_move.opposite()), Termination::Checkmate)
+            } else {
+                (None, Termination::Stalemate)
+            }
+        } else if hashes
+            .iter()
+            .filter(|&&hash| hash == chessboard.hash())
+            .count()
+            >= 3
+        {
+            (None, Termination::Repetition)
+        } else if chessboard.halfmove_clock() >= 100 {
+            (None, Termination::FiftyMoves)
+        } else if chessboard.has_insufficient_material() {
+            (None, Termination::InsufficientMaterial)
+        } else {
+            return None;
+        };
+        Some(GameResult {
+            winner,
+            termination,
+        })
+    }
+
+    pub fn forfeit(loser: Colour) -> GameResult {
+        GameResult {
+            winner: Some(loser.opposite()),
+            termination: Termination::Forfeit,
+        }
+    }
+
+    pub fn to_pgn(self) -> &'static str {
+        match self.winner {
+            Some(Colour::White) => "1-0",
+            Some(Colour::Black) => "0-1",
+            None => "1/2-1/2",
+        }
+    }
+
+    pub fn describe(self) -> String {
+        let winner = match self.winner {
+            Some(Colour::White) => "White wins",
+            Some(Colour::Black) => "Black wins",
+            None => "Draw",
+        };
+        let reason = match self.termination {
+            Termination::Timeout => "on time",
+            Termination::TimeoutVsInsufficientMaterial => "by timeout vs insufficient material",
+            Termination::Checkmate => "by checkmate",
+            Termination::Stalemate => "by stalemate",
+            Termination::Repetition => "by threefold repetition",
+            Termination::FiftyMoves => "by the fifty move rule",
+            Termination::InsufficientMaterial => "by insufficient material",
+            Termination::Forfeit => "by forfeit",
+        };
+        format!("{} {} ({})", winner, reason, self.to_pgn())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_loss_on_time() {
+        let chess_board = ChessBoard::from_fen("4k3/4p3/8/8/8/8/8/4K3 w - - 0 1");
+        let result = GameResult::on_timeout(Colour::White, &chess_board);
+        assert_eq!(result.winner, Some(Colour::Black));
+        assert_eq!(result.to_pgn(), "0-1");
+    }
+
+    #[test]
+    fn test_timeout_against_bare_king_is_a_draw() {
+        let chess_board = ChessBoard::from_fen("4k3/8/8/8/8/8/8/Q3K3 w - - 0 1");
+        let result = GameResult::on_timeout(Colour::White, &chess_board);
+        assert_eq!(result.winner, None);
+        assert_eq!(
+            result.termination,
+            Termination::TimeoutVsInsufficientMaterial
+        );
+        assert_eq!(result.to_pgn(), "1/2-1/2");
+    }
+
+    #[test]
+    fn test_from_position() {
+        let result = |fen: &str| {
+            let mut chess_board = ChessBoard::from_fen(fen);
+            let hashes = [chess_board.hash()];
+            GameResult::from_position(&mut chess_board, &hashes).map(|result| result.describe())
+        };
+        assert_eq!(
+            result("R5k1/5ppp/8/8/8/8/8/4K3 b - - 0 1"),
+            Some("White wins by checkmate (1-0)".to_string())
+        );
+        assert_eq!(
+            result("k7/8/1Q6/8/8/8/8/4K3 b - - 0 1"),
+            Some("Draw by stalemate (1/2-1/2)".to_string())
+        );
+        assert_eq!(
+            result("4k3/8/8/8/8/8/4P3/4K3 w - - 100 80"),
+            Some("Draw by the fifty move rule (1/2-1/2)".to_string())
+        );
+        assert_eq!(
+            result("4k3/8/8/8/8/8/4N3/4K3 w - - 0 1"),
+            Some("Draw by insufficient material (1/2-1/2)".to_string())
+        );
+        assert_eq!(result("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1"), None);
+
+        // Back to the start position twice
+        let mut chess_board = ChessBoard::from_fen(crate::chess_board::START_FEN);
+        let mut hashes = vec![chess_board.hash()];
+        for notation in [
+            "g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6", "f3g1", "f6g8",
+        ] {
+            assert_eq!(GameResult::from_position(&mut chess_board, &hashes), None);
+            let chess_move = chess_board.parse_move(notation).unwrap();
+            chess_board.make_move(chess_move);
+            hashes.push(chess_board.hash());
+        }
+        let result = GameResult::from_position(&mut chess_board, &hashes).unwrap();
+        assert_eq!(result.termination, Termination::Repetition);
+    }
+}
@@ -1,25 +1,93 @@
+use crate::analysis::Analysis;
 use crate::app::App;
-use crate::chess_board::ChessBoard;
+use crate::book::Book;
+use crate::chess_board::{ChessBoard, START_FEN};
+use crate::chess_move::ChessMove;
+use crate::clock::{ChessClock, TimeControl};
+use crate::config::Config;
+use crate::datagen::SelfPlay;
+use crate::engine::{Engine, Report};
+use crate::engine_match::{EngineSpec, Match, Sprt};
+use crate::nnue::Network;
+use crate::piece::Colour;
+use crate::random::Random;
+use crate::search::{SearchInfo, SearchLimits, MAX_MULTI_PV};
+use crate::skill::{Skill, MAX_ELO, MAX_SKILL_LEVEL, MIN_ELO};
+use crate::syzygy::Tablebase;
+use crate::theme::{Theme, THEME_NAMES};
 
+mod analysis;
+mod annotate;
 mod app;
+mod bench;
+mod book;
 mod chess_board;
 mod chess_move;
+mod clock;
+mod config;
+mod datagen;
+mod engine;
+mod engine_match;
+mod eval;
+mod game_result;
+mod material;
+mod move_order;
+mod nnue;
+mod pgn;
 mod piece;
+mod random;
+mod san;
+mod search;
+mod see;
+mod skill;
+mod syzygy;
+mod theme;
+mod time_manager;
+mod tt;
+mod tune;
+mod uci;
 mod ui;
+mod weights;
+mod xboard;
+mod zobrist;
 
 use crossterm::{
     event::{self, Event as CEvent, KeyCode},
     terminal::{disable_raw_mode, enable_raw_mode},
 };
 use std::io::stdout;
-use std::sync::mpsc;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc};
 use std::thread;
 use std::time::{Duration, Instant};
 use tui::{backend::CrosstermBackend, Terminal};
 
 enum Event<I> {
     Input(I),
+    Resize,
     Tick,
+    // The engine's move, tagged with the search it came from
+    EngineMove(u64, Option<ChessMove>),
+    // Progress analysing the position, tagged the same way
+    EngineInfo(u64, SearchInfo),
+    // How annotating the game is going, and then how it went
+    Annotating(String),
+}
+
+// Where games annotated in the terminal are written
+const ANNOTATED_PGN: &str = "annotated.pgn";
+
+fn parse_arg<T: std::str::FromStr>(
+    matches: &clap::ArgMatches,
+    name: &str,
+) -> Result<Option<T>, String> {
+    match matches.value_of(name) {
+        Some(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|_| format!("bad {} {}", name, value)),
+        None => Ok(None),
+    }
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -32,18 +100,591 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .help("Start game with fen string")
                 .takes_value(true),
         )
+        .arg(
+            clap::Arg::with_name("theme")
+                .short("t")
+                .long("theme")
+                .help("Colour theme for the board")
+                .possible_values(&THEME_NAMES)
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("ascii")
+                .long("ascii")
+                .help("Draw pieces as KQRBNP letters instead of chess glyphs"),
+        )
+        .arg(
+            clap::Arg::with_name("large-board")
+                .long("large-board")
+                .help("Draw a bigger board when the terminal has room"),
+        )
+        .arg(
+            clap::Arg::with_name("tc")
+                .long("tc")
+                .help("Play with clocks, e.g. 5+3 for 5 minutes plus a 3 second increment, or 5d3 for a 3 second Bronstein delay")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("vs-engine")
+                .long("vs-engine")
+                .help("Play against the engine, taking this colour")
+                .possible_values(&["white", "black"])
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("analyse")
+                .long("analyse")
+                .help("Have the engine analyse the position on the board as moves are played")
+                .conflicts_with("vs-engine"),
+        )
+        .arg(
+            clap::Arg::with_name("multipv")
+                .long("multipv")
+                .help("How many of the best lines to show when analysing (default 3)")
+                .requires("analyse")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("engine-depth")
+                .long("engine-depth")
+                .help("Limit how many plies deep the engine looks")
+                .requires("vs-engine")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("engine-nodes")
+                .long("engine-nodes")
+                .help("Limit how many positions the engine looks at per move")
+                .requires("vs-engine")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("engine-time")
+                .long("engine-time")
+                .help("Milliseconds the engine thinks per move (default 1000 without --tc)")
+                .requires("vs-engine")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("skill")
+                .long("skill")
+                .help("Engine skill level from 0 to 20 (default 20, full strength)")
+                .requires("vs-engine")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("elo")
+                .long("elo")
+                .help("Weaken the engine to about this Elo, from 1350 to 2850")
+                .requires("vs-engine")
+                .conflicts_with("skill")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("book")
+                .long("book")
+                .help("Polyglot opening book to show moves from and for the engine to play")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("book-depth")
+                .long("book-depth")
+                .help("Last move number the engine plays from the book (default 20)")
+                .requires("book")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("syzygy-path")
+                .long("syzygy-path")
+                .help("Directories of Syzygy endgame tables, separated like PATH")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("uci")
+                .long("uci")
+                .help("Run as a UCI engine on stdin/stdout instead of showing the board"),
+        )
+        .arg(
+            clap::Arg::with_name("xboard")
+                .long("xboard")
+                .conflicts_with("uci")
+                .help("Run as an xboard/WinBoard engine on stdin/stdout"),
+        )
+        .arg(
+            clap::Arg::with_name("config")
+                .short("c")
+                .long("config")
+                .help("Read settings from this file (default ~/.config/chess-rust/config)")
+                .takes_value(true),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("bench")
+                .about("Search a fixed set of positions and report the node count and speed")
+                .arg(
+                    clap::Arg::with_name("depth")
+                        .help("Depth to search each position to (default 5)")
+                        .index(1),
+                )
+                .arg(
+                    clap::Arg::with_name("threads")
+                        .long("threads")
+                        .help("Search with this many threads, for comparing time to depth (default 1)")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("book")
+                .about("Opening book tools")
+                .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    clap::SubCommand::with_name("build")
+                        .about("Build a Polyglot book from PGN files")
+                        .arg(
+                            clap::Arg::with_name("pgn")
+                                .help("PGN files to read games from")
+                                .required(true)
+                                .multiple(true),
+                        )
+                        .arg(
+                            clap::Arg::with_name("output")
+                                .short("o")
+                                .long("output")
+                                .help("Book file to write")
+                                .required(true)
+                                .takes_value(true),
+                        )
+                        .arg(
+                            clap::Arg::with_name("min-games")
+                                .long("min-games")
+                                .help("Leave out moves played in fewer games than this (default 1)")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            clap::Arg::with_name("depth")
+                                .long("depth")
+                                .help("Plies into each game to take moves from (default 40)")
+                                .takes_value(true),
+                        ),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("annotate")
+                .about("Mark the inaccuracies, mistakes and blunders in PGN games")
+                .arg(
+                    clap::Arg::with_name("pgn")
+                        .help("PGN file of games to annotate")
+                        .required(true),
+                )
+                .arg(
+                    clap::Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .help("File to write the annotated games to (default stdout)")
+                        .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::with_name("depth")
+                        .long("depth")
+                        .help("Depth to search each position to (default 10)")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("match")
+                .about("Play two engines against each other and compare their strength")
+                .arg(
+                    clap::Arg::with_name("first")
+                        .long("first")
+                        .help("The first engine, e.g. \"Hash=64\" or \"cmd=./old-build,name=old\" (default this engine)")
+                        .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::with_name("second")
+                        .long("second")
+                        .help("The second engine, set up the same way")
+                        .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::with_name("openings")
+                        .long("openings")
+                        .help("FEN, EPD or PGN file of starting positions, each played with both colours")
+                        .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::with_name("games")
+                        .short("n")
+                        .long("games")
+                        .help("Number of games (default 100)")
+                        .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::with_name("concurrency")
+                        .short("j")
+                        .long("concurrency")
+                        .help("Games to play at once (default 1)")
+                        .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::with_name("tc")
+                        .long("tc")
+                        .help("Time control as minutes+increment, e.g. 0.5+0.1")
+                        .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::with_name("movetime")
+                        .long("movetime")
+                        .help("Milliseconds per move, without a time control (default 100)")
+                        .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::with_name("depth")
+                        .long("depth")
+                        .help("Depth to search each move to, without a time control")
+                        .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::with_name("nodes")
+                        .long("nodes")
+                        .help("Nodes to search each move, without a time control")
+                        .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::with_name("sprt")
+                        .long("sprt")
+                        .help("Stop once an SPRT decides between elo0 and elo1"),
+                )
+                .arg(
+                    clap::Arg::with_name("elo0")
+                        .long("elo0")
+                        .help("Elo difference for the SPRT's null hypothesis (default 0)")
+                        .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::with_name("elo1")
+                        .long("elo1")
+                        .help("Elo difference for the SPRT's alternative hypothesis (default 5)")
+                        .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::with_name("alpha")
+                        .long("alpha")
+                        .help("SPRT false positive rate (default 0.05)")
+                        .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::with_name("beta")
+                        .long("beta")
+                        .help("SPRT false negative rate (default 0.05)")
+                        .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::with_name("pgn")
+                        .long("pgn")
+                        .help("File to write the games to")
+                        .takes_value(true),
+                )
+        )
+        .subcommand(
+            clap::SubCommand::with_name("datagen")
+                .about("Write positions and scores from self-play games, for training a network")
+                .arg(
+                    clap::Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .help("File to write the positions to")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    clap::Arg::with_name("games")
+                        .short("n")
+                        .long("games")
+                        .help("Number of games to play (default 100)")
+                        .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::with_name("concurrency")
+                        .short("j")
+                        .long("concurrency")
+                        .help("Games to play at once (default 1)")
+                        .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::with_name("depth")
+                        .long("depth")
+                        .help("Depth to search each move to (default 6)")
+                        .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::with_name("nodes")
+                        .long("nodes")
+                        .help("Nodes to search each move")
+                        .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::with_name("random-plies")
+                        .long("random-plies")
+                        .help("Random moves to start each game with (default 8)")
+                        .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::with_name("eval-file")
+                        .long("eval-file")
+                        .help("Network to play with instead of the handcrafted evaluation")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("tune")
+                .about("Tune the evaluation weights on positions labelled with game results")
+                .arg(
+                    clap::Arg::with_name("positions")
+                        .help("EPD or FEN file of positions with results, or a PGN file of games")
+                        .required(true),
+                )
+                .arg(
+                    clap::Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .help("File to write the tuned weights.rs to (default stdout)")
+                        .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::with_name("iterations")
+                        .long("iterations")
+                        .help("Most passes over the weights to make (default 100)")
+                        .takes_value(true),
+                ),
+        )
         .get_matches();
 
-    let fen = matches
-        .value_of("fen")
-        .unwrap_or("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+    if let Some(matches) = matches.subcommand_matches("bench") {
+        let depth = match matches.value_of("depth") {
+            Some(depth) => depth
+                .parse()
+                .map_err(|_| format!("bad bench depth {}", depth))?,
+            None => bench::DEFAULT_DEPTH,
+        };
+        let threads = match matches.value_of("threads") {
+            Some(threads) => threads
+                .parse()
+                .ok()
+                .filter(|threads| (1..=search::MAX_THREADS).contains(threads))
+                .ok_or_else(|| format!("bad thread count {}", threads))?,
+            None => 1,
+        };
+        bench::run(depth, threads);
+        return Ok(());
+    }
+
+    if let Some(matches) = matches
+        .subcommand_matches("book")
+        .and_then(|matches| matches.subcommand_matches("build"))
+    {
+        let paths: Vec<&str> = matches.values_of("pgn").into_iter().flatten().collect();
+        let output = matches.value_of("output").unwrap_or_default();
+        let min_games = parse_arg(matches, "min-games")?.unwrap_or(1);
+        let depth = parse_arg(matches, "depth")?.unwrap_or(2 * book::DEFAULT_BOOK_DEPTH as usize);
+        book::build(&paths, output.as_ref(), min_games, depth)?;
+        return Ok(());
+    }
+
+    if let Some(matches) = matches.subcommand_matches("annotate") {
+        let path = matches.value_of("pgn").unwrap_or_default();
+        let output = matches.value_of("output").map(Path::new);
+        let depth = parse_arg(matches, "depth")?.unwrap_or(annotate::DEFAULT_DEPTH);
+        annotate::run(path, output, depth)?;
+        return Ok(());
+    }
+
+    if let Some(matches) = matches.subcommand_matches("match") {
+        let time_control = match matches.value_of("tc") {
+            Some(tc) => {
+                Some(TimeControl::parse(tc).ok_or_else(|| format!("bad time control {}", tc))?)
+            }
+            None => None,
+        };
+        let mut limits = SearchLimits {
+            depth: parse_arg(matches, "depth")?,
+            nodes: parse_arg(matches, "nodes")?,
+            movetime: parse_arg(matches, "movetime")?.map(Duration::from_millis),
+            ..SearchLimits::default()
+        };
+        if limits == SearchLimits::default() {
+            limits.movetime = Some(Duration::from_millis(engine_match::DEFAULT_MOVETIME_MS));
+        }
+        let sprt = if matches.is_present("sprt") {
+            Some(Sprt {
+                elo0: parse_arg(matches, "elo0")?.unwrap_or(0.0),
+                elo1: parse_arg(matches, "elo1")?.unwrap_or(5.0),
+                alpha: parse_arg(matches, "alpha")?.unwrap_or(0.05),
+                beta: parse_arg(matches, "beta")?.unwrap_or(0.05),
+            })
+        } else {
+            None
+        };
+        let engine_match = Match {
+            first: EngineSpec::parse(matches.value_of("first").unwrap_or_default())?,
+            second: EngineSpec::parse(matches.value_of("second").unwrap_or_default())?,
+            openings: match matches.value_of("openings") {
+                Some(path) => engine_match::load_openings(path.as_ref())?,
+                None => vec![START_FEN.to_string()],
+            },
+            games: parse_arg(matches, "games")?.unwrap_or(engine_match::DEFAULT_GAMES),
+            concurrency: parse_arg(matches, "concurrency")?.unwrap_or(1),
+            time_control,
+            limits,
+            sprt,
+            pgn: matches.value_of("pgn").map(PathBuf::from),
+        };
+        engine_match.run()?;
+        return Ok(());
+    }
+
+    if let Some(matches) = matches.subcommand_matches("datagen") {
+        let mut limits = SearchLimits {
+            depth: parse_arg(matches, "depth")?,
+            nodes: parse_arg(matches, "nodes")?,
+            ..SearchLimits::default()
+        };
+        if limits == SearchLimits::default() {
+            limits.depth = Some(datagen::DEFAULT_DEPTH);
+        }
+        let network = match matches.value_of("eval-file") {
+            Some(path) => Some(Arc::new(Network::load(path.as_ref())?)),
+            None => None,
+        };
+        let self_play = SelfPlay {
+            games: parse_arg(matches, "games")?.unwrap_or(datagen::DEFAULT_GAMES),
+            concurrency: parse_arg(matches, "concurrency")?.unwrap_or(1),
+            limits,
+            random_plies: parse_arg(matches, "random-plies")?
+                .unwrap_or(datagen::DEFAULT_RANDOM_PLIES),
+            network,
+            seed: Random::from_time().next_u64(),
+            output: PathBuf::from(matches.value_of("output").unwrap_or_default()),
+        };
+        let positions = self_play.run()?;
+        eprintln!("Wrote {} positions", positions);
+        return Ok(());
+    }
+
+    if let Some(matches) = matches.subcommand_matches("tune") {
+        let path = matches.value_of("positions").unwrap_or_default();
+        let output = matches.value_of("output").map(Path::new);
+        let passes = parse_arg(matches, "iterations")?.unwrap_or(tune::DEFAULT_PASSES);
+        tune::run(path, output, passes)?;
+        return Ok(());
+    }
+
+    if matches.is_present("uci") {
+        uci::run()?;
+        return Ok(());
+    }
+
+    if matches.is_present("xboard") {
+        xboard::run()?;
+        return Ok(());
+    }
+
+    let fen = matches.value_of("fen").unwrap_or(START_FEN);
+    ChessBoard::validate_fen(fen).map_err(|error| format!("bad FEN {}: {}", fen, error))?;
+
+    let config = match matches.value_of("config") {
+        Some(path) => Config::load(path.as_ref())?,
+        None => match Config::default_path() {
+            Some(path) if path.exists() => Config::load(&path)?,
+            _ => Config::default(),
+        },
+    };
+
+    let theme_name = matches
+        .value_of("theme")
+        .map(str::to_string)
+        .or(config.theme)
+        .unwrap_or_else(|| Theme::default().name.to_string());
+    let theme = Theme::by_name(&theme_name)
+        .ok_or_else(|| format!("unknown theme {}, try one of {:?}", theme_name, THEME_NAMES))?;
+    let ascii_pieces = matches.is_present("ascii") || config.ascii.unwrap_or(false);
+
+    let time_control = match matches.value_of("tc") {
+        Some(tc) => Some(TimeControl::parse(tc).ok_or_else(|| format!("bad time control {}", tc))?),
+        None => None,
+    };
+
+    // Without any other limit the engine plays on the clock, or failing that
+    // takes a second a move
+    let mut engine_limits = SearchLimits {
+        depth: parse_arg(&matches, "engine-depth")?,
+        nodes: parse_arg(&matches, "engine-nodes")?,
+        movetime: parse_arg(&matches, "engine-time")?.map(Duration::from_millis),
+        ..SearchLimits::default()
+    };
+    if engine_limits == SearchLimits::default() && time_control.is_none() {
+        engine_limits.movetime = Some(Duration::from_secs(1));
+    }
+
+    let level = parse_arg(&matches, "skill")?.or(config.skill);
+    let elo = parse_arg(&matches, "elo")?;
+    if level.is_some_and(|level| level > MAX_SKILL_LEVEL) {
+        return Err(format!("skill must be 0 to {}", MAX_SKILL_LEVEL).into());
+    }
+    if elo.is_some_and(|elo| !(MIN_ELO..=MAX_ELO).contains(&elo)) {
+        return Err(format!("elo must be {} to {}", MIN_ELO, MAX_ELO).into());
+    }
+    let skill = Skill {
+        level: level.unwrap_or(MAX_SKILL_LEVEL),
+        limit_strength: elo.is_some(),
+        elo: elo.unwrap_or(MAX_ELO),
+    };
 
-    start_ui(fen)
+    let book_depth = parse_arg(&matches, "book-depth")?.unwrap_or(book::DEFAULT_BOOK_DEPTH);
+    if book_depth > book::MAX_BOOK_DEPTH {
+        return Err(format!("book depth must be 0 to {}", book::MAX_BOOK_DEPTH).into());
+    }
+
+    let multi_pv = parse_arg(&matches, "multipv")?.unwrap_or(3);
+    if !(1..=MAX_MULTI_PV).contains(&multi_pv) {
+        return Err(format!("multipv must be 1 to {}", MAX_MULTI_PV).into());
+    }
+
+    let mut app = App::new();
+    app.book = match matches.value_of("book") {
+        Some(path) => Some(Arc::new(Book::load(path.as_ref())?)),
+        None => None,
+    };
+    app.tablebase = match matches.value_of("syzygy-path") {
+        Some(paths) => Some(Arc::new(Tablebase::open(paths)?)),
+        None => None,
+    };
+    app.theme = theme;
+    app.ascii_pieces = ascii_pieces;
+    app.large_board = matches.is_present("large-board");
+    app.analysing = matches.is_present("analyse");
+    app.multi_pv = multi_pv;
+    app.engine_colour = match matches.value_of("vs-engine") {
+        Some("white") => Some(Colour::Black),
+        Some(_) => Some(Colour::White),
+        None => None,
+    };
+
+    start_ui(fen, app, time_control, engine_limits, skill, book_depth)
 }
 
-pub fn start_ui(fen: &str) -> Result<(), Box<dyn std::error::Error>> {
+pub fn start_ui(
+    fen: &str,
+    mut app: App,
+    time_control: Option<TimeControl>,
+    engine_limits: SearchLimits,
+    skill: Skill,
+    book_depth: u32,
+) -> Result<(), Box<dyn std::error::Error>> {
     let mut chessboard = chess_board::ChessBoard::from_fen(fen);
-    let mut app = App::new();
+    app.clock = time_control.map(|time_control| {
+        let mut clock = ChessClock::new(time_control);
+        clock.start(chessboard.side_to_move(), Instant::now());
+        clock
+    });
 
     // Configure Crossterm backend for tui
     let stdout = stdout();
@@ -53,6 +694,7 @@ pub fn start_ui(fen: &str) -> Result<(), Box<dyn std::error::Error>> {
     terminal.clear()?;
 
     let (tx, rx) = mpsc::channel();
+    let engine_tx = tx.clone();
     let tick_rate = Duration::from_millis(200);
     thread::spawn(move || {
         let mut last_tick = Instant::now();
@@ -62,24 +704,128 @@ pub fn start_ui(fen: &str) -> Result<(), Box<dyn std::error::Error>> {
                 .unwrap_or_else(|| Duration::from_secs(0));
 
             if event::poll(timeout).expect("poll works") {
-                if let CEvent::Key(key) = event::read().expect("can read events") {
-                    tx.send(Event::Input(key)).expect("can send events");
+                match event::read().expect("can read events") {
+                    CEvent::Key(key) => tx.send(Event::Input(key)).expect("can send events"),
+                    // Redraw straight away rather than waiting for the next tick
+                    CEvent::Resize(_, _) => tx.send(Event::Resize).expect("can send events"),
+                    _ => {}
                 }
             }
 
-            if last_tick.elapsed() >= tick_rate {
-                if tx.send(Event::Tick).is_ok() {
-                    last_tick = Instant::now();
-                }
+            if last_tick.elapsed() >= tick_rate && tx.send(Event::Tick).is_ok() {
+                last_tick = Instant::now();
             }
         }
     });
 
+    let mut engine = app.engine_colour.is_some().then(|| {
+        let mut engine = Engine::new();
+        engine.set_skill(skill);
+        engine.set_book(app.book.clone());
+        engine.set_book_depth(book_depth);
+        engine.set_tablebase(app.tablebase.clone());
+        engine
+    });
+    // Analysis gets an engine of its own, so it's at full strength whatever
+    // the opponent's skill level
+    let mut analysis_engine = app.analysing.then(|| {
+        let mut engine = Engine::new();
+        engine.set_tablebase(app.tablebase.clone());
+        engine.set_multi_pv(app.multi_pv);
+        engine
+    });
+    let mut search_id = 0;
+    let mut analysis_id = 0;
+    // The moves that led to the position being analysed
+    let mut analysed: Option<Vec<String>> = None;
+
     loop {
+        // Analyse afresh whenever a move is played or taken back
+        if let Some(engine) = analysis_engine.as_mut() {
+            let moves = app.moves_played();
+            if analysed.as_ref() != Some(&moves) {
+                engine.stop();
+                app.analysis = Analysis::default();
+                analysis_id += 1;
+                let played: Vec<&str> = moves.iter().map(String::as_str).collect();
+                if chessboard.legal_moves().is_empty() {
+                    // Nothing to analyse
+                } else if let Err(error) = engine.set_position(fen, &played) {
+                    app.engine_error = Some(error);
+                } else {
+                    let id = analysis_id;
+                    let tx = engine_tx.clone();
+                    let limits = SearchLimits {
+                        infinite: true,
+                        ..SearchLimits::default()
+                    };
+                    engine.go(limits, move |report| {
+                        if let Report::Info(info) = report {
+                            let _ = tx.send(Event::EngineInfo(id, info));
+                        }
+                    });
+                }
+                analysed = Some(moves);
+            }
+        }
+
+        // Set the engine thinking in the background when it's its move
+        if let Some(engine) = engine.as_mut() {
+            if !app.thinking
+                && app.is_engine_turn(&chessboard)
+                && !chessboard.legal_moves().is_empty()
+            {
+                let moves = app.moves_played();
+                let moves: Vec<&str> = moves.iter().map(String::as_str).collect();
+                if let Err(error) = engine.set_position(fen, &moves) {
+                    // Stop playing rather than quit with the terminal as it is
+                    app.engine_error = Some(error);
+                    app.engine_colour = None;
+                } else {
+                    let limits = match &app.clock {
+                        Some(clock) => SearchLimits {
+                            depth: engine_limits.depth,
+                            nodes: engine_limits.nodes,
+                            movetime: engine_limits.movetime,
+                            ..clock.search_limits(Instant::now())
+                        },
+                        None => engine_limits,
+                    };
+                    search_id += 1;
+                    let id = search_id;
+                    let tx = engine_tx.clone();
+                    engine.go(limits, move |report| {
+                        if let Report::BestMove(best_move) = report {
+                            // The board may be gone if we're quitting
+                            let _ = tx.send(Event::EngineMove(id, best_move));
+                        }
+                    });
+                    app.thinking = true;
+                }
+            }
+        }
+
         terminal.draw(|rect| ui::draw(rect, &app, &chessboard))?;
 
-        match rx.recv()? {
-            Event::Input(event) => match (event.modifiers, event.code) {
+        let event = rx.recv()?;
+        app.check_clock(&chessboard, Instant::now());
+
+        if let Event::Annotating(status) = event {
+            app.annotation_status = Some(status);
+        } else if let Event::EngineInfo(id, info) = event {
+            if id == analysis_id {
+                app.analysis.update(&info);
+            }
+        } else if let Event::EngineMove(id, best_move) = event {
+            // Moves from a search that was called off are stale
+            if app.thinking && id == search_id {
+                app.thinking = false;
+                if let Some(best_move) = best_move.filter(|_| app.is_engine_turn(&chessboard)) {
+                    app.play_move(&mut chessboard, best_move, Instant::now());
+                }
+            }
+        } else if let Event::Input(event) = event {
+            match (event.modifiers, event.code) {
                 (event::KeyModifiers::CONTROL, KeyCode::Char('c')) => {
                     // disable_raw_mode()?;
                     // terminal.show_cursor()?;
@@ -90,25 +836,88 @@ pub fn start_ui(fen: &str) -> Result<(), Box<dyn std::error::Error>> {
                 }
                 (event::KeyModifiers::NONE, KeyCode::Enter) => {
                     let square_notation = app.ui_buffer.clone();
+                    let square = ChessBoard::square_from_notation(&square_notation);
+                    let chosen_move = square.and_then(|square| app.find_available_move(square));
                     app.clear_input();
-                    chessboard.reset_highlights();
 
-                    if let Some(square) = ChessBoard::square_from_notation(&square_notation) {
-                        let moves = chessboard.generate_moves(square);
+                    if square_notation == "annotate" {
+                        let moves: Vec<ChessMove> = app
+                            .history()
+                            .iter()
+                            .map(|record| record.chess_move)
+                            .collect();
+                        let game = annotate::game_record(fen, &moves, app.result);
+                        let tablebase = app.tablebase.clone();
+                        let tx = engine_tx.clone();
+                        thread::spawn(move || {
+                            let output = Path::new(ANNOTATED_PGN);
+                            let saved = annotate::save(
+                                &game,
+                                output,
+                                tablebase.as_deref(),
+                                |ply, plies| {
+                                    let status = format!("Annotating... {}/{} moves", ply, plies);
+                                    let _ = tx.send(Event::Annotating(status));
+                                },
+                            );
+                            let _ = tx.send(Event::Annotating(match saved {
+                                Ok(summary) => {
+                                    format!(
+                                        "Annotated game written to {}\n{}",
+                                        ANNOTATED_PGN, summary
+                                    )
+                                }
+                                Err(error) => format!("Couldn't annotate the game: {}", error),
+                            }));
+                        });
+                        app.annotation_status = Some("Annotating...".to_string());
+                    } else if square_notation == "undo" {
+                        if let Some(engine) = engine.as_mut() {
+                            engine.stop();
+                            app.thinking = false;
+                        }
+                        // Against the engine, take back its reply as well
+                        let undone = app.undo_move(&mut chessboard);
+                        while undone
+                            && app.is_engine_turn(&chessboard)
+                            && app.undo_move(&mut chessboard)
+                        {}
+                        if undone {
+                            if let Some(clock) = app.clock.as_mut() {
+                                let now = Instant::now();
+                                clock.stop(now);
+                                clock.start(chessboard.side_to_move(), now);
+                            }
+                        }
+                    } else if app.result.is_some() {
+                        // Game over, leave the board as it is
+                    } else if app.is_engine_turn(&chessboard) {
+                        // Wait for the engine
+                    } else if let Some(chosen_move) = chosen_move {
+                        // Pawns reaching the back rank become queens
+                        let chosen_move = ChessMove {
+                            promotion: chosen_move.promotion.map(|_| 'Q'),
+                            ..chosen_move
+                        };
+                        app.play_move(&mut chessboard, chosen_move, Instant::now());
+                    } else if let Some(square) = square {
                         app.set_selected_square(square);
 
-                        moves.iter().for_each(|m| {
-                            chessboard.highlight_square(m.destination, true);
-                            app.add_available_move(m);
-                        });
+                        let is_turn = match chessboard.get_piece(square) {
+                            Some(piece) => piece.colour() == chessboard.side_to_move(),
+                            None => false,
+                        };
+                        if is_turn {
+                            let moves = chessboard.generate_legal_moves(square);
+                            moves.iter().for_each(|m| app.add_available_move(m));
+                        }
                     }
                 }
                 (event::KeyModifiers::NONE, KeyCode::Char(c)) => {
                     app.ui_buffer.push(c);
                 }
                 _ => {}
-            },
-            _ => {}
+            }
         }
     }
 
@@ -0,0 +1,96 @@
+use crate::{
+    chess_board::MoveRecord,
+    piece::{piece_value, Colour},
+};
+
+#[derive(Default, Debug, PartialEq, Eq)]
+pub struct Captures {
+    // Symbols of the pieces each side has taken
+    pub by_white: Vec<char>,
+    pub by_black: Vec<char>,
+    // Material gained by white minus material gained by black, in pawns
+    pub balance: i32,
+}
+
+// Works from the moves played rather than the board, so undoing a move takes
+// its capture back off and a promoted pawn counts as the piece it became.
+pub fn captures_from_history(history: &[MoveRecord]) -> Captures {
+    let mut captures = Captures::default();
+
+    for record in history {
+        let mut gain = 0;
+
+        if let Some(captured) = &record.captured {
+            gain += captured.value();
+            match record.mover {
+                Colour::White => captures.by_white.push(captured.get_symbol()),
+                Colour::Black => captures.by_black.push(captured.get_symbol()),
+            }
+        }
+
+        if let Some(promotion) = record.chess_move.promotion {
+            gain += piece_value(promotion) - piece_value('P');
+        }
+
+        match record.mover {
+            Colour::White => captures.balance += gain,
+            Colour::Black => captures.balance -= gain,
+        }
+    }
+
+    // Most valuable first, like a scoresheet
+    captures
+        .by_white
+        .sort_by_key(|symbol| -piece_value(*symbol));
+    captures
+        .by_black
+        .sort_by_key(|symbol| -piece_value(*symbol));
+
+    captures
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chess_board::ChessBoard, chess_move::ChessMove};
+
+    fn play(chess_board: &mut ChessBoard, from: &str, to: &str) -> MoveRecord {
+        chess_board.make_move(ChessMove::new(
+            ChessBoard::square_from_notation(from).unwrap(),
+            ChessBoard::square_from_notation(to).unwrap(),
+        ))
+    }
+
+    #[test]
+    fn test_captures() {
+        let mut chess_board = ChessBoard::from_fen("3r3k/8/8/8/8/2p5/1P6/3R3K w - - 0 1");
+        let history = vec![
+            play(&mut chess_board, "b2", "c3"),
+            play(&mut chess_board, "d8", "d1"),
+        ];
+
+        let captures = captures_from_history(&history);
+        assert_eq!(captures.by_white, vec!['p']);
+        assert_eq!(captures.by_black, vec!['R']);
+        assert_eq!(captures.balance, -4);
+    }
+
+    #[test]
+    fn test_promotion_and_undo() {
+        let mut chess_board = ChessBoard::from_fen("1n5k/P7/8/8/8/8/8/7K w - - 0 1");
+        let mut history = vec![chess_board.make_move(ChessMove {
+            promotion: Some('Q'),
+            ..ChessMove::new(
+                ChessBoard::square_from_notation("a7").unwrap(),
+                ChessBoard::square_from_notation("b8").unwrap(),
+            )
+        })];
+
+        let captures = captures_from_history(&history);
+        assert_eq!(captures.by_white, vec!['n']);
+        assert_eq!(captures.balance, 3 + 8);
+
+        chess_board.unmake_move(history.pop().unwrap());
+        assert_eq!(captures_from_history(&history), Captures::default());
+    }
+}
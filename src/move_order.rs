@@ -0,0 +1,248 @@
+use crate::{
+    chess_board::{ChessBoard, TOTAL_SQUARES},
+    chess_move::ChessMove,
+    piece::{piece_value, Colour},
+    search::MAX_PLY,
+    see::see,
+};
+
+// Moves are tried in stages: the hash move, captures that win material,
+// killers, the counter-move, the other quiet moves by history, then captures
+// that lose material
+const TT_MOVE: i32 = 2_000_000;
+const GOOD_CAPTURE: i32 = 1_000_000;
+const FIRST_KILLER: i32 = 800_000;
+const SECOND_KILLER: i32 = 790_000;
+const COUNTER_MOVE: i32 = 780_000;
+const BAD_CAPTURE: i32 = -1_000_000;
+
+// History scores stay within this, well clear of the stages either side
+const HISTORY_MAX: i32 = 16_384;
+
+const SQUARES: usize = TOTAL_SQUARES as usize;
+
+// What the search has learnt about good quiet moves so far
+pub struct MoveOrdering {
+    killers: Vec<[Option<ChessMove>; 2]>,
+    // By side, source and destination
+    history: Vec<[[i32; SQUARES]; SQUARES]>,
+    // The reply that refuted each move last time, by its source and destination
+    counter_moves: Vec<[Option<ChessMove>; SQUARES]>,
+}
+
+fn mvv_lva(board: &ChessBoard, chess_move: ChessMove) -> i32 {
+    let victim = match board.get_piece(chess_move.destination) {
+        Some(piece) => piece.value(),
+        // En passant
+        None if board.is_capture(chess_move) => piece_value('P'),
+        None => 0,
+    };
+    let attacker = board
+        .get_piece(chess_move.source)
+        .as_ref()
+        .map_or(0, |piece| piece.value());
+    let promotion = chess_move.promotion.map_or(0, piece_value);
+    (victim + promotion) * 16 - attacker
+}
+
+fn is_quiet(board: &ChessBoard, chess_move: ChessMove) -> bool {
+    !board.is_capture(chess_move) && chess_move.promotion.is_none()
+}
+
+impl MoveOrdering {
+    pub fn new() -> Self {
+        MoveOrdering {
+            killers: vec![[None; 2]; MAX_PLY as usize + 1],
+            history: vec![[[0; SQUARES]; SQUARES]; 2],
+            counter_moves: vec![[None; SQUARES]; SQUARES],
+        }
+    }
+
+    fn history(&self, colour: Colour, chess_move: ChessMove) -> i32 {
+        self.history[colour as usize][chess_move.source as usize][chess_move.destination as usize]
+    }
+
+    fn counter_move(&self, previous: Option<ChessMove>) -> Option<ChessMove> {
+        previous.and_then(|previous| {
+            self.counter_moves[previous.source as usize][previous.destination as usize]
+        })
+    }
+
+    fn score(
+        &self,
+        board: &ChessBoard,
+        chess_move: ChessMove,
+        ply: u32,
+        previous: Option<ChessMove>,
+    ) -> i32 {
+        if board.is_capture(chess_move) || chess_move.promotion == Some('Q') {
+            let exchange = see(board, chess_move);
+            return if exchange >= 0 {
+                GOOD_CAPTURE + mvv_lva(board, chess_move)
+            } else {
+                BAD_CAPTURE + exchange
+            };
+        }
+        // Under-promotions are hardly ever right
+        if chess_move.promotion.is_some() {
+            return BAD_CAPTURE;
+        }
+
+        let killers = &self.killers[ply as usize];
+        if killers[0] == Some(chess_move) {
+            FIRST_KILLER
+        } else if killers[1] == Some(chess_move) {
+            SECOND_KILLER
+        } else if self.counter_move(previous) == Some(chess_move) {
+            COUNTER_MOVE
+        } else {
+            self.history(board.side_to_move(), chess_move)
+        }
+    }
+
+    // Learns from a quiet move that caused a beta cutoff, and marks down the
+    // quiet moves tried before it that didn't
+    pub fn record_cutoff(
+        &mut self,
+        board: &ChessBoard,
+        chess_move: ChessMove,
+        ply: u32,
+        depth: u32,
+        previous: Option<ChessMove>,
+        tried: &[ChessMove],
+    ) {
+        if !is_quiet(board, chess_move) {
+            return;
+        }
+
+        let killers = &mut self.killers[ply as usize];
+        if killers[0] != Some(chess_move) {
+            killers[1] = killers[0];
+            killers[0] = Some(chess_move);
+        }
+
+        if let Some(previous) = previous {
+            self.counter_moves[previous.source as usize][previous.destination as usize] =
+                Some(chess_move);
+        }
+
+        let colour = board.side_to_move() as usize;
+        let bonus = (depth * depth).min(HISTORY_MAX as u32 / 8) as i32;
+        for &other in tried {
+            if other != chess_move && is_quiet(board, other) {
+                self.update_history(colour, other, -bonus);
+            }
+        }
+        self.update_history(colour, chess_move, bonus);
+    }
+
+    // Moves the entry towards the bonus, less so the closer it is to the limit
+    fn update_history(&mut self, colour: usize, chess_move: ChessMove, bonus: i32) {
+        let entry =
+            &mut self.history[colour][chess_move.source as usize][chess_move.destination as usize];
+        *entry += bonus - *entry * bonus.abs() / HISTORY_MAX;
+    }
+}
+
+// Hands out moves best first. Each move is only picked out from the rest when
+// it's asked for, so a cutoff early on saves sorting the others.
+pub struct MovePicker {
+    moves: Vec<(ChessMove, i32)>,
+}
+
+impl MovePicker {
+    pub fn new(
+        board: &ChessBoard,
+        moves: Vec<ChessMove>,
+        tt_move: Option<ChessMove>,
+        ordering: Option<&MoveOrdering>,
+        ply: u32,
+        previous: Option<ChessMove>,
+    ) -> Self {
+        let moves = moves
+            .into_iter()
+            .map(|chess_move| {
+                let score = if Some(chess_move) == tt_move {
+                    TT_MOVE
+                } else {
+                    match ordering {
+                        Some(ordering) => ordering.score(board, chess_move, ply, previous),
+                        None => 0,
+                    }
+                };
+                (chess_move, score)
+            })
+            .collect();
+        MovePicker { moves }
+    }
+}
+
+impl Iterator for MovePicker {
+    type Item = ChessMove;
+
+    fn next(&mut self) -> Option<ChessMove> {
+        let best = (0..self.moves.len()).max_by_key(|&i| (self.moves[i].1, usize::MAX - i))?;
+        Some(self.moves.remove(best).0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn picked(
+        board: &mut ChessBoard,
+        ordering: &MoveOrdering,
+        tt_move: Option<&str>,
+    ) -> Vec<String> {
+        let tt_move = tt_move.and_then(|notation| board.parse_move(notation));
+        let moves = board.legal_moves();
+        MovePicker::new(board, moves, tt_move, Some(ordering), 0, None)
+            .map(|m| m.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_stages() {
+        // Bishop or queen can take a free rook, but the knight and pawn are
+        // both defended
+        let mut board = ChessBoard::from_fen("4k3/8/2p5/1p1n4/8/3Q3r/8/4KB2 w - - 0 1");
+        let mut ordering = MoveOrdering::new();
+        ordering.killers[0][0] = board.parse_move("e1d2");
+
+        let moves = picked(&mut board, &ordering, Some("d3g6"));
+        assert_eq!(moves[..4], ["d3g6", "f1h3", "d3h3", "e1d2"]);
+        assert_eq!(moves[moves.len() - 2..], ["d3d5", "d3b5"]);
+        assert_eq!(moves.len(), board.legal_moves().len());
+    }
+
+    #[test]
+    fn test_record_cutoff() {
+        let mut board = ChessBoard::from_fen(crate::chess_board::START_FEN);
+        let mut ordering = MoveOrdering::new();
+        let cutoff = board.parse_move("g1f3").unwrap();
+        let tried = board.parse_move("a2a3").unwrap();
+        let previous = Some(ChessMove::new(52, 36));
+
+        ordering.record_cutoff(&board, cutoff, 3, 4, previous, &[tried, cutoff]);
+        assert_eq!(ordering.killers[3][0], Some(cutoff));
+        assert_eq!(ordering.counter_move(previous), Some(cutoff));
+        assert!(ordering.history(Colour::White, cutoff) > 0);
+        assert!(ordering.history(Colour::White, tried) < 0);
+
+        // A second killer pushes the first down
+        ordering.record_cutoff(&board, tried, 3, 4, None, &[tried]);
+        assert_eq!(ordering.killers[3], [Some(tried), Some(cutoff)]);
+    }
+
+    #[test]
+    fn test_history_is_bounded() {
+        let board = ChessBoard::from_fen(crate::chess_board::START_FEN);
+        let mut ordering = MoveOrdering::new();
+        let chess_move = ChessMove::new(6, 21);
+        for _ in 0..10_000 {
+            ordering.record_cutoff(&board, chess_move, 1, 30, None, &[chess_move]);
+        }
+        assert!(ordering.history(Colour::White, chess_move) <= HISTORY_MAX);
+    }
+}
@@ -0,0 +1,415 @@
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::{
+    chess_board::{ChessBoard, SquareIndex, TOTAL_SQUARES},
+    piece::Colour,
+    search::TB_WIN,
+};
+
+// A small efficiently updatable neural network: 768 inputs, one for each
+// piece on each square, seen from each side's point of view, into a hidden
+// layer of HIDDEN clipped ReLUs per side, then to a single output. The
+// hidden layer's inputs are kept in accumulators that moves update a few
+// weights at a time instead of working out from scratch.
+
+pub const INPUTS: usize = 768;
+pub const HIDDEN: usize = 256;
+
+// Network files start with this, then the hidden layer size
+const MAGIC: &[u8; 4] = b"CRNN";
+const HEADER_SIZE: usize = 8;
+const FILE_SIZE: usize = HEADER_SIZE + 2 * (INPUTS * HIDDEN + HIDDEN + 2 * HIDDEN + 1);
+
+// Quantisation: the hidden layer is in units of 1/QA, the output weights in
+// units of 1/QB, and the output is scaled from win probability logits to
+// centipawns by SCALE
+const QA: i32 = 255;
+const QB: i32 = 64;
+const SCALE: i64 = 400;
+
+const PIECES: &str = "PNBRQK";
+
+pub struct Network {
+    // HIDDEN weights for each input
+    input_weights: Vec<[i16; HIDDEN]>,
+    input_biases: [i16; HIDDEN],
+    // For the side to move's half of the hidden layer, then the other side's
+    output_weights: [[i16; HIDDEN]; 2],
+    output_bias: i16,
+}
+
+impl Network {
+    pub fn load(path: &Path) -> Result<Network, String> {
+        let bytes = fs::read(path).map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+        Network::from_bytes(&bytes).map_err(|error| format!("{} {}", path.display(), error))
+    }
+
+    // The header, then every weight as a little endian i16: the input
+    // weights input by input, the hidden biases, the output weights and the
+    // output bias
+    fn from_bytes(bytes: &[u8]) -> Result<Network, String> {
+        if !bytes.starts_with(MAGIC) {
+            return Err("isn't a network file".to_string());
+        }
+        if bytes.len() < HEADER_SIZE {
+            return Err(format!("is {} bytes, not {}", bytes.len(), FILE_SIZE));
+        }
+        let hidden = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) as usize;
+        if hidden != HIDDEN {
+            return Err(format!("has {} hidden neurons, not {}", hidden, HIDDEN));
+        }
+        if bytes.len() != FILE_SIZE {
+            return Err(format!("is {} bytes, not {}", bytes.len(), FILE_SIZE));
+        }
+
+        let mut values = bytes[HEADER_SIZE..]
+            .chunks_exact(2)
+            .map(|pair| i16::from_le_bytes([pair[0], pair[1]]));
+        let mut read_layer = || {
+            let mut layer = [0; HIDDEN];
+            for (weight, value) in layer.iter_mut().zip(values.by_ref()) {
+                *weight = value;
+            }
+            layer
+        };
+        let input_weights = (0..INPUTS).map(|_| read_layer()).collect();
+        let input_biases = read_layer();
+        let output_weights = [read_layer(), read_layer()];
+        let output_bias = values.next().unwrap_or_default();
+        Ok(Network {
+            input_weights,
+            input_biases,
+            output_weights,
+            output_bias,
+        })
+    }
+}
+
+// The input for a piece on a square as one side sees it: its own pieces
+// first, with the board turned round for black
+fn input(perspective: Colour, symbol: char, square: SquareIndex) -> usize {
+    let piece = PIECES
+        .find(symbol.to_ascii_uppercase())
+        .expect("piece symbol");
+    let white = symbol.is_ascii_uppercase();
+    let (own, square) = match perspective {
+        Colour::White => (white, square),
+        Colour::Black => (!white, square ^ 56),
+    };
+    let side = if own { 0 } else { 1 };
+    (side * PIECES.len() + piece) * TOTAL_SQUARES as usize + square as usize
+}
+
+// The hidden layer's inputs from white's then black's point of view
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(align(32))]
+struct Accumulator([[i16; HIDDEN]; 2]);
+
+// The network and an accumulator for each move made since the position was
+// set up, so taking a move back is just dropping one
+#[derive(Clone)]
+pub struct Accumulators {
+    network: Arc<Network>,
+    stack: Vec<Accumulator>,
+}
+
+impl Accumulators {
+    pub fn new(network: Arc<Network>, board: &ChessBoard) -> Accumulators {
+        let mut accumulators = Accumulators {
+            network,
+            stack: Vec::new(),
+        };
+        accumulators.refresh(board);
+        accumulators
+    }
+
+    // Works the accumulator out from scratch, forgetting the moves before
+    pub fn refresh(&mut self, board: &ChessBoard) {
+        self.stack.clear();
+        self.stack.push(Accumulator([self.network.input_biases; 2]));
+        for square in 0..TOTAL_SQUARES {
+            if let Some(piece) = board.get_piece(square) {
+                self.add(piece.get_symbol(), square);
+            }
+        }
+    }
+
+    // Starts a move, which then adds and removes its pieces
+    pub fn push(&mut self) {
+        let top = *self.top();
+        self.stack.push(top);
+    }
+
+    // Takes back a move, false if it was made before the last refresh
+    pub fn pop(&mut self) -> bool {
+        if self.stack.len() > 1 {
+            self.stack.pop();
+            true
+        } else {
+            false
+        }
+    }
+
+    // Sums wrap rather than panic, so a net with weights too big for i16
+    // gives bad evals instead of crashing, and taking a move back still
+    // undoes it exactly
+    pub fn add(&mut self, symbol: char, square: SquareIndex) {
+        self.update(symbol, square, i16::wrapping_add);
+    }
+
+    pub fn remove(&mut self, symbol: char, square: SquareIndex) {
+        self.update(symbol, square, i16::wrapping_sub);
+    }
+
+    // These loops are simple enough for the compiler to vectorise
+    fn update<F>(&mut self, symbol: char, square: SquareIndex, apply: F)
+    where
+        F: Fn(i16, i16) -> i16,
+    {
+        let network = &self.network;
+        let accumulator = self.stack.last_mut().expect("an accumulator");
+        for (perspective, values) in [Colour::White, Colour::Black]
+            .into_iter()
+            .zip(&mut accumulator.0)
+        {
+            let weights = &network.input_weights[input(perspective, symbol, square)];
+            for (value, &weight) in values.iter_mut().zip(weights) {
+                *value = apply(*value, weight);
+            }
+        }
+    }
+
+    fn top(&self) -> &Accumulator {
+        self.stack.last().expect("an accumulator")
+    }
+
+    // Centipawns for the side to move, kept short of the tablebase and mate
+    // scores
+    pub fn evaluate(&self, side_to_move: Colour) -> i32 {
+        let [white, black] = &self.top().0;
+        let (us, them) = match side_to_move {
+            Colour::White => (white, black),
+            Colour::Black => (black, white),
+        };
+        let weights = &self.network.output_weights;
+        // Each dot fits an i32 but the two together may not
+        let output = dot(us, &weights[0]) as i64 + dot(them, &weights[1]) as i64;
+        let eval = (output + self.network.output_bias as i64) * SCALE / (QA * QB) as i64;
+        eval.clamp(-(TB_WIN as i64 - 1), TB_WIN as i64 - 1) as i32
+    }
+}
+
+// The sum of the clipped hidden values times their weights, with AVX2 when
+// the processor has it
+fn dot(values: &[i16; HIDDEN], weights: &[i16; HIDDEN]) -> i32 {
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx2") {
+        // Safe as the processor has AVX2
+        return unsafe { dot_avx2(values, weights) };
+    }
+    dot_scalar(values, weights)
+}
+
+fn dot_scalar(values: &[i16; HIDDEN], weights: &[i16; HIDDEN]) -> i32 {
+    values
+        .iter()
+        .zip(weights)
+        .map(|(&value, &weight)| (value as i32).clamp(0, QA) * weight as i32)
+        .sum()
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn dot_avx2(values: &[i16; HIDDEN], weights: &[i16; HIDDEN]) -> i32 {
+    use std::arch::x86_64::*;
+
+    const LANES: usize = 16;
+    let zero = _mm256_setzero_si256();
+    let ceiling = _mm256_set1_epi16(QA as i16);
+    let mut sum = _mm256_setzero_si256();
+    for i in (0..HIDDEN).step_by(LANES) {
+        let value = _mm256_loadu_si256(values.as_ptr().add(i) as *const __m256i);
+        let weight = _mm256_loadu_si256(weights.as_ptr().add(i) as *const __m256i);
+        let clipped = _mm256_min_epi16(_mm256_max_epi16(value, zero), ceiling);
+        // Multiplies 16 pairs and adds neighbours into 8 sums
+        sum = _mm256_add_epi32(sum, _mm256_madd_epi16(clipped, weight));
+    }
+
+    let halves = _mm_add_epi32(
+        _mm256_castsi256_si128(sum),
+        _mm256_extracti128_si256(sum, 1),
+    );
+    let pairs = _mm_add_epi32(halves, _mm_shuffle_epi32(halves, 0b01_00_11_10));
+    let total = _mm_add_epi32(pairs, _mm_shuffle_epi32(pairs, 0b10_11_00_01));
+    _mm_cvtsi128_si32(total)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::{chess_board::START_FEN, random::Random};
+
+    // A network file with small random weights
+    pub fn random_network_bytes(seed: u64) -> Vec<u8> {
+        let mut random = Random::new(seed);
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend((HIDDEN as u32).to_le_bytes());
+        while bytes.len() < FILE_SIZE {
+            let weight = random.below(129) as i16 - 64;
+            bytes.extend(weight.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn random_network(seed: u64) -> Arc<Network> {
+        Arc::new(Network::from_bytes(&random_network_bytes(seed)).unwrap())
+    }
+
+    #[test]
+    fn test_from_bytes() {
+        let bytes = random_network_bytes(1);
+        let network = Network::from_bytes(&bytes).unwrap();
+        assert_eq!(network.input_weights.len(), INPUTS);
+        assert_eq!(
+            network.input_weights[0][1],
+            i16::from_le_bytes([bytes[10], bytes[11]])
+        );
+        assert_eq!(
+            network.output_bias,
+            i16::from_le_bytes([bytes[FILE_SIZE - 2], bytes[FILE_SIZE - 1]])
+        );
+
+        assert_eq!(
+            Network::from_bytes(&bytes[..FILE_SIZE - 2]).err().unwrap(),
+            format!("is {} bytes, not {}", FILE_SIZE - 2, FILE_SIZE)
+        );
+        let mut wrong_size = bytes.clone();
+        wrong_size[4] = 128;
+        assert!(Network::from_bytes(&wrong_size)
+            .err()
+            .unwrap()
+            .starts_with("has 384 hidden neurons"));
+        assert!(Network::from_bytes(b"PK\x03\x04").is_err());
+        assert_eq!(
+            Network::from_bytes(b"CRNN").err().unwrap(),
+            format!("is 4 bytes, not {}", FILE_SIZE)
+        );
+        assert!(Network::from_bytes(&bytes[..6]).is_err());
+        assert!(Network::load(Path::new("/no/such/network.bin"))
+            .err()
+            .unwrap()
+            .starts_with("can't read"));
+    }
+
+    #[test]
+    fn test_input() {
+        // A white pawn on e2 is black's enemy pawn on e7
+        assert_eq!(input(Colour::White, 'P', 12), 12);
+        assert_eq!(input(Colour::Black, 'P', 12), 384 + 52);
+        assert_eq!(input(Colour::White, 'k', 60), 384 + 5 * 64 + 60);
+        assert_eq!(input(Colour::Black, 'k', 60), 5 * 64 + 4);
+    }
+
+    // Walks the tree checking the incremental accumulators against ones
+    // worked out from scratch
+    fn check_accumulators(board: &mut ChessBoard, network: &Arc<Network>, depth: u32) {
+        let fresh = Accumulators::new(network.clone(), board);
+        assert_eq!(
+            board.nnue().unwrap().top(),
+            fresh.top(),
+            "{}",
+            board.to_fen()
+        );
+        if depth == 0 {
+            return;
+        }
+        for chess_move in board.legal_moves() {
+            let record = board.make_move(chess_move);
+            check_accumulators(board, network, depth - 1);
+            board.unmake_move(record);
+        }
+    }
+
+    #[test]
+    fn test_incremental_accumulators() {
+        let network = random_network(2);
+        for (fen, depth) in [
+            (
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                2,
+            ),
+            ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 3),
+            ("n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1", 2),
+        ] {
+            let mut board = ChessBoard::from_fen(fen);
+            board.set_network(Some(network.clone()));
+            check_accumulators(&mut board, &network, depth);
+        }
+    }
+
+    #[test]
+    fn test_unmake_before_refresh() {
+        let mut board = ChessBoard::from_fen(START_FEN);
+        let record = board.make_move(board.clone().parse_move("e2e4").unwrap());
+        let network = random_network(3);
+        board.set_network(Some(network.clone()));
+        board.unmake_move(record);
+        let fresh = Accumulators::new(network, &board);
+        assert_eq!(board.nnue().unwrap().top(), fresh.top());
+    }
+
+    #[test]
+    fn test_large_weights() {
+        // Weights this big overflow the accumulators, which wrap rather than
+        // panic and still come back to where they were
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend((HIDDEN as u32).to_le_bytes());
+        while bytes.len() < FILE_SIZE {
+            bytes.extend(i16::MAX.to_le_bytes());
+        }
+        let network = Arc::new(Network::from_bytes(&bytes).unwrap());
+        let mut board = ChessBoard::from_fen(START_FEN);
+        board.set_network(Some(network.clone()));
+        let before = *board.nnue().unwrap().top();
+        board.nnue().unwrap().evaluate(Colour::White);
+        check_accumulators(&mut board, &network, 2);
+        assert_eq!(*board.nnue().unwrap().top(), before);
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let network = random_network(4);
+        let mut board = ChessBoard::from_fen(
+            "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3",
+        );
+        board.set_network(Some(network.clone()));
+        let mut mirrored = ChessBoard::from_fen(
+            "rnbqkb1r/pppp1ppp/5n2/4p3/4P3/2N5/PPPP1PPP/R1BQKBNR b KQkq - 2 3",
+        );
+        mirrored.set_network(Some(network));
+
+        // The same position with the colours swapped looks the same to the
+        // side to move
+        let eval = board.nnue().unwrap().evaluate(Colour::White);
+        assert_eq!(mirrored.nnue().unwrap().evaluate(Colour::Black), eval);
+        assert_ne!(board.nnue().unwrap().evaluate(Colour::Black), eval);
+    }
+
+    #[test]
+    fn test_dot() {
+        let mut random = Random::new(5);
+        let mut values = [0; HIDDEN];
+        let mut weights = [0; HIDDEN];
+        for (value, weight) in values.iter_mut().zip(weights.iter_mut()) {
+            *value = random.below(800) as i16 - 300;
+            *weight = random.below(256) as i16 - 128;
+        }
+        let expected: i32 = (0..HIDDEN)
+            .map(|i| (values[i] as i32).clamp(0, 255) * weights[i] as i32)
+            .sum();
+        assert_eq!(dot_scalar(&values, &weights), expected);
+        assert_eq!(dot(&values, &weights), expected);
+    }
+}
@@ -0,0 +1,198 @@
+use crate::piece::Colour;
+
+// A game read from a PGN file: its tag pairs, the moves in SAN and the result
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    pub moves: Vec<String>,
+    pub result: String,
+}
+
+impl PgnGame {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    // The winner, Some(None) for a draw and None if the game wasn't finished
+    pub fn winner(&self) -> Option<Option<Colour>> {
+        match self.result.as_str() {
+            "1-0" => Some(Some(Colour::White)),
+            "0-1" => Some(Some(Colour::Black)),
+            "1/2-1/2" => Some(None),
+            _ => None,
+        }
+    }
+}
+
+const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+// Reads every game in a PGN file. Comments, variations, NAGs and move numbers
+// are skipped; only the main line is kept.
+pub fn parse_pgn(text: &str) -> Vec<PgnGame> {
+    let mut games = Vec::new();
+    let mut game = PgnGame::default();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '[' => {
+                // Tags after moves start the next game, for files missing
+                // a result
+                if !game.moves.is_empty() {
+                    games.push(std::mem::take(&mut game));
+                }
+                let tag: String = chars.by_ref().take_while(|&c| c != ']').collect();
+                if let Some((name, value)) = tag.split_once(' ') {
+                    let value = value.trim().trim_matches('"').replace("\\\"", "\"");
+                    game.tags.push((name.to_string(), value));
+                }
+            }
+            '{' => {
+                chars.by_ref().find(|&c| c == '}');
+            }
+            ';' => {
+                chars.by_ref().find(|&c| c == '\n');
+            }
+            '(' => {
+                let mut depth = 1;
+                for c in chars.by_ref() {
+                    match c {
+                        '(' => depth += 1,
+                        ')' => depth -= 1,
+                        _ => {}
+                    }
+                    if depth == 0 {
+                        break;
+                    }
+                }
+            }
+            _ if c.is_whitespace() => {}
+            _ => {
+                let mut token = c.to_string();
+                while let Some(&next) = chars.peek() {
+                    if next.is_whitespace() || "{}()[];".contains(next) {
+                        break;
+                    }
+                    token.push(next);
+                    chars.next();
+                }
+
+                if RESULTS.contains(&token.as_str()) {
+                    game.result = token;
+                    games.push(std::mem::take(&mut game));
+                } else if !token.starts_with('$') {
+                    // Move numbers can be stuck to the move, as in 1.e4
+                    let san = token.rsplit('.').next().unwrap_or_default();
+                    if !san.is_empty() && !san.bytes().all(|byte| byte.is_ascii_digit()) {
+                        game.moves.push(san.to_string());
+                    }
+                }
+            }
+        }
+    }
+
+    if !game.moves.is_empty() || !game.tags.is_empty() {
+        games.push(game);
+    }
+    games
+}
+
+// Lines of movetext are kept to this many characters
+const LINE_WIDTH: usize = 80;
+
+// Writes a game out as PGN: the tag pairs, then the movetext wrapped between
+// tokens, ending with the result
+pub fn write_pgn(tags: &[(String, String)], movetext: &[String], result: &str) -> String {
+    let mut text = String::new();
+    for (name, value) in tags {
+        let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+        text.push_str(&format!("[{} \"{}\"]\n", name, value));
+    }
+    text.push('\n');
+
+    let mut line = String::new();
+    for token in movetext.iter().map(String::as_str).chain([result]) {
+        if !line.is_empty() && line.len() + 1 + token.len() > LINE_WIDTH {
+            text.push_str(&line);
+            text.push('\n');
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(token);
+    }
+    text.push_str(&line);
+    text.push_str("\n\n");
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pgn() {
+        let text = r#"[Event "Casual"]
+[White "Dan"]
+[Result "1-0"]
+
+1. e4 e5 2.Nf3 {a comment} Nc6 (2... d6 3. d4 (3. Bc4)) 3. Bb5 $1 a6 ; the Morphy
+4. Ba4 1-0
+
+[Event "Second"]
+1. d4 d5 1/2-1/2
+1. c4 *
+1. f3 e5 2. g4 0-0
+"#;
+        let games = parse_pgn(text);
+        assert_eq!(games.len(), 4);
+
+        assert_eq!(games[0].tag("White"), Some("Dan"));
+        assert_eq!(games[0].tag("Black"), None);
+        assert_eq!(
+            games[0].moves,
+            ["e4", "e5", "Nf3", "Nc6", "Bb5", "a6", "Ba4"]
+        );
+        assert_eq!(games[0].winner(), Some(Some(Colour::White)));
+
+        assert_eq!(games[1].tag("Event"), Some("Second"));
+        assert_eq!(games[1].moves, ["d4", "d5"]);
+        assert_eq!(games[1].winner(), Some(None));
+
+        assert_eq!(games[2].moves, ["c4"]);
+        assert_eq!(games[2].winner(), None);
+
+        // Cut off without a result
+        assert_eq!(games[3].moves, ["f3", "e5", "g4", "0-0"]);
+        assert_eq!(games[3].result, "");
+    }
+
+    #[test]
+    fn test_write_pgn() {
+        let tags = vec![
+            ("Event".to_string(), "Say \"hi\"".to_string()),
+            ("Result".to_string(), "1-0".to_string()),
+        ];
+        let movetext: Vec<String> = ["1.", "e4", "{ a comment }", "1...", "e5"]
+            .iter()
+            .map(|token| token.to_string())
+            .collect();
+        let text = write_pgn(&tags, &movetext, "1-0");
+        assert_eq!(
+            text,
+            "[Event \"Say \\\"hi\\\"\"]\n[Result \"1-0\"]\n\n1. e4 { a comment } 1... e5 1-0\n\n"
+        );
+        let games = parse_pgn(&text);
+        assert_eq!(games[0].tag("Result"), Some("1-0"));
+        assert_eq!(games[0].moves, ["e4", "e5"]);
+
+        // Long movetext wraps
+        let movetext = vec!["e4".to_string(); 100];
+        let text = write_pgn(&[], &movetext, "*");
+        assert!(text.lines().all(|line| line.len() <= LINE_WIDTH));
+    }
+}
@@ -1,16 +1,45 @@
 use crate::{
     chess_board::{ChessBoard, SquareIndex},
     chess_move::{
-        ChessMove, DiagonalSlidingMoves, JumpingMoves, MoveGenerator, PawnMoves,
+        CastlingMoves, ChessMove, DiagonalSlidingMoves, JumpingMoves, MoveGenerator, PawnMoves,
         StraightSlidingMoves,
     },
 };
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Colour {
+    White,
+    Black,
+}
+
+impl Colour {
+    pub fn opposite(self) -> Colour {
+        match self {
+            Colour::White => Colour::Black,
+            Colour::Black => Colour::White,
+        }
+    }
+
+    // FEN style letter for a piece of this colour, upper case for white
+    pub fn piece_symbol(self, symbol: char) -> char {
+        match self {
+            Colour::White => symbol.to_ascii_uppercase(),
+            Colour::Black => symbol.to_ascii_lowercase(),
+        }
+    }
+}
+
 pub struct Piece {
     symbol: char,
     move_generators: Vec<Box<dyn MoveGenerator>>,
 }
 
+impl Clone for Piece {
+    fn clone(&self) -> Piece {
+        Piece::new(self.symbol)
+    }
+}
+
 impl Piece {
     pub fn new(symbol: char) -> Piece {
         Piece {
@@ -36,6 +65,11 @@ impl Piece {
             _ => ' ',
         }
     }
+
+    pub fn get_ascii(self: &Piece) -> char {
+        self.symbol
+    }
+
     fn get_move_generators(symbol: char) -> Vec<Box<dyn MoveGenerator>> {
         match symbol {
             'R' | 'r' => vec![Box::new(StraightSlidingMoves::new(7))],
@@ -48,6 +82,7 @@ impl Piece {
             'K' | 'k' => vec![
                 Box::new(StraightSlidingMoves::new(1)),
                 Box::new(DiagonalSlidingMoves::new(1)),
+                Box::new(CastlingMoves::new()),
             ],
             'P' => vec![Box::new(PawnMoves::new(1, 2))],
             'p' => vec![Box::new(PawnMoves::new(-1, 7))],
@@ -61,6 +96,22 @@ impl Piece {
     pub fn is_white(self: &Piece) -> bool {
         !self.is_black()
     }
+    pub fn colour(self: &Piece) -> Colour {
+        if self.is_black() {
+            Colour::Black
+        } else {
+            Colour::White
+        }
+    }
+    pub fn get_symbol(self: &Piece) -> char {
+        self.symbol
+    }
+    pub fn value(self: &Piece) -> i32 {
+        piece_value(self.symbol)
+    }
+    pub fn is_king(self: &Piece) -> bool {
+        self.symbol.eq_ignore_ascii_case(&'K')
+    }
 
     pub fn generate_moves(
         self: &Piece,
@@ -78,3 +129,14 @@ impl Piece {
         self.is_black() == other.is_white()
     }
 }
+
+// Material in pawns, kings aren't counted
+pub fn piece_value(symbol: char) -> i32 {
+    match symbol.to_ascii_uppercase() {
+        'P' => 1,
+        'N' | 'B' => 3,
+        'R' => 5,
+        'Q' => 9,
+        _ => 0,
+    }
+}
@@ -0,0 +1,58 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// A small xorshift generator, plenty for choosing between moves. Seeded from
+// the time unless it needs to be repeatable.
+pub struct Random {
+    state: u64,
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        // Xorshift gets stuck on zero
+        Random { state: seed.max(1) }
+    }
+
+    pub fn from_time() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_nanos() as u64);
+        Random::new(nanos)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    // A number from 0 up to but not including the limit
+    pub fn below(&mut self, limit: u64) -> u64 {
+        if limit == 0 {
+            0
+        } else {
+            self.next_u64() % limit
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repeatable_and_in_range() {
+        let mut first = Random::new(42);
+        let mut second = Random::new(42);
+        for _ in 0..100 {
+            assert_eq!(first.next_u64(), second.next_u64());
+        }
+
+        let mut seen = [false; 6];
+        for _ in 0..1000 {
+            seen[first.below(6) as usize] = true;
+        }
+        assert_eq!(seen, [true; 6]);
+        assert_eq!(Random::new(0).below(0), 0);
+    }
+}
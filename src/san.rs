@@ -0,0 +1,245 @@
+use crate::{
+    chess_board::{ChessBoard, SquareIndex},
+    chess_move::ChessMove,
+    piece::Colour,
+};
+
+// Standard algebraic notation, as used in PGN files: Nf3, exd5, O-O, e8=Q+
+
+fn parse_square(file: u8, rank: u8) -> Option<SquareIndex> {
+    if (b'a'..=b'h').contains(&file) && (b'1'..=b'8').contains(&rank) {
+        ChessBoard::square_from_file_and_rank(file - b'a', rank - b'1')
+    } else {
+        None
+    }
+}
+
+// Finds the legal move a SAN string stands for, None if it's illegal or
+// ambiguous
+pub fn parse_san(board: &mut ChessBoard, san: &str) -> Option<ChessMove> {
+    let san = san.trim_end_matches(['+', '#', '!', '?']);
+    let legal = board.legal_moves();
+
+    // Some files write castling with zeros
+    let castle = match san {
+        "O-O" | "0-0" => Some(2),
+        "O-O-O" | "0-0-0" => Some(-2),
+        _ => None,
+    };
+    if let Some(files) = castle {
+        return legal.into_iter().find(|chess_move| {
+            board
+                .get_piece(chess_move.source)
+                .as_ref()
+                .is_some_and(|piece| piece.is_king())
+                && chess_move.destination as i32 - chess_move.source as i32 == files
+        });
+    }
+
+    let (san, promotion) = match san.split_once('=') {
+        Some((san, piece)) => (san, Some(piece.chars().next()?.to_ascii_uppercase())),
+        None => (san, None),
+    };
+    let bytes = san.as_bytes();
+    if bytes.len() < 2 {
+        return None;
+    }
+    let destination = parse_square(bytes[bytes.len() - 2], bytes[bytes.len() - 1])?;
+    let (piece, rest) = match bytes[0] {
+        b'K' | b'Q' | b'R' | b'B' | b'N' => (bytes[0] as char, &bytes[1..bytes.len() - 2]),
+        _ => ('P', &bytes[..bytes.len() - 2]),
+    };
+    // What's left is any disambiguation and the capture mark
+    let mut from_file = None;
+    let mut from_rank = None;
+    for &byte in rest {
+        match byte {
+            b'a'..=b'h' => from_file = Some(byte - b'a'),
+            b'1'..=b'8' => from_rank = Some(byte - b'1'),
+            b'x' | b':' => {}
+            _ => return None,
+        }
+    }
+
+    let colour = board.side_to_move();
+    let mut candidates = legal.into_iter().filter(|chess_move| {
+        let (file, rank) = ChessBoard::square_to_file_and_rank(chess_move.source);
+        chess_move.destination == destination
+            && chess_move.promotion == promotion
+            && from_file.is_none_or(|from_file| from_file == file)
+            && from_rank.is_none_or(|from_rank| from_rank == rank)
+            && board
+                .get_piece(chess_move.source)
+                .as_ref()
+                .is_some_and(|moved| moved.get_symbol() == colour.piece_symbol(piece))
+    });
+    let chess_move = candidates.next()?;
+    candidates.next().is_none().then_some(chess_move)
+}
+
+// The SAN for a legal move, with + or # when it gives check or mate
+pub fn to_san(board: &mut ChessBoard, chess_move: ChessMove) -> String {
+    let source = chess_move.source;
+    let destination = chess_move.destination;
+    let symbol = board
+        .get_piece(source)
+        .as_ref()
+        .map_or('P', |piece| piece.get_symbol());
+    let (source_file, source_rank) = ChessBoard::square_to_file_and_rank(source);
+    let capture = board.is_capture(chess_move);
+    let target = ChessBoard::square_to_notation(destination).unwrap_or_default();
+
+    let mut san = match symbol.to_ascii_uppercase() {
+        'K' if source.abs_diff(destination) == 2 => {
+            if destination > source { "O-O" } else { "O-O-O" }.to_string()
+        }
+        'P' => {
+            let mut san = String::new();
+            if capture {
+                san.push((b'a' + source_file) as char);
+                san.push('x');
+            }
+            san.push_str(&target);
+            if let Some(promotion) = chess_move.promotion {
+                san.push('=');
+                san.push(promotion.to_ascii_uppercase());
+            }
+            san
+        }
+        piece => {
+            // Other pieces of the same kind that could go there too
+            let others: Vec<ChessMove> = board
+                .legal_moves()
+                .into_iter()
+                .filter(|other| {
+                    other.destination == destination
+                        && other.source != source
+                        && board
+                            .get_piece(other.source)
+                            .as_ref()
+                            .is_some_and(|other| other.get_symbol() == symbol)
+                })
+                .collect();
+            let mut san = piece.to_string();
+            if !others.is_empty() {
+                let on = |other: &ChessMove| ChessBoard::square_to_file_and_rank(other.source);
+                if others.iter().all(|other| on(other).0 != source_file) {
+                    san.push((b'a' + source_file) as char);
+                } else if others.iter().all(|other| on(other).1 != source_rank) {
+                    san.push((b'1' + source_rank) as char);
+                } else {
+                    san.push((b'a' + source_file) as char);
+                    san.push((b'1' + source_rank) as char);
+                }
+            }
+            if capture {
+                san.push('x');
+            }
+            san.push_str(&target);
+            san
+        }
+    };
+
+    let record = board.make_move(chess_move);
+    if board.is_in_check(board.side_to_move()) {
+        san.push(if board.legal_moves().is_empty() {
+            '#'
+        } else {
+            '+'
+        });
+    }
+    board.unmake_move(record);
+    san
+}
+
+// Moves in SAN with their move numbers, e.g. "12... Nf6 13. e5"
+pub fn format_line(board: &ChessBoard, moves: &[ChessMove]) -> String {
+    let mut board = board.clone();
+    let mut words = Vec::new();
+    for (index, &chess_move) in moves.iter().enumerate() {
+        match board.side_to_move() {
+            Colour::White => words.push(format!("{}.", board.fullmove_number())),
+            Colour::Black if index == 0 => words.push(format!("{}...", board.fullmove_number())),
+            Colour::Black => {}
+        }
+        words.push(to_san(&mut board, chess_move));
+        board.make_move(chess_move);
+    }
+    words.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chess_board::START_FEN;
+
+    fn parse(fen: &str, san: &str) -> Option<String> {
+        parse_san(&mut ChessBoard::from_fen(fen), san).map(|chess_move| chess_move.to_string())
+    }
+
+    #[test]
+    fn test_parse_san() {
+        assert_eq!(parse(START_FEN, "e4"), Some("e2e4".to_string()));
+        assert_eq!(parse(START_FEN, "Nf3"), Some("g1f3".to_string()));
+        assert_eq!(parse(START_FEN, "Nd2"), None);
+        assert_eq!(parse(START_FEN, "e5"), None);
+        assert_eq!(parse(START_FEN, "xyz"), None);
+
+        let fen = "r3k2r/1P6/8/3p4/8/2N1N3/8/R3K2R w KQkq - 0 1";
+        assert_eq!(parse(fen, "O-O"), Some("e1g1".to_string()));
+        assert_eq!(parse(fen, "0-0-0+"), Some("e1c1".to_string()));
+        assert_eq!(parse(fen, "bxa8=Q+"), Some("b7a8q".to_string()));
+        assert_eq!(parse(fen, "b8=N"), Some("b7b8n".to_string()));
+        assert_eq!(parse(fen, "b8"), None);
+        // Both knights can take, so it needs the file
+        assert_eq!(parse(fen, "Nxd5"), None);
+        assert_eq!(parse(fen, "Ncxd5!?"), Some("c3d5".to_string()));
+    }
+
+    #[test]
+    fn test_to_san() {
+        let fen = "r3k2r/1P6/8/3p4/8/2N1N3/8/R3K2R w KQkq - 0 1";
+        let san = |notation: &str| {
+            let mut board = ChessBoard::from_fen(fen);
+            let chess_move = board.parse_move(notation).unwrap();
+            to_san(&mut board, chess_move)
+        };
+        assert_eq!(san("e1g1"), "O-O");
+        assert_eq!(san("e1c1"), "O-O-O");
+        assert_eq!(san("b7a8q"), "bxa8=Q+");
+        assert_eq!(san("c3d5"), "Ncxd5");
+        assert_eq!(san("e3g4"), "Ng4");
+        assert_eq!(san("a1a7"), "Ra7");
+
+        // Every SAN reads back as the same move
+        let mut board = ChessBoard::from_fen(fen);
+        for chess_move in board.legal_moves() {
+            let notation = to_san(&mut board, chess_move);
+            assert_eq!(
+                parse_san(&mut board, &notation),
+                Some(chess_move),
+                "{}",
+                notation
+            );
+        }
+    }
+
+    #[test]
+    fn test_format_line() {
+        let mut board = ChessBoard::from_fen(START_FEN);
+        let moves: Vec<ChessMove> = ["f2f3", "e7e5", "g2g4", "d8h4"]
+            .iter()
+            .map(|notation| {
+                let chess_move = board.parse_move(notation).unwrap();
+                board.make_move(chess_move);
+                chess_move
+            })
+            .collect();
+        let start = ChessBoard::from_fen(START_FEN);
+        assert_eq!(format_line(&start, &moves), "1. f3 e5 2. g4 Qh4#");
+
+        let mut board = ChessBoard::from_fen(START_FEN);
+        board.make_move(moves[0]);
+        assert_eq!(format_line(&board, &moves[1..3]), "1... e5 2. g4");
+    }
+}
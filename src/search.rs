@@ -0,0 +1,1240 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{
+    chess_board::ChessBoard,
+    chess_move::ChessMove,
+    eval::evaluate_relative,
+    move_order::{MoveOrdering, MovePicker},
+    see::see,
+    syzygy::{RootProbe, Tablebase, Wdl},
+    time_manager::TimeManager,
+    tt::{score_from_tt, score_to_tt, Bound, TranspositionTable},
+};
+
+pub const INFINITY: i32 = 32_000;
+// Mates are scored as MATE less the number of plies to get there, so shorter
+// mates score higher
+pub const MATE: i32 = 31_000;
+pub const MAX_PLY: u32 = 64;
+// Tablebase wins score below any mate and above any evaluation, less the
+// plies to get to them
+pub const TB_WIN: i32 = MATE - 2 * MAX_PLY as i32;
+pub const MAX_THREADS: usize = 64;
+pub const MAX_MULTI_PV: usize = 64;
+
+// Depths up to which the futility prunings apply, from which a null move is
+// tried, and from which its cutoff is verified
+const FUTILITY_DEPTH: u32 = 3;
+const REVERSE_FUTILITY_DEPTH: u32 = 6;
+const NULL_MOVE_MIN_DEPTH: u32 = 3;
+const NULL_MOVE_VERIFY_DEPTH: u32 = 8;
+
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct SearchLimits {
+    pub depth: Option<u32>,
+    pub nodes: Option<u64>,
+    pub movetime: Option<Duration>,
+    pub wtime: Option<Duration>,
+    pub btime: Option<Duration>,
+    pub winc: Option<Duration>,
+    pub binc: Option<Duration>,
+    pub movestogo: Option<u32>,
+    // Keep going until told to stop
+    pub infinite: bool,
+    // Time lost to the GUI and the connection on every move
+    pub move_overhead: Duration,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Score {
+    Centipawns(i32),
+    // Moves until mate, negative when we're the ones getting mated
+    Mate(i32),
+}
+
+impl Score {
+    // Back to a number that orders mates and centipawns together, the
+    // inverse of from_search
+    pub fn value(self) -> i32 {
+        match self {
+            Score::Centipawns(centipawns) => centipawns,
+            Score::Mate(moves) if moves > 0 => MATE - (moves * 2 - 1),
+            Score::Mate(moves) => -MATE - moves * 2,
+        }
+    }
+
+    pub fn from_search(score: i32) -> Score {
+        if score > MATE - MAX_PLY as i32 {
+            Score::Mate((MATE - score + 1) / 2)
+        } else if score < -MATE + MAX_PLY as i32 {
+            Score::Mate(-(MATE + score) / 2)
+        } else {
+            Score::Centipawns(score)
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SearchInfo {
+    pub depth: u32,
+    // Which of the best lines this is, from 1
+    pub multipv: usize,
+    pub score: Score,
+    pub nodes: u64,
+    pub time: Duration,
+    pub pv: Vec<ChessMove>,
+    // Permille of the transposition table in use
+    pub hashfull: u32,
+    // Positions looked up in the endgame tables
+    pub tbhits: u64,
+}
+
+// Switches and settings for the search itself, as opposed to when it stops.
+// Setting any of the numbers to 0 turns that pruning or extension off.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SearchParams {
+    // Killers, counter-moves, history and sorted captures after the hash move
+    pub move_ordering: bool,
+    // How much shallower to look after handing the opponent a free move
+    pub null_move_reduction: i32,
+    // Late moves are searched shallower from this depth...
+    pub lmr_min_depth: i32,
+    // ...once this many moves have had a full search
+    pub lmr_full_moves: i32,
+    // Per ply left, how far below alpha a quiet move can start and be skipped
+    pub futility_margin: i32,
+    // Per ply left, how far above beta the position can be and cut straight off
+    pub reverse_futility_margin: i32,
+    // Extra plies to look when in check
+    pub check_extension: i32,
+    // Half the width of the window around the last iteration's score
+    pub aspiration_window: i32,
+    // Threads searching the same position, sharing the hash table
+    pub threads: usize,
+    // How many best lines to find, each leaving out the first moves of the
+    // lines before it
+    pub multi_pv: usize,
+}
+
+impl Default for SearchParams {
+    fn default() -> Self {
+        SearchParams {
+            move_ordering: true,
+            null_move_reduction: 3,
+            lmr_min_depth: 3,
+            lmr_full_moves: 3,
+            futility_margin: 100,
+            reverse_futility_margin: 80,
+            check_extension: 1,
+            aspiration_window: 30,
+            threads: 1,
+            multi_pv: 1,
+        }
+    }
+}
+
+pub struct Tunable {
+    pub name: &'static str,
+    pub min: i32,
+    pub max: i32,
+}
+
+pub const TUNABLES: [Tunable; 7] = [
+    Tunable {
+        name: "NullMoveReduction",
+        min: 0,
+        max: 6,
+    },
+    Tunable {
+        name: "LMRMinDepth",
+        min: 0,
+        max: 10,
+    },
+    Tunable {
+        name: "LMRFullMoves",
+        min: 1,
+        max: 20,
+    },
+    Tunable {
+        name: "FutilityMargin",
+        min: 0,
+        max: 500,
+    },
+    Tunable {
+        name: "ReverseFutilityMargin",
+        min: 0,
+        max: 500,
+    },
+    Tunable {
+        name: "CheckExtension",
+        min: 0,
+        max: 1,
+    },
+    Tunable {
+        name: "AspirationWindow",
+        min: 0,
+        max: 500,
+    },
+];
+
+impl SearchParams {
+    fn field(&mut self, name: &str) -> Option<&mut i32> {
+        match name {
+            "NullMoveReduction" => Some(&mut self.null_move_reduction),
+            "LMRMinDepth" => Some(&mut self.lmr_min_depth),
+            "LMRFullMoves" => Some(&mut self.lmr_full_moves),
+            "FutilityMargin" => Some(&mut self.futility_margin),
+            "ReverseFutilityMargin" => Some(&mut self.reverse_futility_margin),
+            "CheckExtension" => Some(&mut self.check_extension),
+            "AspirationWindow" => Some(&mut self.aspiration_window),
+            _ => None,
+        }
+    }
+
+    pub fn tunable(&self, name: &str) -> Option<i32> {
+        let mut params = *self;
+        params.field(name).map(|value| *value)
+    }
+
+    // Sets a tunable by name, false if there's no such tunable or the value
+    // is out of its range
+    pub fn set_tunable(&mut self, name: &str, value: i32) -> bool {
+        let in_range = TUNABLES
+            .iter()
+            .any(|tunable| tunable.name == name && (tunable.min..=tunable.max).contains(&value));
+        match self.field(name) {
+            Some(field) if in_range => {
+                *field = value;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PvLine {
+    pub score: Score,
+    pub pv: Vec<ChessMove>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SearchResult {
+    pub best_move: Option<ChessMove>,
+    pub score: Score,
+    pub pv: Vec<ChessMove>,
+    // Every line found at the last finished depth, best first
+    pub lines: Vec<PvLine>,
+    pub depth: u32,
+    pub nodes: u64,
+}
+
+struct Searcher<'a> {
+    board: &'a mut ChessBoard,
+    params: SearchParams,
+    tt: &'a TranspositionTable,
+    ordering: MoveOrdering,
+    // Moves made from the root to get to the current node, None for a null move
+    line: Vec<Option<ChessMove>>,
+    // Hashes of the positions before the current one, back to the start of
+    // the game, with 0 for a null move so repetitions aren't looked for past it
+    hashes: Vec<u64>,
+    // Root moves already given a line of their own this iteration
+    excluded: Vec<ChessMove>,
+    stop: &'a AtomicBool,
+    // Node counts of every thread, updated as they go, and which one is ours
+    thread_nodes: &'a [AtomicU64],
+    thread: usize,
+    tablebase: Option<&'a Tablebase>,
+    tb_hits: &'a AtomicU64,
+    // The root moves the tables keep, when they have the root position
+    root_probe: Option<RootProbe>,
+    deadline: Option<Instant>,
+    max_nodes: Option<u64>,
+    nodes: u64,
+    stopped: bool,
+}
+
+impl<'a> Searcher<'a> {
+    fn new(
+        board: &'a mut ChessBoard,
+        params: SearchParams,
+        tt: &'a TranspositionTable,
+        stop: &'a AtomicBool,
+        thread_nodes: &'a [AtomicU64],
+        thread: usize,
+        tb_hits: &'a AtomicU64,
+    ) -> Self {
+        Searcher {
+            board,
+            params,
+            tt,
+            ordering: MoveOrdering::new(),
+            line: Vec::new(),
+            hashes: Vec::new(),
+            excluded: Vec::new(),
+            stop,
+            thread_nodes,
+            thread,
+            tablebase: None,
+            tb_hits,
+            root_probe: None,
+            deadline: None,
+            max_nodes: None,
+            nodes: 0,
+            stopped: false,
+        }
+    }
+
+    // A position seen before since the last capture or pawn move is scored as
+    // a draw, as whatever made it worth repeating once will again
+    fn is_repetition(&self) -> bool {
+        let hash = self.board.hash();
+        self.hashes
+            .iter()
+            .rev()
+            .take(self.board.halfmove_clock() as usize)
+            .take_while(|&&previous| previous != 0)
+            .skip(1)
+            .step_by(2)
+            .any(|&previous| previous == hash)
+    }
+
+    fn should_stop(&mut self) -> bool {
+        if !self.stopped && self.nodes.is_multiple_of(1024) {
+            self.thread_nodes[self.thread].store(self.nodes, Ordering::Relaxed);
+            self.stopped = self.stop.load(Ordering::Relaxed)
+                || self
+                    .deadline
+                    .is_some_and(|deadline| Instant::now() >= deadline)
+                || self
+                    .max_nodes
+                    .is_some_and(|max_nodes| self.total_nodes() >= max_nodes);
+        }
+        self.stopped
+    }
+
+    // Nodes searched by all the threads so far
+    fn total_nodes(&self) -> u64 {
+        self.thread_nodes
+            .iter()
+            .enumerate()
+            .map(|(thread, nodes)| {
+                if thread == self.thread {
+                    self.nodes
+                } else {
+                    nodes.load(Ordering::Relaxed)
+                }
+            })
+            .sum()
+    }
+
+    // The tables know the result at the root even where the search can't
+    // see it, so that's reported unless the search finds a mate
+    fn root_score(&self, score: i32) -> i32 {
+        match &self.root_probe {
+            Some(probe) if score.abs() < MATE - MAX_PLY as i32 => match probe.wdl {
+                Wdl::Win => TB_WIN - probe.dtz,
+                Wdl::Loss => -TB_WIN - probe.dtz,
+                _ => 0,
+            },
+            _ => score,
+        }
+    }
+
+    // Deepens one ply at a time from first_depth until stopped, returning
+    // the last finished iteration
+    fn iterate<F>(
+        &mut self,
+        first_depth: u32,
+        max_depth: u32,
+        mut time: Option<&mut TimeManager>,
+        start: Instant,
+        mut on_info: F,
+    ) -> SearchResult
+    where
+        F: FnMut(&SearchInfo),
+    {
+        let root_moves = match &self.root_probe {
+            Some(probe) => probe.moves.clone(),
+            None => self.board.legal_moves(),
+        };
+        let mut result = SearchResult {
+            best_move: root_moves.first().copied(),
+            score: Score::Centipawns(0),
+            pv: Vec::new(),
+            lines: Vec::new(),
+            depth: 0,
+            nodes: 0,
+        };
+        let line_count = self.params.multi_pv.clamp(1, root_moves.len().max(1));
+
+        let mut previous_scores: Vec<i32> = Vec::new();
+        for depth in first_depth..=max_depth {
+            let mut lines: Vec<(i32, Vec<ChessMove>)> = Vec::new();
+            self.excluded.clear();
+
+            for index in 0..line_count {
+                let mut pv = Vec::new();
+
+                // Start with a narrow window around the last score, widening
+                // it on whichever side the score falls outside
+                let mut delta = self.params.aspiration_window;
+                let (mut alpha, mut beta) = match previous_scores.get(index) {
+                    Some(&score)
+                        if delta > 0 && depth >= 4 && score.abs() < MATE - MAX_PLY as i32 =>
+                    {
+                        (
+                            (score - delta).max(-INFINITY),
+                            (score + delta).min(INFINITY),
+                        )
+                    }
+                    _ => (-INFINITY, INFINITY),
+                };
+                let score = loop {
+                    let score = self.negamax(depth, 0, alpha, beta, &mut pv, true);
+                    if self.stopped {
+                        break score;
+                    }
+                    if score <= alpha {
+                        alpha = (score - delta).max(-INFINITY);
+                    } else if score >= beta {
+                        beta = (score + delta).min(INFINITY);
+                    } else {
+                        break score;
+                    }
+                    delta *= 2;
+                };
+
+                // A part finished iteration can't be trusted
+                if self.stopped {
+                    break;
+                }
+
+                on_info(&SearchInfo {
+                    depth,
+                    multipv: index + 1,
+                    score: Score::from_search(self.root_score(score)),
+                    nodes: self.total_nodes(),
+                    time: start.elapsed(),
+                    pv: pv.clone(),
+                    hashfull: self.tt.hashfull(),
+                    tbhits: self.tb_hits.load(Ordering::Relaxed),
+                });
+                // No moves at all when the game's already over
+                if let Some(&first) = pv.first() {
+                    self.excluded.push(first);
+                }
+                lines.push((score, pv));
+            }
+            self.excluded.clear();
+
+            if self.stopped || lines.is_empty() {
+                break;
+            }
+
+            // A later line can come out ahead of an earlier one
+            lines.sort_by_key(|(score, _)| -score);
+            previous_scores = lines.iter().map(|&(score, _)| score).collect();
+            let score = lines[0].0;
+            result = SearchResult {
+                best_move: lines[0].1.first().copied().or(result.best_move),
+                score: Score::from_search(self.root_score(score)),
+                pv: lines[0].1.clone(),
+                lines: lines
+                    .into_iter()
+                    .map(|(score, pv)| PvLine {
+                        score: Score::from_search(self.root_score(score)),
+                        pv,
+                    })
+                    .collect(),
+                depth,
+                nodes: self.nodes,
+            };
+
+            if let Some(time) = time.as_mut() {
+                time.update(result.best_move, score);
+                if time.stop_deepening(Instant::now()) {
+                    break;
+                }
+            }
+
+            // No point looking deeper once we've found a forced mate
+            if matches!(result.score, Score::Mate(moves) if moves > 0 && (moves * 2 - 1) as u32 <= depth)
+            {
+                break;
+            }
+        }
+
+        result.nodes = self.nodes;
+        result
+    }
+
+    // How many plies to take off a late quiet move: more the later the move
+    // and the deeper the search
+    fn late_move_reduction(&self, depth: u32, move_count: u32, is_pv: bool) -> u32 {
+        let reduction = ((depth as f64).ln() * ((move_count + 1) as f64).ln() / 2.0) as u32;
+        reduction
+            .saturating_sub(is_pv as u32)
+            .max(1)
+            .min(depth.saturating_sub(2))
+    }
+
+    fn negamax(
+        &mut self,
+        depth: u32,
+        ply: u32,
+        mut alpha: i32,
+        beta: i32,
+        pv: &mut Vec<ChessMove>,
+        allow_null: bool,
+    ) -> i32 {
+        self.nodes += 1;
+        pv.clear();
+        if self.should_stop() {
+            return 0;
+        }
+        if ply > 0 && self.is_repetition() {
+            return 0;
+        }
+
+        let colour = self.board.side_to_move();
+        let in_check = self.board.is_in_check(colour);
+
+        // Look further at checks so a mate or lost piece just past the horizon
+        // isn't missed
+        let depth = if in_check {
+            depth + self.params.check_extension as u32
+        } else {
+            depth
+        };
+
+        if depth == 0 || ply >= MAX_PLY {
+            return self.quiescence(ply, alpha, beta, pv);
+        }
+
+        let key = self.board.hash();
+        let entry = self.tt.probe(key);
+        if let Some(entry) = entry {
+            // The root always searches so there's a move and PV to report
+            if ply > 0 && entry.depth >= depth {
+                let score = score_from_tt(entry.score, ply);
+                match entry.bound {
+                    Bound::Exact => return score,
+                    Bound::Lower if score >= beta => return score,
+                    Bound::Upper if score <= alpha => return score,
+                    _ => {}
+                }
+            }
+        }
+
+        // Straight after a capture or pawn move the tables have the result,
+        // if there are few enough pieces
+        if ply > 0 && self.board.halfmove_clock() == 0 {
+            if let Some(wdl) = self
+                .tablebase
+                .and_then(|tablebase| tablebase.probe_wdl(self.board))
+            {
+                self.tb_hits.fetch_add(1, Ordering::Relaxed);
+                let (score, bound) = match wdl {
+                    Wdl::Win => (TB_WIN - ply as i32, Bound::Lower),
+                    Wdl::Loss => (-TB_WIN + ply as i32, Bound::Upper),
+                    _ => (0, Bound::Exact),
+                };
+                if bound == Bound::Exact
+                    || (bound == Bound::Lower && score >= beta)
+                    || (bound == Bound::Upper && score <= alpha)
+                {
+                    self.tt
+                        .store(key, None, score_to_tt(score, ply), depth, bound);
+                    return score;
+                }
+            }
+        }
+
+        let mut moves = self.board.legal_moves();
+        if moves.is_empty() {
+            return if in_check { -MATE + ply as i32 } else { 0 };
+        }
+        if ply == 0 {
+            moves.retain(|chess_move| !self.excluded.contains(chess_move));
+            if let Some(probe) = &self.root_probe {
+                moves.retain(|chess_move| probe.moves.contains(chess_move));
+            }
+        }
+
+        if self.board.halfmove_clock() >= 100 {
+            return 0;
+        }
+
+        let is_pv = beta - alpha > 1;
+        let mut child_pv = Vec::new();
+        let mut futile = false;
+
+        // Pruning on the static evaluation, only where an exact score isn't
+        // needed and there's no check to answer
+        if !is_pv && !in_check && beta.abs() < MATE - MAX_PLY as i32 {
+            let static_eval = evaluate_relative(self.board);
+
+            // So far ahead that any move will do
+            let margin = self.params.reverse_futility_margin * depth as i32;
+            if margin > 0 && depth <= REVERSE_FUTILITY_DEPTH && static_eval - margin >= beta {
+                return static_eval - margin;
+            }
+
+            // If handing the opponent a free move still leaves us above beta, a
+            // real move will too. Not when we only have pawns, where zugzwang
+            // is common, and never twice in a row.
+            let reduction = self.params.null_move_reduction as u32;
+            if reduction > 0
+                && allow_null
+                && depth >= NULL_MOVE_MIN_DEPTH
+                && static_eval >= beta
+                && self.board.has_non_pawn_material(colour)
+            {
+                let record = self.board.make_null_move();
+                self.line.push(None);
+                self.hashes.push(0);
+                let score = -self.negamax(
+                    depth.saturating_sub(1 + reduction),
+                    ply + 1,
+                    -beta,
+                    -beta + 1,
+                    &mut child_pv,
+                    false,
+                );
+                self.line.pop();
+                self.hashes.pop();
+                self.board.unmake_null_move(record);
+
+                if self.stopped {
+                    return 0;
+                }
+                if score >= beta {
+                    // Deep cutoffs are checked with a normal search in case
+                    // this is zugzwang after all
+                    if depth < NULL_MOVE_VERIFY_DEPTH
+                        || self.negamax(
+                            depth - reduction,
+                            ply,
+                            beta - 1,
+                            beta,
+                            &mut child_pv,
+                            false,
+                        ) >= beta
+                    {
+                        // A mate found by passing isn't a real one
+                        return beta;
+                    }
+                }
+            }
+
+            futile = self.params.futility_margin > 0
+                && depth <= FUTILITY_DEPTH
+                && static_eval + self.params.futility_margin * depth as i32 <= alpha;
+        }
+
+        // Whatever was best here last time is the most likely to be best again
+        let previous = self.line.last().copied().flatten();
+        let picker = MovePicker::new(
+            self.board,
+            moves,
+            entry.and_then(|entry| entry.best_move),
+            self.params.move_ordering.then_some(&self.ordering),
+            ply,
+            previous,
+        );
+
+        let original_alpha = alpha;
+        let mut best_score = -INFINITY;
+        let mut best_move = None;
+        let mut tried = Vec::new();
+        let mut move_count = 0;
+
+        for chess_move in picker {
+            let quiet = !self.board.is_capture(chess_move) && chess_move.promotion.is_none();
+            let record = self.board.make_move(chess_move);
+            let gives_check = self.board.is_in_check(colour.opposite());
+
+            // Too far behind for a quiet move to catch up before the horizon
+            if futile && quiet && !gives_check && move_count > 0 {
+                self.board.unmake_move(record);
+                continue;
+            }
+
+            tried.push(chess_move);
+            self.line.push(Some(chess_move));
+            self.hashes.push(record.hash);
+
+            // The first move gets a full window, the rest only have to show
+            // they're no better, and late quiet ones at a reduced depth
+            let score = if move_count == 0 {
+                -self.negamax(depth - 1, ply + 1, -beta, -alpha, &mut child_pv, true)
+            } else {
+                let reduction = if self.params.lmr_min_depth > 0
+                    && depth >= self.params.lmr_min_depth as u32
+                    && move_count >= self.params.lmr_full_moves as u32
+                    && quiet
+                    && !in_check
+                    && !gives_check
+                {
+                    self.late_move_reduction(depth, move_count, is_pv)
+                } else {
+                    0
+                };
+
+                let mut score = -self.negamax(
+                    depth.saturating_sub(1 + reduction),
+                    ply + 1,
+                    -alpha - 1,
+                    -alpha,
+                    &mut child_pv,
+                    true,
+                );
+                if score > alpha && reduction > 0 {
+                    score =
+                        -self.negamax(depth - 1, ply + 1, -alpha - 1, -alpha, &mut child_pv, true);
+                }
+                if score > alpha && score < beta {
+                    score = -self.negamax(depth - 1, ply + 1, -beta, -alpha, &mut child_pv, true);
+                }
+                score
+            };
+            move_count += 1;
+
+            self.line.pop();
+            self.hashes.pop();
+            self.board.unmake_move(record);
+
+            if self.stopped {
+                return 0;
+            }
+
+            if score > best_score {
+                best_score = score;
+            }
+            if score > alpha {
+                alpha = score;
+                best_move = Some(chess_move);
+                pv.clear();
+                pv.push(chess_move);
+                pv.extend_from_slice(&child_pv);
+            }
+            if alpha >= beta {
+                if self.params.move_ordering {
+                    self.ordering
+                        .record_cutoff(self.board, chess_move, ply, depth, previous, &tried);
+                }
+                break;
+            }
+        }
+
+        let bound = if best_score >= beta {
+            Bound::Lower
+        } else if best_score > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        // The root's best move isn't its best with some moves left out
+        if ply > 0 || (self.excluded.is_empty() && self.root_probe.is_none()) {
+            self.tt
+                .store(key, best_move, score_to_tt(best_score, ply), depth, bound);
+        }
+
+        best_score
+    }
+
+    // Keeps searching captures and promotions past the horizon so the score
+    // isn't taken in the middle of an exchange
+    fn quiescence(&mut self, ply: u32, mut alpha: i32, beta: i32, pv: &mut Vec<ChessMove>) -> i32 {
+        self.nodes += 1;
+        pv.clear();
+        if self.should_stop() {
+            return 0;
+        }
+
+        // Standing pat: the side to move doesn't have to capture
+        let stand_pat = evaluate_relative(self.board);
+        if stand_pat >= beta || ply >= MAX_PLY {
+            return stand_pat;
+        }
+        alpha = alpha.max(stand_pat);
+
+        // Captures that lose material on the exchange aren't worth a look
+        let mut captures: Vec<(ChessMove, i32)> = self
+            .board
+            .legal_captures()
+            .into_iter()
+            .map(|chess_move| (chess_move, see(self.board, chess_move)))
+            .filter(|(_, exchange)| *exchange >= 0)
+            .collect();
+        captures.sort_by_key(|(_, exchange)| -exchange);
+
+        let mut best_score = stand_pat;
+        let mut child_pv = Vec::new();
+
+        for (chess_move, _) in captures {
+            let record = self.board.make_move(chess_move);
+            let score = -self.quiescence(ply + 1, -beta, -alpha, &mut child_pv);
+            self.board.unmake_move(record);
+
+            if self.stopped {
+                return 0;
+            }
+
+            if score > best_score {
+                best_score = score;
+            }
+            if score > alpha {
+                alpha = score;
+                pv.clear();
+                pv.push(chess_move);
+                pv.extend_from_slice(&child_pv);
+            }
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        best_score
+    }
+}
+
+// The deepest finished search wins, the better score between equals
+fn best_thread(results: Vec<SearchResult>) -> SearchResult {
+    let nodes = results.iter().map(|result| result.nodes).sum();
+    let mut best = results
+        .into_iter()
+        .reduce(|best, result| {
+            if result.best_move.is_some()
+                && (result.depth, result.score.value()) > (best.depth, best.score.value())
+            {
+                result
+            } else {
+                best
+            }
+        })
+        .expect("the main thread has a result");
+    best.nodes = nodes;
+    best
+}
+
+// Iterative deepening: searches one ply deeper each time until a limit is
+// hit, reporting each completed depth through `on_info`. Helper threads, if
+// the params ask for them, share what they find through the hash table so
+// the main thread gets further; only the main thread watches the clock and
+// reports progress. With endgame tables the root moves are narrowed down to
+// those that keep the best result. `history` has the hashes of the game's
+// positions before this one, so the search can see repetitions coming.
+#[allow(clippy::too_many_arguments)]
+pub fn search<F>(
+    board: &mut ChessBoard,
+    history: &[u64],
+    limits: &SearchLimits,
+    params: &SearchParams,
+    tt: &TranspositionTable,
+    tablebase: Option<&Tablebase>,
+    stop: &AtomicBool,
+    on_info: F,
+) -> SearchResult
+where
+    F: FnMut(&SearchInfo),
+{
+    let start = Instant::now();
+    let max_depth = limits.depth.unwrap_or(MAX_PLY).clamp(1, MAX_PLY);
+    let mut time = TimeManager::new(limits, board.side_to_move(), start);
+    let threads = params.threads.clamp(1, MAX_THREADS);
+    let thread_nodes: Vec<AtomicU64> = (0..threads).map(|_| AtomicU64::new(0)).collect();
+    let helpers_stop = AtomicBool::new(false);
+    let root_probe = tablebase.and_then(|tablebase| tablebase.probe_root(board));
+    let tb_hits = AtomicU64::new(
+        root_probe
+            .as_ref()
+            .map_or(0, |probe| probe.moves.len() as u64),
+    );
+
+    tt.new_search();
+    thread::scope(|scope| {
+        let helpers: Vec<_> = (1..threads)
+            .map(|thread| {
+                let mut board = board.clone();
+                let (thread_nodes, helpers_stop, tb_hits) =
+                    (&thread_nodes, &helpers_stop, &tb_hits);
+                let root_probe = root_probe.clone();
+                scope.spawn(move || {
+                    let mut searcher = Searcher::new(
+                        &mut board,
+                        *params,
+                        tt,
+                        helpers_stop,
+                        thread_nodes,
+                        thread,
+                        tb_hits,
+                    );
+                    searcher.hashes = history.to_vec();
+                    searcher.tablebase = tablebase;
+                    searcher.root_probe = root_probe;
+                    // Half the helpers start a ply deeper so the threads
+                    // spread over more depths
+                    searcher.iterate(1 + thread as u32 % 2, max_depth, None, start, |_| {})
+                })
+            })
+            .collect();
+
+        let mut searcher = Searcher::new(board, *params, tt, stop, &thread_nodes, 0, &tb_hits);
+        searcher.hashes = history.to_vec();
+        searcher.tablebase = tablebase;
+        searcher.root_probe = root_probe;
+        searcher.deadline = time.deadline();
+        searcher.max_nodes = limits.nodes;
+        let main = searcher.iterate(1, max_depth, Some(&mut time), start, on_info);
+
+        helpers_stop.store(true, Ordering::Relaxed);
+        let mut results = vec![main];
+        results.extend(
+            helpers
+                .into_iter()
+                .map(|helper| helper.join().expect("search thread finished")),
+        );
+        best_thread(results)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn search_depth(fen: &str, depth: u32) -> SearchResult {
+        let mut board = ChessBoard::from_fen(fen);
+        let limits = SearchLimits {
+            depth: Some(depth),
+            ..SearchLimits::default()
+        };
+        let tt = TranspositionTable::new(1);
+        search(
+            &mut board,
+            &[],
+            &limits,
+            &SearchParams::default(),
+            &tt,
+            None,
+            &AtomicBool::new(false),
+            |_| {},
+        )
+    }
+
+    #[test]
+    fn test_mate_in_one() {
+        let result = search_depth("6k1/5ppp/8/8/8/8/q7/1R4K1 w - - 0 1", 3);
+        assert_eq!(result.best_move.unwrap().to_string(), "b1b8");
+        assert_eq!(result.score, Score::Mate(1));
+    }
+
+    #[test]
+    fn test_mate_in_two() {
+        // Cut off the seventh rank, then mate on the eighth
+        let result = search_depth("7k/8/8/8/8/8/R7/1R4K1 w - - 0 1", 4);
+        assert_eq!(result.score, Score::Mate(2));
+        assert_eq!(result.pv.len(), 3);
+    }
+
+    #[test]
+    fn test_getting_mated() {
+        let result = search_depth("6k1/5ppp/8/8/8/8/5PPP/r5K1 w - - 0 1", 2);
+        assert_eq!(result.score, Score::Mate(-0));
+        assert!(result.best_move.is_none());
+    }
+
+    #[test]
+    fn test_avoids_losing_the_queen() {
+        // Taking the pawn loses the queen to the rook
+        let result = search_depth("3rk3/8/8/3p4/8/8/3Q4/4K3 w - - 0 1", 2);
+        assert_ne!(result.best_move.unwrap().to_string(), "d2d5");
+    }
+
+    #[test]
+    fn test_quiescence_sees_the_recapture() {
+        // At depth 1 taking the defended pawn looks like it wins a pawn
+        // unless the exchange is played out
+        let result = search_depth("4k3/8/3p4/4p3/8/5N2/8/4K3 w - - 0 1", 1);
+        assert_ne!(result.best_move.unwrap().to_string(), "f3e5");
+        assert!(matches!(result.score, Score::Centipawns(cp) if cp < 300));
+    }
+
+    #[test]
+    fn test_pv_is_playable() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let result = search_depth(fen, 3);
+        let mut board = ChessBoard::from_fen(fen);
+        assert_eq!(result.pv.first(), result.best_move.as_ref());
+        for chess_move in result.pv {
+            assert!(board.parse_move(&chess_move.to_string()).is_some());
+            board.make_move(chess_move);
+        }
+    }
+
+    #[test]
+    fn test_node_limit() {
+        let mut board = ChessBoard::from_fen(crate::chess_board::START_FEN);
+        let limits = SearchLimits {
+            nodes: Some(2000),
+            ..SearchLimits::default()
+        };
+        let tt = TranspositionTable::new(1);
+        let result = search(
+            &mut board,
+            &[],
+            &limits,
+            &SearchParams::default(),
+            &tt,
+            None,
+            &AtomicBool::new(false),
+            |_| {},
+        );
+        assert!(result.best_move.is_some());
+        assert!(result.nodes < 2000 + 1024);
+    }
+
+    #[test]
+    fn test_stop_flag() {
+        let mut board = ChessBoard::from_fen(crate::chess_board::START_FEN);
+        let limits = SearchLimits {
+            infinite: true,
+            ..SearchLimits::default()
+        };
+        let tt = TranspositionTable::new(1);
+        let result = search(
+            &mut board,
+            &[],
+            &limits,
+            &SearchParams::default(),
+            &tt,
+            None,
+            &AtomicBool::new(true),
+            |_| {},
+        );
+        // Stopped straight away, but still has a move to play
+        assert!(result.best_move.is_some());
+    }
+
+    #[test]
+    fn test_reuses_work_from_the_table() {
+        let mut board = ChessBoard::from_fen(crate::chess_board::START_FEN);
+        let limits = SearchLimits {
+            depth: Some(4),
+            ..SearchLimits::default()
+        };
+        let tt = TranspositionTable::new(1);
+        let stop = AtomicBool::new(false);
+
+        let mut hashfull = 0;
+        let first = search(
+            &mut board,
+            &[],
+            &limits,
+            &SearchParams::default(),
+            &tt,
+            None,
+            &stop,
+            |info| hashfull = info.hashfull,
+        );
+        assert!(hashfull > 0);
+
+        let second = search(
+            &mut board,
+            &[],
+            &limits,
+            &SearchParams::default(),
+            &tt,
+            None,
+            &stop,
+            |_| {},
+        );
+        assert!(second.nodes < first.nodes / 2);
+        assert_eq!(first.score, second.score);
+    }
+
+    #[test]
+    fn test_move_ordering_saves_nodes() {
+        let mut board = ChessBoard::from_fen(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        );
+        let limits = SearchLimits {
+            depth: Some(3),
+            ..SearchLimits::default()
+        };
+        let stop = AtomicBool::new(false);
+        let mut nodes = |move_ordering| {
+            let params = SearchParams {
+                move_ordering,
+                ..SearchParams::default()
+            };
+            let tt = TranspositionTable::new(1);
+            search(&mut board, &[], &limits, &params, &tt, None, &stop, |_| {}).nodes
+        };
+
+        let plain = nodes(false);
+        let ordered = nodes(true);
+        assert!(ordered * 2 < plain, "{} vs {}", ordered, plain);
+    }
+
+    #[test]
+    fn test_mate_score_conversion() {
+        assert_eq!(Score::from_search(MATE - 1), Score::Mate(1));
+        assert_eq!(Score::from_search(MATE - 3), Score::Mate(2));
+        assert_eq!(Score::from_search(-MATE + 2), Score::Mate(-1));
+        assert_eq!(Score::from_search(150), Score::Centipawns(150));
+        // Exact for the scores a search can give, mates ending on the right side
+        for score in [MATE - 1, MATE - 3, -MATE, -MATE + 4, 150, -70] {
+            assert_eq!(Score::from_search(score).value(), score);
+        }
+    }
+
+    #[test]
+    fn test_threads() {
+        let mut board = ChessBoard::from_fen("7k/8/8/8/8/8/R7/1R4K1 w - - 0 1");
+        let params = SearchParams {
+            threads: 3,
+            ..SearchParams::default()
+        };
+        let limits = SearchLimits {
+            depth: Some(4),
+            ..SearchLimits::default()
+        };
+        let tt = TranspositionTable::new(1);
+        let mut reported = Vec::new();
+        let result = search(
+            &mut board,
+            &[],
+            &limits,
+            &params,
+            &tt,
+            None,
+            &AtomicBool::new(false),
+            |info| reported.push(info.nodes),
+        );
+        assert_eq!(result.score, Score::Mate(2));
+        assert_eq!(board.to_fen(), "7k/8/8/8/8/8/R7/1R4K1 w - - 0 1");
+        // Every thread's nodes count
+        assert!(result.nodes >= *reported.last().unwrap());
+
+        // Helpers stop along with the main thread
+        let limits = SearchLimits {
+            nodes: Some(5000),
+            ..SearchLimits::default()
+        };
+        let result = search(
+            &mut board,
+            &[],
+            &limits,
+            &params,
+            &tt,
+            None,
+            &AtomicBool::new(false),
+            |_| {},
+        );
+        assert!(result.best_move.is_some());
+    }
+
+    #[test]
+    fn test_multi_pv() {
+        let mut board = ChessBoard::from_fen("3rk3/8/8/3p4/8/8/3Q4/4K3 w - - 0 1");
+        let params = SearchParams {
+            multi_pv: 3,
+            ..SearchParams::default()
+        };
+        let limits = SearchLimits {
+            depth: Some(3),
+            ..SearchLimits::default()
+        };
+        let tt = TranspositionTable::new(1);
+        let mut reported = Vec::new();
+        let result = search(
+            &mut board,
+            &[],
+            &limits,
+            &params,
+            &tt,
+            None,
+            &AtomicBool::new(false),
+            |info| reported.push((info.depth, info.multipv)),
+        );
+
+        assert_eq!(result.lines.len(), 3);
+        assert_eq!(result.lines[0].pv, result.pv);
+        let first_moves: Vec<ChessMove> = result.lines.iter().map(|line| line.pv[0]).collect();
+        assert!(first_moves[1..].iter().all(|&m| m != first_moves[0]));
+        assert_ne!(first_moves[1], first_moves[2]);
+        assert!(result
+            .lines
+            .windows(2)
+            .all(|pair| pair[0].score.value() >= pair[1].score.value()));
+        assert_eq!(&reported[..3], [(1, 1), (1, 2), (1, 3)]);
+    }
+
+    #[test]
+    fn test_best_thread() {
+        let result = |best_move: &str, score, depth| SearchResult {
+            best_move: Some(
+                ChessBoard::from_fen(crate::chess_board::START_FEN)
+                    .parse_move(best_move)
+                    .unwrap(),
+            ),
+            score,
+            pv: Vec::new(),
+            lines: Vec::new(),
+            depth,
+            nodes: 100,
+        };
+        let best = best_thread(vec![
+            result("e2e4", Score::Centipawns(30), 8),
+            result("d2d4", Score::Centipawns(20), 9),
+            result("g1f3", Score::Centipawns(40), 9),
+            result("c2c4", Score::Mate(3), 7),
+        ]);
+        assert_eq!(best.best_move.unwrap().to_string(), "g1f3");
+        assert_eq!(best.nodes, 400);
+    }
+
+    #[test]
+    fn test_repetition_is_a_draw() {
+        // Black is lost, but the king going back to g8 repeats the position
+        // from four plies ago
+        let mut board = ChessBoard::from_fen("7k/8/8/8/8/8/8/KQ6 b - - 0 1");
+        let mut history = Vec::new();
+        for notation in ["h8g8", "b1c1", "g8h8", "c1b1"] {
+            let chess_move = board.parse_move(notation).unwrap();
+            history.push(board.make_move(chess_move).hash);
+        }
+        let limits = SearchLimits {
+            depth: Some(3),
+            ..SearchLimits::default()
+        };
+        let stop = AtomicBool::new(false);
+        let mut search_with = |history: &[u64]| {
+            let tt = TranspositionTable::new(1);
+            let params = SearchParams::default();
+            search(
+                &mut board,
+                history,
+                &limits,
+                &params,
+                &tt,
+                None,
+                &stop,
+                |_| {},
+            )
+        };
+
+        let result = search_with(&history);
+        assert_eq!(result.score, Score::Centipawns(0));
+        assert_eq!(result.best_move.unwrap().to_string(), "h8g8");
+
+        // Without the game's moves there's nothing to repeat
+        let result = search_with(&[]);
+        assert!(result.score.value() < -500, "{:?}", result.score);
+    }
+}
@@ -0,0 +1,246 @@
+use crate::{
+    chess_board::{ChessBoard, SquareIndex, TOTAL_FILES, TOTAL_RANKS, TOTAL_SQUARES},
+    chess_move::ChessMove,
+    piece::{piece_value, Colour},
+};
+
+static STRAIGHT: [(i8, i8); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+static DIAGONAL: [(i8, i8); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+static KNIGHT: [(i8, i8); 8] = [
+    (1, 2),
+    (-1, -2),
+    (2, 1),
+    (-2, -1),
+    (1, -2),
+    (-1, 2),
+    (2, -1),
+    (-2, 1),
+];
+static KING: [(i8, i8); 8] = [
+    (1, 0),
+    (0, 1),
+    (-1, 0),
+    (0, -1),
+    (1, 1),
+    (1, -1),
+    (-1, 1),
+    (-1, -1),
+];
+
+// Centipawns, with the king worth more than anything it could win
+fn exchange_value(symbol: char) -> i32 {
+    match symbol.to_ascii_uppercase() {
+        'K' => 10_000,
+        _ => piece_value(symbol) * 100,
+    }
+}
+
+fn colour_of(symbol: char) -> Colour {
+    if symbol.is_ascii_lowercase() {
+        Colour::Black
+    } else {
+        Colour::White
+    }
+}
+
+fn offset(square: SquareIndex, df: i8, dr: i8) -> Option<SquareIndex> {
+    let (file, rank) = ChessBoard::square_to_file_and_rank(square);
+    let file = file as i8 + df;
+    let rank = rank as i8 + dr;
+    if !(0..TOTAL_FILES as i8).contains(&file) || !(0..TOTAL_RANKS as i8).contains(&rank) {
+        return None;
+    }
+    ChessBoard::square_from_file_and_rank(file as u8, rank as u8)
+}
+
+// The cheapest piece of `colour` attacking `target`. Works on a copy of the
+// board with pieces taken off as they capture, so x-ray attackers lined up
+// behind them join in.
+fn least_valuable_attacker(
+    squares: &[Option<char>],
+    target: SquareIndex,
+    colour: Colour,
+) -> Option<(SquareIndex, char)> {
+    let mut best: Option<(SquareIndex, char)> = None;
+    let mut consider = |square: SquareIndex, wanted: &[char]| {
+        if let Some(symbol) = squares[square as usize] {
+            if colour_of(symbol) == colour
+                && wanted.contains(&symbol.to_ascii_uppercase())
+                && best.is_none_or(|(_, current)| exchange_value(symbol) < exchange_value(current))
+            {
+                best = Some((square, symbol));
+            }
+        }
+    };
+
+    let pawn_rank = if colour == Colour::White { -1 } else { 1 };
+    for df in [-1, 1] {
+        if let Some(square) = offset(target, df, pawn_rank) {
+            consider(square, &['P']);
+        }
+    }
+    for (df, dr) in KNIGHT.iter() {
+        if let Some(square) = offset(target, *df, *dr) {
+            consider(square, &['N']);
+        }
+    }
+    for (directions, slider) in [(&STRAIGHT, 'R'), (&DIAGONAL, 'B')] {
+        for (df, dr) in directions.iter() {
+            let mut distance = 1;
+            while let Some(square) = offset(target, df * distance, dr * distance) {
+                if squares[square as usize].is_some() {
+                    consider(square, &[slider, 'Q']);
+                    break;
+                }
+                distance += 1;
+            }
+        }
+    }
+    for (df, dr) in KING.iter() {
+        if let Some(square) = offset(target, *df, *dr) {
+            consider(square, &['K']);
+        }
+    }
+
+    best
+}
+
+// Static exchange evaluation: what the mover expects to win or lose, in
+// centipawns, if both sides keep recapturing on the destination square with
+// their cheapest piece for as long as it pays. Pins are ignored.
+pub fn see(board: &ChessBoard, chess_move: ChessMove) -> i32 {
+    let mut squares: Vec<Option<char>> = (0..TOTAL_SQUARES)
+        .map(|square| {
+            board
+                .get_piece(square)
+                .as_ref()
+                .map(|piece| piece.get_symbol())
+        })
+        .collect();
+
+    let target = chess_move.destination;
+    let mover = match squares[chess_move.source as usize] {
+        Some(symbol) => symbol,
+        None => return 0,
+    };
+
+    let mut gain = [0; 32];
+    if let Some(captured) = squares[target as usize] {
+        gain[0] = exchange_value(captured);
+    } else if mover.eq_ignore_ascii_case(&'P') && board.en_passant() == Some(target) {
+        gain[0] = exchange_value('P');
+        let (file, _) = ChessBoard::square_to_file_and_rank(target);
+        let (_, rank) = ChessBoard::square_to_file_and_rank(chess_move.source);
+        if let Some(square) = ChessBoard::square_from_file_and_rank(file, rank) {
+            squares[square as usize] = None;
+        }
+    }
+
+    let mut on_square = mover;
+    if let Some(promotion) = chess_move.promotion {
+        gain[0] += exchange_value(promotion) - exchange_value('P');
+        on_square = promotion;
+    }
+    squares[chess_move.source as usize] = None;
+
+    let mut colour = colour_of(mover).opposite();
+    let mut depth = 0;
+    while depth + 1 < gain.len() {
+        let (square, attacker) = match least_valuable_attacker(&squares, target, colour) {
+            Some(attacker) => attacker,
+            None => break,
+        };
+
+        // A king can't take back if the other side still covers the square
+        if attacker.eq_ignore_ascii_case(&'K') {
+            squares[square as usize] = None;
+            if least_valuable_attacker(&squares, target, colour.opposite()).is_some() {
+                break;
+            }
+        }
+
+        depth += 1;
+        gain[depth] = exchange_value(on_square) - gain[depth - 1];
+        on_square = attacker;
+        squares[square as usize] = None;
+        colour = colour.opposite();
+    }
+
+    // Either side can stop capturing when carrying on would lose out
+    while depth > 0 {
+        gain[depth - 1] = -(-gain[depth - 1]).max(gain[depth]);
+        depth -= 1;
+    }
+
+    gain[0]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn see_notation(fen: &str, notation: &str) -> i32 {
+        let mut board = ChessBoard::from_fen(fen);
+        let chess_move = board.parse_move(notation).unwrap();
+        see(&board, chess_move)
+    }
+
+    #[test]
+    fn test_see_undefended() {
+        assert_eq!(
+            see_notation("1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1", "e1e5"),
+            100
+        );
+    }
+
+    #[test]
+    fn test_see_defended() {
+        // Knight takes a pawn defended by a pawn
+        assert_eq!(
+            see_notation("4k3/8/3p4/4p3/8/5N2/8/4K3 w - - 0 1", "f3e5"),
+            -200
+        );
+        // Quiet move onto an attacked square
+        assert_eq!(
+            see_notation("4k3/8/3p4/8/8/8/8/2R1K3 w - - 0 1", "c1c5"),
+            -500
+        );
+    }
+
+    #[test]
+    fn test_see_x_ray() {
+        // Pieces lined up behind the first attacker join the exchange
+        assert_eq!(
+            see_notation(
+                "1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1",
+                "d3e5"
+            ),
+            -200
+        );
+        assert_eq!(
+            see_notation("3r3k/8/8/3p4/8/8/3R4/3RK3 w - - 0 1", "d2d5"),
+            100
+        );
+    }
+
+    #[test]
+    fn test_see_king_recapture() {
+        // The king can't take back on a square the rook still covers
+        assert_eq!(
+            see_notation("8/8/8/8/3k4/4p3/4R3/4R1K1 w - - 0 1", "e2e3"),
+            100
+        );
+    }
+
+    #[test]
+    fn test_see_promotion_and_en_passant() {
+        assert_eq!(
+            see_notation("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1", "b7b8q"),
+            800
+        );
+        assert_eq!(
+            see_notation("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6"),
+            100
+        );
+    }
+}
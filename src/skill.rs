@@ -0,0 +1,172 @@
+use crate::{
+    chess_move::ChessMove,
+    random::Random,
+    search::{PvLine, SearchLimits, SearchParams},
+};
+
+pub const MAX_SKILL_LEVEL: u32 = 20;
+pub const MIN_ELO: u32 = 1350;
+pub const MAX_ELO: u32 = 2850;
+
+// Best lines a weakened engine chooses between
+const LINES: usize = 4;
+
+// Centipawns of noise on the scores for each level below full strength
+const NOISE_PER_LEVEL: f64 = 15.0;
+
+// How well the engine plays, from a level of 0 to 20 or a target Elo. Below
+// full strength it looks less deep and picks among the best few moves,
+// sometimes a clearly worse one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Skill {
+    pub level: u32,
+    // Play to the Elo rather than the level
+    pub limit_strength: bool,
+    pub elo: u32,
+}
+
+impl Default for Skill {
+    fn default() -> Self {
+        Skill {
+            level: MAX_SKILL_LEVEL,
+            limit_strength: false,
+            elo: MAX_ELO,
+        }
+    }
+}
+
+impl Skill {
+    // An Elo usually lands between two levels
+    fn effective_level(&self) -> f64 {
+        if self.limit_strength {
+            let elo = self.elo.clamp(MIN_ELO, MAX_ELO) - MIN_ELO;
+            elo as f64 * MAX_SKILL_LEVEL as f64 / (MAX_ELO - MIN_ELO) as f64
+        } else {
+            self.level.min(MAX_SKILL_LEVEL) as f64
+        }
+    }
+
+    pub fn is_weakened(&self) -> bool {
+        self.effective_level() < MAX_SKILL_LEVEL as f64
+    }
+
+    // Cuts the search down to the level
+    pub fn limit(&self, limits: &mut SearchLimits, params: &mut SearchParams) {
+        if !self.is_weakened() {
+            return;
+        }
+        let depth = 1 + self.effective_level() as u32;
+        limits.depth = Some(limits.depth.map_or(depth, |limit| limit.min(depth)));
+        params.multi_pv = params.multi_pv.max(LINES);
+    }
+
+    // Chooses from the lines a search found, with random noise on each
+    // score that grows the weaker the level, so a weak engine sometimes
+    // settles for a clearly worse move
+    pub fn pick(&self, lines: &[PvLine], random: &mut Random) -> Option<ChessMove> {
+        let noise = ((MAX_SKILL_LEVEL as f64 - self.effective_level()) * NOISE_PER_LEVEL) as u64;
+
+        let mut best = None;
+        let mut best_score = i64::MIN;
+        for line in lines.iter().take(LINES) {
+            let score = line.score.value() as i64 + random.below(noise + 1) as i64;
+            if score > best_score {
+                best_score = score;
+                best = line.pv.first().copied();
+            }
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::Score;
+
+    fn lines(scores: &[i32]) -> Vec<PvLine> {
+        scores
+            .iter()
+            .enumerate()
+            .map(|(i, &score)| PvLine {
+                score: Score::Centipawns(score),
+                pv: vec![ChessMove::new(8 + i as u8, 16 + i as u8)],
+            })
+            .collect()
+    }
+
+    fn picks(skill: Skill, scores: &[i32]) -> Vec<usize> {
+        let lines = lines(scores);
+        let mut random = Random::new(7);
+        let mut counts = vec![0; scores.len()];
+        for _ in 0..1000 {
+            let chosen = skill.pick(&lines, &mut random).unwrap();
+            counts[(chosen.source - 8) as usize] += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn test_levels_and_elo() {
+        assert!(!Skill::default().is_weakened());
+        let skill = Skill {
+            level: 5,
+            ..Skill::default()
+        };
+        let mut limits = SearchLimits::default();
+        let mut params = SearchParams::default();
+        skill.limit(&mut limits, &mut params);
+        assert_eq!(limits.depth, Some(6));
+        assert_eq!(params.multi_pv, LINES);
+
+        // A tighter limit that's already there stays
+        let mut limits = SearchLimits {
+            depth: Some(3),
+            ..SearchLimits::default()
+        };
+        skill.limit(&mut limits, &mut params);
+        assert_eq!(limits.depth, Some(3));
+
+        // The Elo takes over from the level when asked to
+        let elo = |elo| {
+            Skill {
+                limit_strength: true,
+                elo,
+                ..Skill::default()
+            }
+            .effective_level()
+        };
+        assert_eq!(elo(MIN_ELO), 0.0);
+        assert_eq!(elo(2100), 10.0);
+        assert_eq!(elo(MAX_ELO), 20.0);
+        assert_eq!(elo(100), 0.0);
+    }
+
+    #[test]
+    fn test_pick() {
+        let scores = [50, 40, 35, -200];
+        let full = picks(Skill::default(), &scores);
+        assert_eq!(full, [1000, 0, 0, 0]);
+
+        // Weak levels stray from the best move, strong ones hardly ever do
+        let weak = picks(
+            Skill {
+                level: 0,
+                ..Skill::default()
+            },
+            &scores,
+        );
+        let strong = picks(
+            Skill {
+                level: 19,
+                ..Skill::default()
+            },
+            &scores,
+        );
+        assert!(weak[0] < 900, "{:?}", weak);
+        assert!(weak[3] > 0, "{:?}", weak);
+        assert_eq!(strong[3], 0);
+        assert!(strong[0] > weak[0], "{:?} {:?}", strong, weak);
+        assert_eq!(weak.iter().sum::<usize>(), 1000);
+    }
+}
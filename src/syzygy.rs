@@ -0,0 +1,1745 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::{
+    chess_board::{ChessBoard, SquareIndex, TOTAL_SQUARES},
+    chess_move::ChessMove,
+    piece::Colour,
+};
+
+// Probes Syzygy endgame tablebases: WDL tables for whether a position is won,
+// drawn or lost, and DTZ tables for how many plies until the next capture or
+// pawn move that keeps the result. The file format and the encoding below
+// follow Ronald de Man's original prober.
+
+// The largest tables there are
+pub const MAX_PIECES: usize = 7;
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xe8, 0x23, 0x5d];
+const DTZ_MAGIC: [u8; 4] = [0xd7, 0x66, 0x0c, 0xa5];
+
+// Layout flags in the header
+const SPLIT: u8 = 1;
+const HAS_PAWNS: u8 = 2;
+
+// Flags for each subtable
+const STM: u8 = 1;
+const MAPPED: u8 = 2;
+const WIN_PLIES: u8 = 4;
+const LOSS_PLIES: u8 = 8;
+const WIDE_DTZ: u8 = 16;
+const SINGLE_VALUE: u8 = 128;
+
+// Roles as numbered in the tables, used to index piece counts less one
+const PAWN: u8 = 1;
+const KING: u8 = 6;
+const ROLE_LETTERS: [char; 6] = ['P', 'N', 'B', 'R', 'Q', 'K'];
+
+// Win, draw or loss for the side to move. Cursed wins and blessed losses are
+// wins and losses that the fifty move rule turns into draws.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Wdl {
+    Loss = -2,
+    BlessedLoss = -1,
+    Draw = 0,
+    CursedWin = 1,
+    Win = 2,
+}
+
+impl Wdl {
+    fn from_value(value: u16) -> Option<Wdl> {
+        Some(match value {
+            0 => Wdl::Loss,
+            1 => Wdl::BlessedLoss,
+            2 => Wdl::Draw,
+            3 => Wdl::CursedWin,
+            4 => Wdl::Win,
+            _ => return None,
+        })
+    }
+
+    // The same result from the other side
+    pub fn flip(self) -> Wdl {
+        match self {
+            Wdl::Loss => Wdl::Win,
+            Wdl::BlessedLoss => Wdl::CursedWin,
+            Wdl::Draw => Wdl::Draw,
+            Wdl::CursedWin => Wdl::BlessedLoss,
+            Wdl::Win => Wdl::Loss,
+        }
+    }
+
+    // DTZ of a position whose best move is a capture or pawn move
+    fn dtz_before_zeroing(self) -> i32 {
+        match self {
+            Wdl::Loss => -1,
+            Wdl::BlessedLoss => -101,
+            Wdl::Draw => 0,
+            Wdl::CursedWin => 101,
+            Wdl::Win => 1,
+        }
+    }
+
+    // Index into the DTZ value maps
+    fn map_index(self) -> usize {
+        match self {
+            Wdl::Win => 0,
+            Wdl::Loss => 1,
+            Wdl::CursedWin => 2,
+            Wdl::BlessedLoss | Wdl::Draw => 3,
+        }
+    }
+}
+
+// Plies further from zeroing, away from 0 whichever the sign
+fn add_plies(dtz: i32, plies: i32) -> i32 {
+    match dtz.signum() {
+        1 => dtz + plies,
+        -1 => dtz - plies,
+        _ => 0,
+    }
+}
+
+fn binomial(mut n: u64, k: u64) -> u64 {
+    if k > n {
+        return 0;
+    }
+    if k > n - k {
+        return binomial(n, n - k);
+    }
+    let mut result = 1;
+    for d in 1..=k {
+        result = result * n / d;
+        n -= 1;
+    }
+    result
+}
+
+fn file_of(square: SquareIndex) -> u8 {
+    square & 7
+}
+
+fn rank_of(square: SquareIndex) -> u8 {
+    square >> 3
+}
+
+fn flip_vertical(square: SquareIndex) -> SquareIndex {
+    square ^ 56
+}
+
+fn flip_horizontal(square: SquareIndex) -> SquareIndex {
+    square ^ 7
+}
+
+// Mirrors in the a1-h8 diagonal
+fn flip_diagonal(square: SquareIndex) -> SquareIndex {
+    ((square >> 3) | (square << 3)) & 63
+}
+
+fn off_diagonal(square: SquareIndex) -> bool {
+    file_of(square) != rank_of(square)
+}
+
+// Maps squares into the a1-d1-d4 triangle
+#[rustfmt::skip]
+const TRIANGLE: [u64; 64] = [
+    6, 0, 1, 2, 2, 1, 0, 6,
+    0, 7, 3, 4, 4, 3, 7, 0,
+    1, 3, 8, 5, 5, 8, 3, 1,
+    2, 4, 5, 9, 9, 5, 4, 2,
+    2, 4, 5, 9, 9, 5, 4, 2,
+    1, 3, 8, 5, 5, 8, 3, 1,
+    0, 7, 3, 4, 4, 3, 7, 0,
+    6, 0, 1, 2, 2, 1, 0, 6,
+];
+
+// The inverse of TRIANGLE
+const INV_TRIANGLE: [usize; 10] = [1, 2, 3, 10, 11, 19, 0, 9, 18, 27];
+
+// Maps the b1-h1-h7 triangle to 0 to 27
+#[rustfmt::skip]
+const LOWER: [u64; 64] = [
+    28,  0,  1,  2,  3,  4,  5,  6,
+     0, 29,  7,  8,  9, 10, 11, 12,
+     1,  7, 30, 13, 14, 15, 16, 17,
+     2,  8, 13, 31, 18, 19, 20, 21,
+     3,  9, 14, 18, 32, 22, 23, 24,
+     4, 10, 15, 19, 22, 33, 25, 26,
+     5, 11, 16, 20, 23, 25, 34, 27,
+     6, 12, 17, 21, 24, 26, 27, 35,
+];
+
+// Orders the squares for groups of identical pieces
+#[rustfmt::skip]
+const MULT_TWIST: [u64; 64] = [
+    15, 63, 55, 47, 40, 48, 56, 12,
+    62, 11, 39, 31, 24, 32,  8, 57,
+    54, 38,  7, 23, 16,  4, 33, 49,
+    46, 30, 22,  3,  0, 17, 25, 41,
+    45, 29, 21,  2,  1, 18, 26, 42,
+    53, 37,  6, 20, 19,  5, 34, 50,
+    61, 10, 36, 28, 27, 35,  9, 58,
+    14, 60, 52, 44, 43, 51, 59, 13,
+];
+
+// Unused entries
+const Z0: u64 = u64::MAX;
+
+// Every legal placing of the two kings, with the first in the a1-d1-d4
+// triangle
+#[rustfmt::skip]
+const KK_IDX: [[u64; 64]; 10] = [[
+     Z0,  Z0,  Z0,   0,   1,   2,   3,   4,
+     Z0,  Z0,  Z0,   5,   6,   7,   8,   9,
+     10,  11,  12,  13,  14,  15,  16,  17,
+     18,  19,  20,  21,  22,  23,  24,  25,
+     26,  27,  28,  29,  30,  31,  32,  33,
+     34,  35,  36,  37,  38,  39,  40,  41,
+     42,  43,  44,  45,  46,  47,  48,  49,
+     50,  51,  52,  53,  54,  55,  56,  57,
+], [
+     58,  Z0,  Z0,  Z0,  59,  60,  61,  62,
+     63,  Z0,  Z0,  Z0,  64,  65,  66,  67,
+     68,  69,  70,  71,  72,  73,  74,  75,
+     76,  77,  78,  79,  80,  81,  82,  83,
+     84,  85,  86,  87,  88,  89,  90,  91,
+     92,  93,  94,  95,  96,  97,  98,  99,
+    100, 101, 102, 103, 104, 105, 106, 107,
+    108, 109, 110, 111, 112, 113, 114, 115,
+], [
+    116, 117,  Z0,  Z0,  Z0, 118, 119, 120,
+    121, 122,  Z0,  Z0,  Z0, 123, 124, 125,
+    126, 127, 128, 129, 130, 131, 132, 133,
+    134, 135, 136, 137, 138, 139, 140, 141,
+    142, 143, 144, 145, 146, 147, 148, 149,
+    150, 151, 152, 153, 154, 155, 156, 157,
+    158, 159, 160, 161, 162, 163, 164, 165,
+    166, 167, 168, 169, 170, 171, 172, 173,
+], [
+    174,  Z0,  Z0,  Z0, 175, 176, 177, 178,
+    179,  Z0,  Z0,  Z0, 180, 181, 182, 183,
+    184,  Z0,  Z0,  Z0, 185, 186, 187, 188,
+    189, 190, 191, 192, 193, 194, 195, 196,
+    197, 198, 199, 200, 201, 202, 203, 204,
+    205, 206, 207, 208, 209, 210, 211, 212,
+    213, 214, 215, 216, 217, 218, 219, 220,
+    221, 222, 223, 224, 225, 226, 227, 228,
+], [
+    229, 230,  Z0,  Z0,  Z0, 231, 232, 233,
+    234, 235,  Z0,  Z0,  Z0, 236, 237, 238,
+    239, 240,  Z0,  Z0,  Z0, 241, 242, 243,
+    244, 245, 246, 247, 248, 249, 250, 251,
+    252, 253, 254, 255, 256, 257, 258, 259,
+    260, 261, 262, 263, 264, 265, 266, 267,
+    268, 269, 270, 271, 272, 273, 274, 275,
+    276, 277, 278, 279, 280, 281, 282, 283,
+], [
+    284, 285, 286, 287, 288, 289, 290, 291,
+    292, 293,  Z0,  Z0,  Z0, 294, 295, 296,
+    297, 298,  Z0,  Z0,  Z0, 299, 300, 301,
+    302, 303,  Z0,  Z0,  Z0, 304, 305, 306,
+    307, 308, 309, 310, 311, 312, 313, 314,
+    315, 316, 317, 318, 319, 320, 321, 322,
+    323, 324, 325, 326, 327, 328, 329, 330,
+    331, 332, 333, 334, 335, 336, 337, 338,
+], [
+     Z0,  Z0, 339, 340, 341, 342, 343, 344,
+     Z0,  Z0, 345, 346, 347, 348, 349, 350,
+     Z0,  Z0, 441, 351, 352, 353, 354, 355,
+     Z0,  Z0,  Z0, 442, 356, 357, 358, 359,
+     Z0,  Z0,  Z0,  Z0, 443, 360, 361, 362,
+     Z0,  Z0,  Z0,  Z0,  Z0, 444, 363, 364,
+     Z0,  Z0,  Z0,  Z0,  Z0,  Z0, 445, 365,
+     Z0,  Z0,  Z0,  Z0,  Z0,  Z0,  Z0, 446,
+], [
+     Z0,  Z0,  Z0, 366, 367, 368, 369, 370,
+     Z0,  Z0,  Z0, 371, 372, 373, 374, 375,
+     Z0,  Z0,  Z0, 376, 377, 378, 379, 380,
+     Z0,  Z0,  Z0, 447, 381, 382, 383, 384,
+     Z0,  Z0,  Z0,  Z0, 448, 385, 386, 387,
+     Z0,  Z0,  Z0,  Z0,  Z0, 449, 388, 389,
+     Z0,  Z0,  Z0,  Z0,  Z0,  Z0, 450, 390,
+     Z0,  Z0,  Z0,  Z0,  Z0,  Z0,  Z0, 451,
+], [
+    452, 391, 392, 393, 394, 395, 396, 397,
+     Z0,  Z0,  Z0,  Z0, 398, 399, 400, 401,
+     Z0,  Z0,  Z0,  Z0, 402, 403, 404, 405,
+     Z0,  Z0,  Z0,  Z0, 406, 407, 408, 409,
+     Z0,  Z0,  Z0,  Z0, 453, 410, 411, 412,
+     Z0,  Z0,  Z0,  Z0,  Z0, 454, 413, 414,
+     Z0,  Z0,  Z0,  Z0,  Z0,  Z0, 455, 415,
+     Z0,  Z0,  Z0,  Z0,  Z0,  Z0,  Z0, 456,
+], [
+    457, 416, 417, 418, 419, 420, 421, 422,
+     Z0, 458, 423, 424, 425, 426, 427, 428,
+     Z0,  Z0,  Z0,  Z0,  Z0, 429, 430, 431,
+     Z0,  Z0,  Z0,  Z0,  Z0, 432, 433, 434,
+     Z0,  Z0,  Z0,  Z0,  Z0, 435, 436, 437,
+     Z0,  Z0,  Z0,  Z0,  Z0, 459, 438, 439,
+     Z0,  Z0,  Z0,  Z0,  Z0,  Z0, 460, 440,
+     Z0,  Z0,  Z0,  Z0,  Z0,  Z0,  Z0, 461,
+]];
+
+// Pairs of identical pieces
+#[rustfmt::skip]
+const PP_IDX: [[u64; 64]; 10] = [[
+      0,  Z0,   1,   2,   3,   4,   5,   6,
+      7,   8,   9,  10,  11,  12,  13,  14,
+     15,  16,  17,  18,  19,  20,  21,  22,
+     23,  24,  25,  26,  27,  28,  29,  30,
+     31,  32,  33,  34,  35,  36,  37,  38,
+     39,  40,  41,  42,  43,  44,  45,  46,
+     Z0,  47,  48,  49,  50,  51,  52,  53,
+     54,  55,  56,  57,  58,  59,  60,  61,
+], [
+     62,  Z0,  Z0,  63,  64,  65,  Z0,  66,
+     Z0,  67,  68,  69,  70,  71,  72,  Z0,
+     73,  74,  75,  76,  77,  78,  79,  80,
+     81,  82,  83,  84,  85,  86,  87,  88,
+     89,  90,  91,  92,  93,  94,  95,  96,
+     Z0,  97,  98,  99, 100, 101, 102, 103,
+     Z0, 104, 105, 106, 107, 108, 109,  Z0,
+    110,  Z0, 111, 112, 113, 114,  Z0, 115,
+], [
+    116,  Z0,  Z0,  Z0, 117,  Z0,  Z0, 118,
+     Z0, 119, 120, 121, 122, 123, 124,  Z0,
+     Z0, 125, 126, 127, 128, 129, 130,  Z0,
+    131, 132, 133, 134, 135, 136, 137, 138,
+     Z0, 139, 140, 141, 142, 143, 144, 145,
+     Z0, 146, 147, 148, 149, 150, 151,  Z0,
+     Z0, 152, 153, 154, 155, 156, 157,  Z0,
+    158,  Z0,  Z0, 159, 160,  Z0,  Z0, 161,
+], [
+    162,  Z0,  Z0,  Z0,  Z0,  Z0,  Z0, 163,
+     Z0, 164,  Z0, 165, 166, 167, 168,  Z0,
+     Z0, 169, 170, 171, 172, 173, 174,  Z0,
+     Z0, 175, 176, 177, 178, 179, 180,  Z0,
+     Z0, 181, 182, 183, 184, 185, 186,  Z0,
+     Z0,  Z0, 187, 188, 189, 190, 191,  Z0,
+     Z0, 192, 193, 194, 195, 196, 197,  Z0,
+    198,  Z0,  Z0,  Z0,  Z0,  Z0,  Z0, 199,
+], [
+    200,  Z0,  Z0,  Z0,  Z0,  Z0,  Z0, 201,
+     Z0, 202,  Z0,  Z0, 203,  Z0, 204,  Z0,
+     Z0,  Z0, 205, 206, 207, 208,  Z0,  Z0,
+     Z0, 209, 210, 211, 212, 213, 214,  Z0,
+     Z0,  Z0, 215, 216, 217, 218, 219,  Z0,
+     Z0,  Z0, 220, 221, 222, 223,  Z0,  Z0,
+     Z0, 224,  Z0, 225, 226,  Z0, 227,  Z0,
+    228,  Z0,  Z0,  Z0,  Z0,  Z0,  Z0, 229,
+], [
+    230,  Z0,  Z0,  Z0,  Z0,  Z0,  Z0, 231,
+     Z0, 232,  Z0,  Z0,  Z0,  Z0, 233,  Z0,
+     Z0,  Z0, 234,  Z0, 235, 236,  Z0,  Z0,
+     Z0,  Z0, 237, 238, 239, 240,  Z0,  Z0,
+     Z0,  Z0,  Z0, 241, 242, 243,  Z0,  Z0,
+     Z0,  Z0, 244, 245, 246, 247,  Z0,  Z0,
+     Z0, 248,  Z0,  Z0,  Z0,  Z0, 249,  Z0,
+    250,  Z0,  Z0,  Z0,  Z0,  Z0,  Z0, 251,
+], [
+     Z0,  Z0,  Z0,  Z0,  Z0,  Z0,  Z0, 259,
+     Z0, 252,  Z0,  Z0,  Z0,  Z0, 260,  Z0,
+     Z0,  Z0, 253,  Z0,  Z0, 261,  Z0,  Z0,
+     Z0,  Z0,  Z0, 254, 262,  Z0,  Z0,  Z0,
+     Z0,  Z0,  Z0,  Z0, 255,  Z0,  Z0,  Z0,
+     Z0,  Z0,  Z0,  Z0,  Z0, 256,  Z0,  Z0,
+     Z0,  Z0,  Z0,  Z0,  Z0,  Z0, 257,  Z0,
+     Z0,  Z0,  Z0,  Z0,  Z0,  Z0,  Z0, 258,
+], [
+     Z0,  Z0,  Z0,  Z0,  Z0,  Z0,  Z0,  Z0,
+     Z0,  Z0,  Z0,  Z0,  Z0,  Z0, 268,  Z0,
+     Z0,  Z0, 263,  Z0,  Z0, 269,  Z0,  Z0,
+     Z0,  Z0,  Z0, 264, 270,  Z0,  Z0,  Z0,
+     Z0,  Z0,  Z0,  Z0, 265,  Z0,  Z0,  Z0,
+     Z0,  Z0,  Z0,  Z0,  Z0, 266,  Z0,  Z0,
+     Z0,  Z0,  Z0,  Z0,  Z0,  Z0, 267,  Z0,
+     Z0,  Z0,  Z0,  Z0,  Z0,  Z0,  Z0,  Z0,
+], [
+     Z0,  Z0,  Z0,  Z0,  Z0,  Z0,  Z0,  Z0,
+     Z0,  Z0,  Z0,  Z0,  Z0,  Z0,  Z0,  Z0,
+     Z0,  Z0,  Z0,  Z0,  Z0, 274,  Z0,  Z0,
+     Z0,  Z0,  Z0, 271, 275,  Z0,  Z0,  Z0,
+     Z0,  Z0,  Z0,  Z0, 272,  Z0,  Z0,  Z0,
+     Z0,  Z0,  Z0,  Z0,  Z0, 273,  Z0,  Z0,
+     Z0,  Z0,  Z0,  Z0,  Z0,  Z0,  Z0,  Z0,
+     Z0,  Z0,  Z0,  Z0,  Z0,  Z0,  Z0,  Z0,
+], [
+     Z0,  Z0,  Z0,  Z0,  Z0,  Z0,  Z0,  Z0,
+     Z0,  Z0,  Z0,  Z0,  Z0,  Z0,  Z0,  Z0,
+     Z0,  Z0,  Z0,  Z0,  Z0,  Z0,  Z0,  Z0,
+     Z0,  Z0,  Z0,  Z0, 277,  Z0,  Z0,  Z0,
+     Z0,  Z0,  Z0,  Z0, 276,  Z0,  Z0,  Z0,
+     Z0,  Z0,  Z0,  Z0,  Z0,  Z0,  Z0,  Z0,
+     Z0,  Z0,  Z0,  Z0,  Z0,  Z0,  Z0,  Z0,
+     Z0,  Z0,  Z0,  Z0,  Z0,  Z0,  Z0,  Z0
+]];
+
+// The a5-a7-c5 triangle
+const TEST45: [SquareIndex; 6] = [32, 33, 34, 40, 41, 48];
+
+// Index tables for the leading groups, built once
+struct Consts {
+    mult_idx: [[u64; 10]; 5],
+    mult_factor: [u64; 5],
+    map_pawns: [u64; 64],
+    lead_pawn_idx: [[u64; 64]; 6],
+    lead_pawns_size: [[u64; 4]; 6],
+}
+
+fn consts() -> &'static Consts {
+    static CONSTS: OnceLock<Consts> = OnceLock::new();
+    CONSTS.get_or_init(|| {
+        let mut mult_idx = [[0; 10]; 5];
+        let mut mult_factor = [0; 5];
+        for i in 0..5 {
+            let mut total = 0;
+            for j in 0..10 {
+                mult_idx[i][j] = total;
+                total += if i == 0 {
+                    1
+                } else {
+                    binomial(MULT_TWIST[INV_TRIANGLE[j]], i as u64)
+                };
+            }
+            mult_factor[i] = total;
+        }
+
+        let mut available_squares = 48;
+        let mut map_pawns = [0; 64];
+        let mut lead_pawn_idx = [[0; 64]; 6];
+        let mut lead_pawns_size = [[0; 4]; 6];
+        for lead_pawns in 1..=5 {
+            for (file, size) in lead_pawns_size[lead_pawns].iter_mut().enumerate() {
+                let mut idx = 0;
+                for rank in 1..7 {
+                    let square = file + 8 * rank;
+                    if lead_pawns == 1 {
+                        available_squares -= 1;
+                        map_pawns[square] = available_squares;
+                        available_squares -= 1;
+                        map_pawns[square ^ 7] = available_squares;
+                    }
+                    lead_pawn_idx[lead_pawns][square] = idx;
+                    idx += binomial(map_pawns[square], lead_pawns as u64 - 1);
+                }
+                *size = idx;
+            }
+        }
+
+        Consts {
+            mult_idx,
+            mult_factor,
+            map_pawns,
+            lead_pawn_idx,
+            lead_pawns_size,
+        }
+    })
+}
+
+// A piece as the tables describe it, white or black being the first or
+// second side of the table rather than the board
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct TablePiece {
+    white: bool,
+    role: u8,
+}
+
+// How many of each piece each side has: the name of a table
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct Material {
+    // By colour, then by role less one
+    counts: [[u8; 6]; 2],
+}
+
+impl Material {
+    fn from_name(name: &str) -> Option<Material> {
+        let (white, black) = name.split_once('v')?;
+        let mut counts = [[0; 6]; 2];
+        for (side, letters) in [white, black].into_iter().enumerate() {
+            for letter in letters.chars() {
+                let role = ROLE_LETTERS.iter().position(|&role| role == letter)?;
+                counts[side][role] += 1;
+            }
+        }
+        Some(Material { counts })
+    }
+
+    fn from_board(board: &ChessBoard) -> Material {
+        let mut counts = [[0; 6]; 2];
+        for square in 0..TOTAL_SQUARES {
+            if let Some(piece) = board.get_piece(square) {
+                let symbol = piece.get_symbol();
+                counts[piece.is_black() as usize][role_of(symbol) as usize - 1] += 1;
+            }
+        }
+        Material { counts }
+    }
+
+    fn from_pieces(pieces: &[TablePiece]) -> Material {
+        let mut counts = [[0; 6]; 2];
+        for piece in pieces {
+            counts[!piece.white as usize][piece.role as usize - 1] += 1;
+        }
+        Material { counts }
+    }
+
+    fn flipped(&self) -> Material {
+        Material {
+            counts: [self.counts[1], self.counts[0]],
+        }
+    }
+
+    fn count(&self) -> usize {
+        self.counts
+            .iter()
+            .flatten()
+            .map(|&count| count as usize)
+            .sum()
+    }
+
+    fn side_count(&self, side: usize) -> usize {
+        self.counts[side].iter().map(|&count| count as usize).sum()
+    }
+
+    fn side_has_pawns(&self, side: usize) -> bool {
+        self.counts[side][0] > 0
+    }
+
+    fn has_pawns(&self) -> bool {
+        self.side_has_pawns(0) || self.side_has_pawns(1)
+    }
+
+    fn is_symmetric(&self) -> bool {
+        self.counts[0] == self.counts[1]
+    }
+
+    fn unique_pieces(&self) -> usize {
+        self.counts
+            .iter()
+            .flatten()
+            .filter(|&&count| count == 1)
+            .count()
+    }
+
+    // The fewest of any piece there's more than one of
+    fn min_like_man(&self) -> usize {
+        self.counts
+            .iter()
+            .flatten()
+            .filter(|&&count| count >= 2)
+            .min()
+            .map_or(0, |&count| count as usize)
+    }
+}
+
+fn role_of(symbol: char) -> u8 {
+    match symbol.to_ascii_uppercase() {
+        'P' => 1,
+        'N' => 2,
+        'B' => 3,
+        'R' => 4,
+        'Q' => 5,
+        _ => KING,
+    }
+}
+
+// Reads from anywhere in a file without moving a shared cursor, so threads
+// can probe the same table at once
+struct TableFile {
+    file: File,
+}
+
+impl TableFile {
+    #[cfg(unix)]
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> io::Result<()> {
+        use std::os::unix::fs::FileExt;
+        self.file.read_exact_at(buffer, offset)
+    }
+
+    #[cfg(windows)]
+    fn read_at(&self, mut offset: u64, mut buffer: &mut [u8]) -> io::Result<()> {
+        use std::os::windows::fs::FileExt;
+        while !buffer.is_empty() {
+            match self.file.seek_read(buffer, offset)? {
+                0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+                read => {
+                    buffer = &mut buffer[read..];
+                    offset += read as u64;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn read_u8(&self, offset: u64) -> io::Result<u8> {
+        let mut buffer = [0; 1];
+        self.read_at(offset, &mut buffer)?;
+        Ok(buffer[0])
+    }
+
+    fn read_u16(&self, offset: u64) -> io::Result<u16> {
+        let mut buffer = [0; 2];
+        self.read_at(offset, &mut buffer)?;
+        Ok(u16::from_le_bytes(buffer))
+    }
+
+    fn read_u32(&self, offset: u64) -> io::Result<u32> {
+        let mut buffer = [0; 4];
+        self.read_at(offset, &mut buffer)?;
+        Ok(u32::from_le_bytes(buffer))
+    }
+}
+
+fn bad_table() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "corrupt table")
+}
+
+// How the pieces are split into groups that are indexed together, and the
+// multiplier for each group's index
+struct GroupData {
+    pieces: Vec<TablePiece>,
+    lens: Vec<usize>,
+    factors: Vec<u64>,
+}
+
+impl GroupData {
+    fn new(pieces: Vec<TablePiece>, order: [u8; 2], file: usize) -> io::Result<GroupData> {
+        let material = Material::from_pieces(&pieces);
+
+        // Without pawns, three unique pieces or else the kings lead. The
+        // remaining identical pieces are grouped together.
+        let first_len = if material.has_pawns() {
+            0
+        } else if material.unique_pieces() >= 3 {
+            3
+        } else if material.unique_pieces() == 2 {
+            2
+        } else {
+            material.min_like_man()
+        };
+        let mut lens = Vec::new();
+        if first_len > 0 {
+            lens.push(first_len);
+        }
+        let mut rest = &pieces[first_len.min(pieces.len())..];
+        while let Some(&first) = rest.first() {
+            let len = rest.iter().take_while(|&&piece| piece == first).count();
+            lens.push(len);
+            rest = &rest[len..];
+        }
+        if lens.is_empty() {
+            return Err(bad_table());
+        }
+
+        let both_pawns = material.side_has_pawns(0) && material.side_has_pawns(1);
+        let mut factors = vec![0; lens.len() + 1];
+        let mut free_squares = 64 - lens[0] - if both_pawns { lens[1] } else { 0 };
+        let mut next = if both_pawns { 2 } else { 1 };
+        let mut idx = 1;
+        let mut k = 0;
+        let consts = consts();
+
+        while next < lens.len() || k == order[0] || k == order[1] {
+            if k == order[0] {
+                // The leading pawns or pieces
+                factors[0] = idx;
+                idx *= if material.has_pawns() {
+                    consts.lead_pawns_size[lens[0]][file]
+                } else if material.unique_pieces() >= 3 {
+                    31_332
+                } else if material.unique_pieces() == 2 {
+                    462
+                } else if material.min_like_man() == 2 {
+                    278
+                } else {
+                    consts.mult_factor[material.min_like_man() - 1]
+                };
+            } else if k == order[1] {
+                // The other side's pawns
+                factors[1] = idx;
+                idx *= binomial(48 - lens[0] as u64, lens[1] as u64);
+            } else {
+                factors[next] = idx;
+                idx *= binomial(free_squares as u64, lens[next] as u64);
+                free_squares -= lens[next];
+                next += 1;
+            }
+            k += 1;
+        }
+        factors[lens.len()] = idx;
+
+        Ok(GroupData {
+            pieces,
+            lens,
+            factors,
+        })
+    }
+}
+
+// Remaps stored DTZ values, separately for each result
+enum DtzMap {
+    Normal { offset: u64, by_wdl: [u16; 4] },
+    Wide { offset: u64, by_wdl: [u16; 4] },
+}
+
+// One subtable: the encoding and the Huffman compression of its values
+struct PairsData {
+    flags: u8,
+    groups: GroupData,
+    // The value when the whole table is one value
+    single_value: u16,
+    block_size: u32,
+    // There's a sparse index entry about every span values
+    span: u32,
+    blocks_num: u32,
+    min_symlen: u8,
+    // Lowest symbol of each length, and the same padded to 64 bits
+    lowest_sym: Vec<u16>,
+    base: Vec<u64>,
+    // The symbol tree, three bytes a node, and how many values each
+    // symbol stands for less one
+    btree: Vec<u8>,
+    symlen: Vec<u8>,
+    sparse_index: u64,
+    sparse_index_size: u64,
+    block_lengths: u64,
+    block_length_size: u32,
+    data: u64,
+    dtz_map: Option<DtzMap>,
+}
+
+impl PairsData {
+    fn parse(
+        file: &TableFile,
+        mut ptr: u64,
+        groups: GroupData,
+        is_wdl: bool,
+    ) -> io::Result<(PairsData, u64)> {
+        let flags = file.read_u8(ptr)?;
+        let mut data = PairsData {
+            flags,
+            groups,
+            single_value: 0,
+            block_size: 0,
+            span: 0,
+            blocks_num: 0,
+            min_symlen: 0,
+            lowest_sym: Vec::new(),
+            base: Vec::new(),
+            btree: Vec::new(),
+            symlen: Vec::new(),
+            sparse_index: 0,
+            sparse_index_size: 0,
+            block_lengths: 0,
+            block_length_size: 0,
+            data: 0,
+            dtz_map: None,
+        };
+
+        if flags & SINGLE_VALUE != 0 {
+            if is_wdl {
+                data.single_value = file.read_u8(ptr + 1)? as u16;
+            }
+            return Ok((data, ptr + 2));
+        }
+
+        let mut header = [0; 10];
+        file.read_at(ptr, &mut header)?;
+        let table_size = data.groups.factors[data.groups.lens.len()];
+        if header[1] > 10 || header[2] > 31 || header[8] > 32 || header[9] > header[8] {
+            return Err(bad_table());
+        }
+        data.block_size = 1 << header[1];
+        data.span = 1 << header[2];
+        data.sparse_index_size = table_size.div_ceil(data.span as u64);
+        data.blocks_num = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        data.block_length_size = data.blocks_num + header[3] as u32;
+        let max_symlen = header[8];
+        data.min_symlen = header[9];
+        let lengths = (max_symlen - data.min_symlen + 1) as usize;
+
+        let lowest_sym = ptr + 10;
+        for i in 0..lengths {
+            data.lowest_sym
+                .push(file.read_u16(lowest_sym + 2 * i as u64)?);
+        }
+        data.base = vec![0; lengths];
+        for i in (0..lengths - 1).rev() {
+            data.base[i] = (data.base[i + 1] + data.lowest_sym[i] as u64)
+                .checked_sub(data.lowest_sym[i + 1] as u64)
+                .ok_or_else(bad_table)?
+                / 2;
+            if data.base[i] * 2 < data.base[i + 1] {
+                return Err(bad_table());
+            }
+        }
+        for (i, base) in data.base.iter_mut().enumerate() {
+            *base = base
+                .checked_shl(64 - (data.min_symlen as u32 + i as u32))
+                .ok_or_else(bad_table)?;
+        }
+
+        ptr += 10 + 2 * lengths as u64;
+        let symbols = file.read_u16(ptr)? as usize;
+        ptr += 2;
+        data.btree = vec![0; symbols * 3];
+        file.read_at(ptr, &mut data.btree)?;
+        data.symlen = vec![0; symbols];
+        let mut visited = vec![false; symbols];
+        for symbol in 0..symbols {
+            data.read_symlen(&mut visited, symbol, 16)?;
+        }
+        ptr += symbols as u64 * 3 + (symbols as u64 & 1);
+
+        Ok((data, ptr))
+    }
+
+    // Left and right children of a tree node
+    fn node(&self, symbol: usize) -> io::Result<(usize, usize)> {
+        let bytes = self
+            .btree
+            .get(3 * symbol..3 * symbol + 3)
+            .ok_or_else(bad_table)?;
+        let left = ((bytes[1] as usize & 0xf) << 8) | bytes[0] as usize;
+        let right = ((bytes[2] as usize) << 4) | (bytes[1] as usize >> 4);
+        Ok((left, right))
+    }
+
+    fn read_symlen(&mut self, visited: &mut [bool], symbol: usize, depth: u8) -> io::Result<()> {
+        if *visited.get(symbol).ok_or_else(bad_table)? {
+            return Ok(());
+        }
+        let (left, right) = self.node(symbol)?;
+        if right == 0xfff {
+            self.symlen[symbol] = 0;
+        } else {
+            let depth = depth.checked_sub(1).ok_or_else(bad_table)?;
+            self.read_symlen(visited, left, depth)?;
+            self.read_symlen(visited, right, depth)?;
+            self.symlen[symbol] = self.symlen[left]
+                .checked_add(self.symlen[right])
+                .and_then(|len| len.checked_add(1))
+                .ok_or_else(bad_table)?;
+        }
+        visited[symbol] = true;
+        Ok(())
+    }
+
+    fn symlen(&self, symbol: usize) -> io::Result<i64> {
+        self.symlen
+            .get(symbol)
+            .map(|&len| len as i64)
+            .ok_or_else(bad_table)
+    }
+
+    // The value stored at an index, found by decoding the Huffman symbols
+    // of the block it's in
+    fn decompress(&self, file: &TableFile, idx: u64, is_wdl: bool) -> io::Result<u16> {
+        if self.flags & SINGLE_VALUE != 0 {
+            return Ok(self.single_value);
+        }
+
+        // The sparse index gets close, then step to the right block
+        let main_idx = idx / self.span as u64;
+        let mut block = file.read_u32(self.sparse_index + 6 * main_idx)?;
+        let offset = file.read_u16(self.sparse_index + 6 * main_idx + 4)? as i64;
+        let mut lit_idx = (idx % self.span as u64) as i64 - self.span as i64 / 2 + offset;
+
+        while lit_idx < 0 {
+            block = block.checked_sub(1).ok_or_else(bad_table)?;
+            lit_idx += file.read_u16(self.block_lengths + 2 * block as u64)? as i64 + 1;
+        }
+        loop {
+            let length = file.read_u16(self.block_lengths + 2 * block as u64)? as i64 + 1;
+            if lit_idx < length {
+                break;
+            }
+            lit_idx -= length;
+            block += 1;
+        }
+
+        // With four bytes over so the bit buffer can always be refilled
+        let mut buffer = vec![0; self.block_size as usize + 4];
+        file.read_at(
+            self.data + block as u64 * self.block_size as u64,
+            &mut buffer,
+        )?;
+        let mut cursor = 8;
+        let mut bits = u64::from_be_bytes(buffer[..8].try_into().expect("8 bytes"));
+        let mut bits_left = 64;
+
+        let mut symbol;
+        loop {
+            let mut len = 0;
+            while bits < *self.base.get(len).ok_or_else(bad_table)? {
+                len += 1;
+            }
+            symbol = ((bits - self.base[len]) >> (64 - len - self.min_symlen as usize)) as usize;
+            symbol += self.lowest_sym[len] as usize;
+
+            let symlen = self.symlen(symbol)?;
+            if lit_idx < symlen + 1 {
+                break;
+            }
+            lit_idx -= symlen + 1;
+            len += self.min_symlen as usize;
+            bits <<= len;
+            bits_left -= len;
+
+            if bits_left <= 32 {
+                let next = buffer.get(cursor..cursor + 4).ok_or_else(bad_table)?;
+                bits_left += 32;
+                bits |= (u32::from_be_bytes(next.try_into().expect("4 bytes")) as u64)
+                    << (64 - bits_left);
+                cursor += 4;
+            }
+        }
+
+        // Walk down the tree to the value
+        while self.symlen(symbol)? != 0 {
+            let (left, right) = self.node(symbol)?;
+            let left_len = self.symlen(left)?;
+            if lit_idx < left_len + 1 {
+                symbol = left;
+            } else {
+                lit_idx -= left_len + 1;
+                symbol = right;
+            }
+        }
+
+        let bytes = &self.btree[3 * symbol..3 * symbol + 2];
+        Ok(if is_wdl {
+            bytes[0] as u16
+        } else {
+            u16::from_le_bytes([bytes[0], bytes[1]]) & 0xfff
+        })
+    }
+}
+
+// A WDL or DTZ table, with a subtable for each side to move and, with pawns,
+// for each file of the leading pawn
+struct Table {
+    file: TableFile,
+    is_wdl: bool,
+    // By file of the leading pawn, then side
+    files: Vec<Vec<PairsData>>,
+}
+
+impl Table {
+    fn open(path: &Path, is_wdl: bool) -> io::Result<Table> {
+        let file = TableFile {
+            file: File::open(path)?,
+        };
+        let name = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or_default();
+        let material = Material::from_name(name).ok_or_else(bad_table)?;
+
+        let mut magic = [0; 4];
+        file.read_at(0, &mut magic)?;
+        if magic != if is_wdl { WDL_MAGIC } else { DTZ_MAGIC } {
+            return Err(bad_table());
+        }
+
+        let layout = file.read_u8(4)?;
+        let has_pawns = layout & HAS_PAWNS != 0;
+        if has_pawns != material.has_pawns() || (layout & SPLIT != 0) == material.is_symmetric() {
+            return Err(bad_table());
+        }
+
+        let both_pawns = material.side_has_pawns(0) && material.side_has_pawns(1);
+        let num_files = if has_pawns { 4 } else { 1 };
+        let num_sides = if is_wdl && !material.is_symmetric() {
+            2
+        } else {
+            1
+        };
+        let count = material.count();
+
+        let mut ptr = 5;
+        let mut groups = Vec::new();
+        for pawn_file in 0..num_files {
+            let first = file.read_u8(ptr)?;
+            let second = if both_pawns {
+                file.read_u8(ptr + 1)?
+            } else {
+                0xff
+            };
+            let orders = [[first & 0xf, second & 0xf], [first >> 4, second >> 4]];
+            ptr += 1 + both_pawns as u64;
+
+            let mut bytes = vec![0; count];
+            file.read_at(ptr, &mut bytes)?;
+            let mut sides = Vec::new();
+            for (side, order) in orders.into_iter().enumerate().take(num_sides) {
+                let pieces = bytes
+                    .iter()
+                    .map(|&byte| {
+                        let nibble = if side == 0 { byte & 0xf } else { byte >> 4 };
+                        let role = nibble & 7;
+                        if (1..=6).contains(&role) {
+                            Ok(TablePiece {
+                                white: nibble & 8 == 0,
+                                role,
+                            })
+                        } else {
+                            Err(bad_table())
+                        }
+                    })
+                    .collect::<io::Result<Vec<_>>>()?;
+                let key = Material::from_pieces(&pieces);
+                if key != material && key.flipped() != material {
+                    return Err(bad_table());
+                }
+                sides.push(GroupData::new(pieces, order, pawn_file)?);
+            }
+            ptr += count as u64;
+            groups.push(sides);
+        }
+        ptr += ptr & 1;
+
+        if (groups[0][0].pieces[0].role == PAWN) != has_pawns {
+            return Err(bad_table());
+        }
+
+        let mut files = Vec::new();
+        for sides in groups {
+            let mut pairs = Vec::new();
+            for group in sides {
+                let (data, next) = PairsData::parse(&file, ptr, group, is_wdl)?;
+                ptr = next;
+                pairs.push(data);
+            }
+            files.push(pairs);
+        }
+
+        if !is_wdl {
+            let map_start = ptr;
+            for sides in &mut files {
+                let side = &mut sides[0];
+                if side.flags & MAPPED == 0 {
+                    continue;
+                }
+                let mut by_wdl = [0; 4];
+                if side.flags & WIDE_DTZ != 0 {
+                    for index in &mut by_wdl {
+                        *index = ((ptr - map_start + 2) / 2) as u16;
+                        ptr += file.read_u16(ptr)? as u64 * 2 + 2;
+                    }
+                    side.dtz_map = Some(DtzMap::Wide {
+                        offset: map_start,
+                        by_wdl,
+                    });
+                } else {
+                    for index in &mut by_wdl {
+                        *index = (ptr - map_start + 1) as u16;
+                        ptr += file.read_u8(ptr)? as u64 + 1;
+                    }
+                    side.dtz_map = Some(DtzMap::Normal {
+                        offset: map_start,
+                        by_wdl,
+                    });
+                }
+            }
+            ptr += ptr & 1;
+        }
+
+        for side in files.iter_mut().flatten() {
+            side.sparse_index = ptr;
+            ptr += side.sparse_index_size * 6;
+        }
+        for side in files.iter_mut().flatten() {
+            side.block_lengths = ptr;
+            ptr += side.block_length_size as u64 * 2;
+        }
+        for side in files.iter_mut().flatten() {
+            // Each side's data is 64 byte aligned
+            ptr = (ptr + 0x3f) & !0x3f;
+            side.data = ptr;
+            ptr += side.blocks_num as u64 * side.block_size as u64;
+        }
+
+        Ok(Table {
+            file,
+            is_wdl,
+            files,
+        })
+    }
+
+    // Finds the subtable and the index in it for a position, None when a
+    // DTZ table only has the other side to move
+    fn encode(&self, board: &ChessBoard) -> io::Result<Option<(&PairsData, u64)>> {
+        let consts = consts();
+        let material = Material::from_pieces(&self.files[0][0].groups.pieces);
+        let key = Material::from_board(board);
+        let black_to_move = board.side_to_move() == Colour::Black;
+
+        // Tables are stored with their stronger side as white, and
+        // symmetric ones only with white to move
+        let flip = (material.is_symmetric() && black_to_move) || key != material;
+        let black_side = black_to_move ^ flip;
+        let flip_square = |square: SquareIndex| if flip { flip_vertical(square) } else { square };
+
+        let mut squares: Vec<SquareIndex> = Vec::with_capacity(MAX_PIECES);
+        let mut used = [false; 64];
+        let find = |piece: TablePiece, used: &[bool; 64]| {
+            // White in the table is black on the board when flipped
+            let black = piece.white == flip;
+            (0..TOTAL_SQUARES).find(|&square| {
+                !used[square as usize]
+                    && board.get_piece(square).as_ref().is_some_and(|on_board| {
+                        on_board.is_black() == black && role_of(on_board.get_symbol()) == piece.role
+                    })
+            })
+        };
+
+        // With pawns there's a subtable for each file the leading pawn
+        // can be on
+        let pawn_file = if material.has_pawns() {
+            let lead = self.files[0][0].groups.pieces[0];
+            while let Some(square) = find(lead, &used) {
+                used[square as usize] = true;
+                squares.push(flip_square(square));
+            }
+            for i in 1..squares.len() {
+                if consts.map_pawns[squares[0] as usize] < consts.map_pawns[squares[i] as usize] {
+                    squares.swap(0, i);
+                }
+            }
+            let file = file_of(squares[0]) as usize;
+            if file >= 4 {
+                7 - file
+            } else {
+                file
+            }
+        } else {
+            0
+        };
+
+        let sides = &self.files[pawn_file];
+        let side = &sides[if black_side { sides.len() - 1 } else { 0 }];
+        if !self.is_wdl
+            && (side.flags & STM != 0) != black_side
+            && (!material.is_symmetric() || material.has_pawns())
+        {
+            return Ok(None);
+        }
+
+        let lead_pawns = squares.len();
+        for &piece in side.groups.pieces.iter().skip(lead_pawns) {
+            let square = find(piece, &used).ok_or_else(bad_table)?;
+            used[square as usize] = true;
+            squares.push(flip_square(square));
+        }
+        if squares.len() < 2 {
+            return Err(bad_table());
+        }
+
+        if file_of(squares[0]) >= 4 {
+            squares
+                .iter_mut()
+                .for_each(|square| *square = flip_horizontal(*square));
+        }
+
+        let lens = &side.groups.lens;
+        let mut idx = if material.has_pawns() {
+            let mut idx = consts.lead_pawn_idx[lead_pawns][squares[0] as usize];
+            squares[1..lead_pawns]
+                .sort_unstable_by_key(|&square| consts.map_pawns[square as usize]);
+            for (i, &square) in squares.iter().enumerate().take(lead_pawns).skip(1) {
+                idx += binomial(consts.map_pawns[square as usize], i as u64);
+            }
+            idx
+        } else {
+            if rank_of(squares[0]) >= 4 {
+                squares
+                    .iter_mut()
+                    .for_each(|square| *square = flip_vertical(*square));
+            }
+            for i in 0..lens[0] {
+                if !off_diagonal(squares[i]) {
+                    continue;
+                }
+                if rank_of(squares[i]) > file_of(squares[i]) {
+                    squares[i..]
+                        .iter_mut()
+                        .for_each(|square| *square = flip_diagonal(*square));
+                }
+                break;
+            }
+            self.encode_leading(&mut squares, &material, lens[0])
+        };
+        idx *= side.groups.factors[0];
+
+        // Then each group of identical pieces
+        let mut remaining_pawns = material.side_has_pawns(0) && material.side_has_pawns(1);
+        let mut group_start = lens[0];
+        for (next, &len) in lens.iter().enumerate().skip(1) {
+            let (previous, group) = squares.split_at_mut(group_start);
+            let group = &mut group[..len];
+            group.sort_unstable();
+
+            let mut n = 0;
+            for (i, &square) in group.iter().enumerate() {
+                let adjust = previous.iter().filter(|&&before| square > before).count() as u64;
+                n += binomial(
+                    square as u64 - adjust - if remaining_pawns { 8 } else { 0 },
+                    i as u64 + 1,
+                );
+            }
+            remaining_pawns = false;
+            idx += n * side.groups.factors[next];
+            group_start += len;
+        }
+
+        Ok(Some((side, idx)))
+    }
+
+    // The index of the leading group in a table without pawns
+    fn encode_leading(&self, squares: &mut [SquareIndex], material: &Material, lead: usize) -> u64 {
+        let triangle = |square: SquareIndex| TRIANGLE[square as usize];
+        let flip_all = |squares: &mut [SquareIndex], flip: fn(SquareIndex) -> SquareIndex| {
+            squares
+                .iter_mut()
+                .for_each(|square| *square = flip(*square))
+        };
+
+        if material.unique_pieces() > 2 {
+            let adjust1 = (squares[1] > squares[0]) as u64;
+            let adjust2 = (squares[2] > squares[0]) as u64 + (squares[2] > squares[1]) as u64;
+            if off_diagonal(squares[0]) {
+                triangle(squares[0]) * 63 * 62
+                    + (squares[1] as u64 - adjust1) * 62
+                    + (squares[2] as u64 - adjust2)
+            } else if off_diagonal(squares[1]) {
+                6 * 63 * 62
+                    + rank_of(squares[0]) as u64 * 28 * 62
+                    + LOWER[squares[1] as usize] * 62
+                    + squares[2] as u64
+                    - adjust2
+            } else if off_diagonal(squares[2]) {
+                6 * 63 * 62
+                    + 4 * 28 * 62
+                    + rank_of(squares[0]) as u64 * 7 * 28
+                    + (rank_of(squares[1]) as u64 - adjust1) * 28
+                    + LOWER[squares[2] as usize]
+            } else {
+                6 * 63 * 62
+                    + 4 * 28 * 62
+                    + 4 * 7 * 28
+                    + rank_of(squares[0]) as u64 * 7 * 6
+                    + (rank_of(squares[1]) as u64 - adjust1) * 6
+                    + (rank_of(squares[2]) as u64 - adjust2)
+            }
+        } else if material.unique_pieces() == 2 {
+            KK_IDX[triangle(squares[0]) as usize][squares[1] as usize]
+        } else if material.min_like_man() == 2 {
+            if triangle(squares[0]) > triangle(squares[1]) {
+                squares.swap(0, 1);
+            }
+            if file_of(squares[0]) >= 4 {
+                flip_all(squares, flip_horizontal);
+            }
+            if rank_of(squares[0]) >= 4 {
+                flip_all(squares, flip_vertical);
+            }
+            if rank_of(squares[0]) > file_of(squares[0])
+                || (!off_diagonal(squares[0]) && rank_of(squares[1]) > file_of(squares[1]))
+            {
+                flip_all(squares, flip_diagonal);
+            }
+            if TEST45.contains(&squares[1]) && triangle(squares[0]) == triangle(squares[1]) {
+                squares.swap(0, 1);
+                flip_all(squares, |square| flip_diagonal(flip_vertical(square)));
+            }
+            PP_IDX[triangle(squares[0]) as usize][squares[1] as usize]
+        } else {
+            for i in 1..lead {
+                if triangle(squares[0]) > triangle(squares[i]) {
+                    squares.swap(0, i);
+                }
+            }
+            if file_of(squares[0]) >= 4 {
+                flip_all(squares, flip_horizontal);
+            }
+            if rank_of(squares[0]) >= 4 {
+                flip_all(squares, flip_vertical);
+            }
+            if rank_of(squares[0]) > file_of(squares[0]) {
+                flip_all(squares, flip_diagonal);
+            }
+            for i in 1..lead {
+                for j in i + 1..lead {
+                    if MULT_TWIST[squares[i] as usize] > MULT_TWIST[squares[j] as usize] {
+                        squares.swap(i, j);
+                    }
+                }
+            }
+            let consts = consts();
+            let mut idx = consts.mult_idx[lead - 1][triangle(squares[0]) as usize];
+            for (i, &square) in squares.iter().enumerate().take(lead).skip(1) {
+                idx += binomial(MULT_TWIST[square as usize], i as u64);
+            }
+            idx
+        }
+    }
+
+    fn probe_wdl(&self, board: &ChessBoard) -> io::Result<Wdl> {
+        let (side, idx) = self.encode(board)?.ok_or_else(bad_table)?;
+        let value = side.decompress(&self.file, idx, true)?;
+        Wdl::from_value(value).ok_or_else(bad_table)
+    }
+
+    // Plies to zeroing, None if the table only has the other side to move
+    fn probe_dtz(&self, board: &ChessBoard, wdl: Wdl) -> io::Result<Option<i32>> {
+        let Some((side, idx)) = self.encode(board)? else {
+            return Ok(None);
+        };
+        let value = side.decompress(&self.file, idx, false)?;
+        let value = match &side.dtz_map {
+            None => value,
+            Some(DtzMap::Normal { offset, by_wdl }) => self
+                .file
+                .read_u8(offset + by_wdl[wdl.map_index()] as u64 + value as u64)?
+                as u16,
+            Some(DtzMap::Wide { offset, by_wdl }) => self
+                .file
+                .read_u16(offset + 2 * (by_wdl[wdl.map_index()] as u64 + value as u64))?,
+        };
+
+        // Most tables store full moves, rounding the plies
+        let stores_plies = match wdl {
+            Wdl::Win => side.flags & WIN_PLIES != 0,
+            Wdl::Loss => side.flags & LOSS_PLIES != 0,
+            _ => false,
+        };
+        Ok(Some(if stores_plies {
+            value as i32
+        } else {
+            2 * value as i32
+        }))
+    }
+}
+
+// Whether a position's best move is known to be a capture or pawn move,
+// which the DTZ tables leave out
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ProbeState {
+    Normal,
+    ZeroingBestMove,
+}
+
+fn is_pawn_move(board: &ChessBoard, chess_move: ChessMove) -> bool {
+    board
+        .get_piece(chess_move.source)
+        .as_ref()
+        .is_some_and(|piece| role_of(piece.get_symbol()) == PAWN)
+}
+
+fn is_en_passant(board: &ChessBoard, chess_move: ChessMove) -> bool {
+    board.en_passant() == Some(chess_move.destination)
+        && is_pawn_move(board, chess_move)
+        && file_of(chess_move.source) != file_of(chess_move.destination)
+}
+
+// Moves that reset the fifty move count
+fn is_zeroing(board: &ChessBoard, chess_move: ChessMove) -> bool {
+    board.is_capture(chess_move) || is_pawn_move(board, chess_move)
+}
+
+fn is_checkmate(board: &mut ChessBoard) -> bool {
+    board.is_in_check(board.side_to_move()) && board.legal_moves().is_empty()
+}
+
+// Captures only, leaving out promotions that don't take anything
+fn captures(board: &mut ChessBoard) -> Vec<ChessMove> {
+    let mut moves = board.legal_captures();
+    moves.retain(|&chess_move| board.is_capture(chess_move));
+    moves
+}
+
+// The best moves at the root by the tables, and the result they keep
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RootProbe {
+    pub wdl: Wdl,
+    pub dtz: i32,
+    pub moves: Vec<ChessMove>,
+}
+
+type Tables = HashMap<Material, (PathBuf, OnceLock<Option<Table>>)>;
+
+// A set of table files. They're only opened when first probed, and can be
+// probed from several threads at once.
+pub struct Tablebase {
+    wdl: Tables,
+    dtz: Tables,
+    max_pieces: usize,
+}
+
+impl Tablebase {
+    // Adds the tables in each directory of a list separated like PATH
+    pub fn open(paths: &str) -> Result<Tablebase, String> {
+        let mut tablebase = Tablebase {
+            wdl: HashMap::new(),
+            dtz: HashMap::new(),
+            max_pieces: 0,
+        };
+        for directory in std::env::split_paths(paths) {
+            let entries = fs::read_dir(&directory)
+                .map_err(|err| format!("can't read {}: {}", directory.display(), err))?;
+            for entry in entries.flatten() {
+                tablebase.add_file(entry.path());
+            }
+        }
+        if tablebase.wdl.is_empty() {
+            return Err(format!("no tables in {}", paths));
+        }
+        Ok(tablebase)
+    }
+
+    fn add_file(&mut self, path: PathBuf) {
+        let Some(material) = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(Material::from_name)
+        else {
+            return;
+        };
+        let pieces = material.count();
+        if pieces > MAX_PIECES || material.side_count(0) == 0 || material.side_count(1) == 0 {
+            return;
+        }
+        let tables = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("rtbw") => &mut self.wdl,
+            Some("rtbz") => &mut self.dtz,
+            _ => return,
+        };
+        // Table files are always 16 bytes over a multiple of 64
+        if !fs::metadata(&path).is_ok_and(|meta| meta.is_file() && meta.len() % 64 == 16) {
+            return;
+        }
+        tables.insert(material, (path, OnceLock::new()));
+        self.max_pieces = self.max_pieces.max(pieces);
+    }
+
+    pub fn len(&self) -> usize {
+        self.wdl.len() + self.dtz.len()
+    }
+
+    pub fn max_pieces(&self) -> usize {
+        self.max_pieces
+    }
+
+    fn table<'a>(tables: &'a Tables, key: &Material, is_wdl: bool) -> Option<&'a Table> {
+        let (path, table) = tables.get(key).or_else(|| tables.get(&key.flipped()))?;
+        table
+            .get_or_init(|| Table::open(path, is_wdl).ok())
+            .as_ref()
+    }
+
+    fn probe_wdl_table(&self, board: &ChessBoard) -> Option<Wdl> {
+        let key = Material::from_board(board);
+        // Just the kings
+        if key.count() == 2 {
+            return Some(Wdl::Draw);
+        }
+        Self::table(&self.wdl, &key, true)?.probe_wdl(board).ok()
+    }
+
+    fn probe_dtz_table(&self, board: &ChessBoard, wdl: Wdl) -> Option<Option<i32>> {
+        let key = Material::from_board(board);
+        Self::table(&self.dtz, &key, false)?
+            .probe_dtz(board, wdl)
+            .ok()
+    }
+
+    // Resolves captures, which the tables may store as any lower result when
+    // there's a capture that does better. Only for positions without en
+    // passant.
+    fn probe_ab_no_ep(&self, board: &mut ChessBoard, mut alpha: Wdl, beta: Wdl) -> Option<Wdl> {
+        for chess_move in captures(board) {
+            let record = board.make_move(chess_move);
+            let wdl = self.probe_ab_no_ep(board, beta.flip(), alpha.flip());
+            board.unmake_move(record);
+            let wdl = wdl?.flip();
+            if wdl >= beta {
+                return Some(wdl);
+            }
+            alpha = alpha.max(wdl);
+        }
+        Some(alpha.max(self.probe_wdl_table(board)?))
+    }
+
+    fn probe(&self, board: &mut ChessBoard) -> Option<(Wdl, ProbeState)> {
+        if Material::from_board(board).count() > self.max_pieces {
+            return None;
+        }
+        let castling = board.castling_rights();
+        if [Colour::White, Colour::Black]
+            .into_iter()
+            .any(|colour| castling.can_castle(colour, true) || castling.can_castle(colour, false))
+        {
+            return None;
+        }
+
+        // The best capture, with en passant apart as the tables leave it out
+        let mut best_capture = Wdl::Loss;
+        let mut best_ep = Wdl::Loss;
+        let legal = board.legal_moves();
+        for &chess_move in &legal {
+            if !board.is_capture(chess_move) {
+                continue;
+            }
+            let en_passant = is_en_passant(board, chess_move);
+            let record = board.make_move(chess_move);
+            let wdl = self.probe_ab_no_ep(board, Wdl::Loss, best_capture.flip());
+            board.unmake_move(record);
+            let wdl = wdl?.flip();
+            if wdl == Wdl::Win {
+                return Some((wdl, ProbeState::ZeroingBestMove));
+            }
+            if en_passant {
+                best_ep = best_ep.max(wdl);
+            } else {
+                best_capture = best_capture.max(wdl);
+            }
+        }
+
+        let wdl = self.probe_wdl_table(board)?;
+        if best_ep > wdl.max(best_capture) {
+            return Some((best_ep, ProbeState::ZeroingBestMove));
+        }
+        best_capture = best_capture.max(best_ep);
+        if best_capture >= wdl {
+            let state = if best_capture > Wdl::Draw {
+                ProbeState::ZeroingBestMove
+            } else {
+                ProbeState::Normal
+            };
+            return Some((best_capture, state));
+        }
+        // Stalemate but for en passant
+        if wdl == Wdl::Draw
+            && !legal.is_empty()
+            && legal
+                .iter()
+                .all(|&chess_move| is_en_passant(board, chess_move))
+        {
+            return Some((best_ep, ProbeState::ZeroingBestMove));
+        }
+        Some((wdl, ProbeState::Normal))
+    }
+
+    fn dtz(&self, board: &mut ChessBoard, wdl: Wdl, state: ProbeState) -> Option<i32> {
+        if wdl == Wdl::Draw {
+            return Some(0);
+        }
+        if state == ProbeState::ZeroingBestMove {
+            return Some(wdl.dtz_before_zeroing());
+        }
+
+        // A winning pawn move zeroes straight away. Captures were already
+        // looked at.
+        let legal = board.legal_moves();
+        if wdl >= Wdl::CursedWin {
+            for &chess_move in &legal {
+                if !is_pawn_move(board, chess_move) || board.is_capture(chess_move) {
+                    continue;
+                }
+                let record = board.make_move(chess_move);
+                let after = self.probe(board);
+                board.unmake_move(record);
+                if after?.0.flip() == wdl {
+                    return Some(wdl.dtz_before_zeroing());
+                }
+            }
+        }
+
+        if let Some(plies) = self.probe_dtz_table(board, wdl)? {
+            return Some(add_plies(wdl.dtz_before_zeroing(), plies));
+        }
+
+        // The table only has the other side to move, so search a ply
+        let mut best = (wdl < Wdl::CursedWin).then(|| wdl.dtz_before_zeroing());
+        for &chess_move in &legal {
+            if is_zeroing(board, chess_move) {
+                continue;
+            }
+            let record = board.make_move(chess_move);
+            let dtz = self.probe_dtz(board);
+            let mate = is_checkmate(board);
+            board.unmake_move(record);
+            let dtz = -dtz?;
+            if dtz == 1 && mate {
+                best = Some(1);
+            } else if dtz.signum() == (wdl as i32).signum() {
+                let dtz = add_plies(dtz, 1);
+                best = Some(best.map_or(dtz, |best| best.min(dtz)));
+            }
+        }
+        best
+    }
+
+    // The result for the side to move, as if the last move was a capture or
+    // pawn move so the fifty move count doesn't come into it
+    pub fn probe_wdl(&self, board: &mut ChessBoard) -> Option<Wdl> {
+        self.probe(board).map(|(wdl, _)| wdl)
+    }
+
+    // Plies to the next capture or pawn move that keeps the result, positive
+    // when winning. Can be one ply long where a table rounds to full moves.
+    pub fn probe_dtz(&self, board: &mut ChessBoard) -> Option<i32> {
+        let (wdl, state) = self.probe(board)?;
+        self.dtz(board, wdl, state)
+    }
+
+    // Keeps the moves that win quickest under the fifty move rule, or hold
+    // the draw, or lose slowest
+    pub fn probe_root(&self, board: &mut ChessBoard) -> Option<RootProbe> {
+        let mut ranked = Vec::new();
+        for chess_move in board.legal_moves() {
+            let zeroing = is_zeroing(board, chess_move);
+            let record = board.make_move(chess_move);
+            let dtz = if is_checkmate(board) {
+                Some(1)
+            } else if zeroing {
+                self.probe_wdl(board)
+                    .map(|wdl| wdl.flip().dtz_before_zeroing())
+            } else {
+                self.probe_dtz(board).map(|dtz| add_plies(-dtz, 1))
+            };
+            board.unmake_move(record);
+
+            let clock = if zeroing {
+                0
+            } else {
+                board.halfmove_clock() as i32
+            };
+            let dtz = dtz?;
+            let rank = if dtz > 0 && dtz + clock <= 100 {
+                10_000 - dtz
+            } else if dtz < 0 && -dtz + clock <= 100 {
+                -10_000 - dtz
+            } else {
+                0
+            };
+            ranked.push((chess_move, dtz, rank));
+        }
+
+        let best = ranked.iter().map(|&(_, _, rank)| rank).max()?;
+        ranked.retain(|&(_, _, rank)| rank == best);
+        let (wdl, dtz) = match best.signum() {
+            1 => (Wdl::Win, ranked[0].1),
+            -1 => (Wdl::Loss, ranked[0].1),
+            _ => (Wdl::Draw, 0),
+        };
+        Some(RootProbe {
+            wdl,
+            dtz,
+            moves: ranked
+                .into_iter()
+                .map(|(chess_move, _, _)| chess_move)
+                .collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_material() {
+        let material = Material::from_name("KRPvKN").unwrap();
+        assert_eq!(material.count(), 5);
+        assert_eq!(material.flipped(), Material::from_name("KNvKRP").unwrap());
+        assert!(material.has_pawns());
+        assert!(!material.is_symmetric());
+        assert_eq!(material.unique_pieces(), 5);
+        assert_eq!(Material::from_name("KNNvK").unwrap().min_like_man(), 2);
+        assert_eq!(Material::from_name("KXvK"), None);
+
+        let board = ChessBoard::from_fen("8/8/1n6/8/7K/8/3k4/1Q6 w - - 0 1");
+        assert_eq!(
+            Material::from_board(&board),
+            Material::from_name("KQvKN").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_wdl() {
+        assert_eq!(binomial(5, 2), 10);
+        assert_eq!(binomial(2, 5), 0);
+        assert_eq!(Wdl::CursedWin.flip(), Wdl::BlessedLoss);
+        assert_eq!(add_plies(-3, 2), -5);
+        assert_eq!(add_plies(0, 2), 0);
+        assert_eq!(flip_diagonal(1), 8);
+    }
+
+    #[test]
+    fn test_probe_fixture() {
+        // A few three piece tables checked in under tests/syzygy
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/syzygy");
+        let tablebase = Tablebase::open(path).unwrap();
+
+        let positions = [
+            ("8/8/8/2R5/1K6/8/5k2/8 w - - 0 1", Wdl::Win, 21),
+            ("8/5p2/6k1/K7/8/8/8/8 w - - 0 1", Wdl::Loss, -2),
+            ("8/8/8/2K5/5kp1/8/8/8 b - - 0 1", Wdl::Win, 1),
+            ("8/3k4/8/8/8/8/4P3/3K4 w - - 0 1", Wdl::Draw, 0),
+            ("6k1/8/8/8/8/4n3/8/K7 b - - 0 1", Wdl::Draw, 0),
+        ];
+        for (fen, wdl, dtz) in positions {
+            let mut board = ChessBoard::from_fen(fen);
+            assert_eq!(tablebase.probe_wdl(&mut board), Some(wdl), "{}", fen);
+            assert_eq!(tablebase.probe_dtz(&mut board), Some(dtz), "{}", fen);
+        }
+
+        // Qh8 and Qb7 both mate
+        let mut board = ChessBoard::from_fen("k7/8/1K6/8/8/8/8/7Q w - - 0 1");
+        let root = tablebase.probe_root(&mut board).unwrap();
+        assert_eq!((root.wdl, root.dtz), (Wdl::Win, 1));
+        let mut moves: Vec<String> = root.moves.iter().map(|m| m.to_string()).collect();
+        moves.sort();
+        assert_eq!(moves, ["h1b7", "h1h8"]);
+
+        // No table for this
+        let mut board = ChessBoard::from_fen("8/8/1n6/8/7K/8/3k4/1Q6 w - - 0 1");
+        assert_eq!(tablebase.probe_wdl(&mut board), None);
+    }
+
+    // Needs the three and four piece tables, e.g.
+    // SYZYGY_PATH=/path/to/3-4-5 cargo test syzygy -- --ignored
+    #[test]
+    #[ignore = "needs SYZYGY_PATH"]
+    fn test_probe() {
+        let path = std::env::var("SYZYGY_PATH").expect("SYZYGY_PATH is set");
+        let tablebase = Tablebase::open(&path).unwrap();
+
+        // Positions with their WDL and DTZ
+        let positions = [
+            ("8/8/1n6/8/7K/8/3k4/1Q6 w - - 0 1", Wdl::Win, 1),
+            ("2K5/8/8/8/6P1/8/2n5/1k6 w - - 0 1", Wdl::Draw, 0),
+            ("8/8/5k2/8/2K5/3N4/2B5/8 w - - 0 1", Wdl::Win, 48),
+            ("3B4/K7/8/k3N3/8/8/8/8 b - - 0 1", Wdl::Loss, -53),
+        ];
+        for (fen, wdl, dtz) in positions {
+            let mut board = ChessBoard::from_fen(fen);
+            assert_eq!(tablebase.probe_wdl(&mut board), Some(wdl), "{}", fen);
+            assert_eq!(tablebase.probe_dtz(&mut board), Some(dtz), "{}", fen);
+        }
+
+        let mut board = ChessBoard::from_fen("8/8/1n6/8/7K/8/3k4/1Q6 w - - 0 1");
+        let root = tablebase.probe_root(&mut board).unwrap();
+        assert_eq!(root.wdl, Wdl::Win);
+        assert_eq!(root.moves, vec![board.parse_move("b1b6").unwrap()]);
+
+        // Too many pieces
+        let mut board = ChessBoard::from_fen("8/8/1n6/8/7K/8/3k4/1QR2R2 w - - 0 1");
+        assert_eq!(tablebase.probe_wdl(&mut board), None);
+    }
+}
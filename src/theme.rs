@@ -0,0 +1,229 @@
+use tui::style::Color;
+
+use crate::app::Highlight;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SquareColours {
+    pub light: Color,
+    pub dark: Color,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Theme {
+    pub name: &'static str,
+    pub squares: SquareColours,
+    pub selected: SquareColours,
+    pub moves: SquareColours,
+    pub captures: SquareColours,
+    pub last_move: SquareColours,
+    pub check: SquareColours,
+    pub white_pieces: Color,
+    pub black_pieces: Color,
+}
+
+pub const THEME_NAMES: [&str; 4] = ["classic", "green", "high-contrast", "colour-blind"];
+
+impl Theme {
+    pub fn by_name(name: &str) -> Option<Theme> {
+        match name {
+            "classic" => Some(Theme::classic()),
+            "green" => Some(Theme::green()),
+            "high-contrast" => Some(Theme::high_contrast()),
+            "colour-blind" => Some(Theme::colour_blind()),
+            _ => None,
+        }
+    }
+
+    pub fn classic() -> Theme {
+        Theme {
+            name: "classic",
+            squares: SquareColours {
+                light: Color::Rgb(168, 123, 80),
+                dark: Color::Rgb(100, 70, 25),
+            },
+            selected: SquareColours {
+                light: Color::Rgb(130, 200, 130),
+                dark: Color::Rgb(90, 160, 90),
+            },
+            moves: SquareColours {
+                light: Color::Rgb(255, 189, 123),
+                dark: Color::Rgb(240, 179, 64),
+            },
+            captures: SquareColours {
+                light: Color::Rgb(240, 120, 100),
+                dark: Color::Rgb(200, 80, 60),
+            },
+            last_move: SquareColours {
+                light: Color::Rgb(205, 210, 106),
+                dark: Color::Rgb(170, 162, 58),
+            },
+            check: SquareColours {
+                light: Color::Rgb(220, 30, 30),
+                dark: Color::Rgb(220, 30, 30),
+            },
+            white_pieces: Color::Rgb(240, 240, 240),
+            black_pieces: Color::Rgb(0, 0, 0),
+        }
+    }
+
+    pub fn green() -> Theme {
+        Theme {
+            name: "green",
+            squares: SquareColours {
+                light: Color::Rgb(238, 238, 210),
+                dark: Color::Rgb(118, 150, 86),
+            },
+            selected: SquareColours {
+                light: Color::Rgb(246, 246, 105),
+                dark: Color::Rgb(186, 202, 43),
+            },
+            moves: SquareColours {
+                light: Color::Rgb(170, 210, 230),
+                dark: Color::Rgb(110, 160, 190),
+            },
+            captures: SquareColours {
+                light: Color::Rgb(235, 125, 105),
+                dark: Color::Rgb(200, 85, 70),
+            },
+            last_move: SquareColours {
+                light: Color::Rgb(245, 246, 130),
+                dark: Color::Rgb(185, 202, 67),
+            },
+            check: SquareColours {
+                light: Color::Rgb(230, 40, 40),
+                dark: Color::Rgb(230, 40, 40),
+            },
+            white_pieces: Color::Rgb(255, 255, 255),
+            black_pieces: Color::Rgb(30, 30, 30),
+        }
+    }
+
+    pub fn high_contrast() -> Theme {
+        Theme {
+            name: "high-contrast",
+            squares: SquareColours {
+                light: Color::Rgb(200, 200, 200),
+                dark: Color::Rgb(90, 90, 90),
+            },
+            selected: SquareColours {
+                light: Color::Rgb(0, 255, 255),
+                dark: Color::Rgb(0, 200, 200),
+            },
+            moves: SquareColours {
+                light: Color::Rgb(255, 255, 0),
+                dark: Color::Rgb(200, 200, 0),
+            },
+            captures: SquareColours {
+                light: Color::Rgb(255, 0, 255),
+                dark: Color::Rgb(200, 0, 200),
+            },
+            last_move: SquareColours {
+                light: Color::Rgb(0, 255, 0),
+                dark: Color::Rgb(0, 190, 0),
+            },
+            check: SquareColours {
+                light: Color::Rgb(255, 0, 0),
+                dark: Color::Rgb(255, 0, 0),
+            },
+            white_pieces: Color::Rgb(255, 255, 255),
+            black_pieces: Color::Rgb(0, 0, 0),
+        }
+    }
+
+    // Okabe-Ito palette, which stays distinguishable for the common forms of
+    // colour blindness.
+    pub fn colour_blind() -> Theme {
+        Theme {
+            name: "colour-blind",
+            squares: SquareColours {
+                light: Color::Rgb(220, 220, 220),
+                dark: Color::Rgb(128, 128, 128),
+            },
+            selected: SquareColours {
+                light: Color::Rgb(240, 228, 66),
+                dark: Color::Rgb(200, 190, 50),
+            },
+            moves: SquareColours {
+                light: Color::Rgb(86, 180, 233),
+                dark: Color::Rgb(0, 114, 178),
+            },
+            captures: SquareColours {
+                light: Color::Rgb(230, 159, 0),
+                dark: Color::Rgb(213, 94, 0),
+            },
+            last_move: SquareColours {
+                light: Color::Rgb(0, 158, 115),
+                dark: Color::Rgb(0, 120, 90),
+            },
+            check: SquareColours {
+                light: Color::Rgb(204, 121, 167),
+                dark: Color::Rgb(204, 121, 167),
+            },
+            white_pieces: Color::Rgb(255, 255, 255),
+            black_pieces: Color::Rgb(0, 0, 0),
+        }
+    }
+
+    pub fn square_colour(&self, is_light: bool, highlight: Option<Highlight>) -> Color {
+        let colours = match highlight {
+            Some(Highlight::Selected) => self.selected,
+            Some(Highlight::Move) => self.moves,
+            Some(Highlight::Capture) => self.captures,
+            Some(Highlight::LastMove) => self.last_move,
+            Some(Highlight::Check) => self.check,
+            None => self.squares,
+        };
+
+        if is_light {
+            colours.light
+        } else {
+            colours.dark
+        }
+    }
+
+    pub fn piece_colour(&self, is_black: bool) -> Color {
+        if is_black {
+            self.black_pieces
+        } else {
+            self.white_pieces
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::classic()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_all_theme_names_resolve() {
+        for name in THEME_NAMES {
+            assert_eq!(Theme::by_name(name).unwrap().name, name);
+        }
+        assert_eq!(Theme::by_name("purple"), None);
+    }
+
+    #[test]
+    fn test_highlights_differ_from_plain_squares() {
+        for name in THEME_NAMES {
+            let theme = Theme::by_name(name).unwrap();
+            for highlight in [
+                Highlight::Selected,
+                Highlight::Move,
+                Highlight::Capture,
+                Highlight::LastMove,
+                Highlight::Check,
+            ] {
+                assert_ne!(
+                    theme.square_colour(true, Some(highlight)),
+                    theme.square_colour(true, None)
+                );
+            }
+        }
+    }
+}
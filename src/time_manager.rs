@@ -0,0 +1,203 @@
+use std::time::{Duration, Instant};
+
+use crate::{
+    chess_move::ChessMove,
+    piece::Colour,
+    search::{SearchLimits, MATE, MAX_PLY},
+};
+
+pub const DEFAULT_MOVE_OVERHEAD_MS: u64 = 30;
+pub const MAX_MOVE_OVERHEAD_MS: u64 = 5000;
+
+// Moves to plan for when the clock doesn't say
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+
+// How far past the soft limit the search may run when it's unsure
+const MAX_SCALE: f64 = 2.5;
+
+// Decides how long to think. The soft limit is when to stop starting new
+// iterations, stretched when the best move keeps changing or the score
+// drops; the hard limit stops the search mid iteration.
+pub struct TimeManager {
+    start: Instant,
+    soft: Option<Duration>,
+    hard: Option<Duration>,
+    previous_move: Option<ChessMove>,
+    previous_score: Option<i32>,
+    // Recent best move changes, fading by half each iteration
+    instability: f64,
+    scale: f64,
+}
+
+impl TimeManager {
+    pub fn new(limits: &SearchLimits, colour: Colour, start: Instant) -> Self {
+        let (soft, hard) = match Self::budget(limits, colour) {
+            Some((soft, hard)) => (Some(soft), Some(hard)),
+            None => (None, None),
+        };
+        TimeManager {
+            start,
+            soft,
+            hard,
+            previous_move: None,
+            previous_score: None,
+            instability: 0.0,
+            scale: 1.0,
+        }
+    }
+
+    fn budget(limits: &SearchLimits, colour: Colour) -> Option<(Duration, Duration)> {
+        if limits.infinite {
+            return None;
+        }
+        if let Some(movetime) = limits.movetime {
+            let movetime = movetime.saturating_sub(limits.move_overhead);
+            return Some((movetime, movetime));
+        }
+
+        let (time, increment) = match colour {
+            Colour::White => (limits.wtime?, limits.winc.unwrap_or_default()),
+            Colour::Black => (limits.btime?, limits.binc.unwrap_or_default()),
+        };
+        let moves_to_go = limits.movestogo.unwrap_or(DEFAULT_MOVES_TO_GO).max(1);
+
+        // Each move to come costs the overhead, and whatever happens a
+        // quarter of the clock is kept back
+        let overheads = limits.move_overhead * moves_to_go.min(10);
+        let available = time.saturating_sub(overheads.max(limits.move_overhead));
+        let most = time.saturating_sub(limits.move_overhead).mul_f64(0.75);
+
+        let soft = (available / moves_to_go + increment.mul_f64(0.75)).min(most);
+        let hard = (soft * 4).min(most);
+        Some((soft, hard))
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.hard.map(|hard| self.start + hard)
+    }
+
+    // Called after each finished iteration with its result
+    pub fn update(&mut self, best_move: Option<ChessMove>, score: i32) {
+        self.instability /= 2.0;
+        if self.previous_move.is_some() && best_move != self.previous_move {
+            self.instability += 1.0;
+        }
+
+        // Falling scores need a closer look, mates are what they are
+        let drop = match self.previous_score {
+            Some(previous) if previous.abs() < MATE - MAX_PLY as i32 => {
+                (previous - score).clamp(0, 100)
+            }
+            _ => 0,
+        };
+
+        self.scale = ((1.0 + self.instability * 0.5) * (1.0 + drop as f64 / 200.0)).min(MAX_SCALE);
+        self.previous_move = best_move;
+        self.previous_score = Some(score);
+    }
+
+    // Whether to start another iteration
+    pub fn stop_deepening(&self, now: Instant) -> bool {
+        match (self.soft, self.hard) {
+            (Some(soft), Some(hard)) => now - self.start >= soft.mul_f64(self.scale).min(hard),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clock(millis: u64, increment: u64, moves_to_go: Option<u32>) -> SearchLimits {
+        SearchLimits {
+            wtime: Some(Duration::from_millis(millis)),
+            winc: Some(Duration::from_millis(increment)),
+            movestogo: moves_to_go,
+            move_overhead: Duration::from_millis(DEFAULT_MOVE_OVERHEAD_MS),
+            ..SearchLimits::default()
+        }
+    }
+
+    fn limits(limits: &SearchLimits) -> (Duration, Duration) {
+        let manager = TimeManager::new(limits, Colour::White, Instant::now());
+        (manager.soft.unwrap(), manager.hard.unwrap())
+    }
+
+    #[test]
+    fn test_budgets() {
+        let (soft, hard) = limits(&clock(60_000, 0, None));
+        assert_eq!(soft, Duration::from_millis(1990));
+        assert_eq!(hard, soft * 4);
+
+        let (soft, _) = limits(&clock(60_000, 1000, None));
+        assert_eq!(soft, Duration::from_millis(2740));
+
+        // The last move before the time control can use most of the clock
+        let (soft, hard) = limits(&clock(10_000, 0, Some(1)));
+        assert_eq!(soft, hard);
+        assert_eq!(
+            hard,
+            Duration::from_millis(7477) + Duration::from_micros(500)
+        );
+
+        let movetime = SearchLimits {
+            movetime: Some(Duration::from_millis(500)),
+            move_overhead: Duration::from_millis(50),
+            ..SearchLimits::default()
+        };
+        assert_eq!(
+            limits(&movetime),
+            (Duration::from_millis(450), Duration::from_millis(450))
+        );
+
+        assert!(
+            TimeManager::new(&SearchLimits::default(), Colour::White, Instant::now())
+                .deadline()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_never_flags() {
+        // Less time left than the overhead, so stop at once
+        for millis in [0, 10, 30] {
+            let (soft, hard) = limits(&clock(millis, 0, None));
+            assert_eq!((soft, hard), (Duration::ZERO, Duration::ZERO));
+        }
+        // A big increment doesn't tempt it into using time it hasn't got
+        let (_, hard) = limits(&clock(200, 5000, None));
+        assert!(hard < Duration::from_millis(200));
+    }
+
+    #[test]
+    fn test_instability_takes_longer() {
+        let start = Instant::now();
+        let mut manager = TimeManager::new(&clock(60_000, 0, None), Colour::White, start);
+        let first = ChessMove::new(12, 28);
+        let second = ChessMove::new(6, 21);
+
+        manager.update(Some(first), 20);
+        manager.update(Some(first), 20);
+        let soft = manager.soft.unwrap();
+        assert!(manager.stop_deepening(start + soft));
+        assert!(!manager.stop_deepening(start + soft / 2));
+
+        // The best move changing and the score dropping both buy more time
+        manager.update(Some(second), 20);
+        assert!(!manager.stop_deepening(start + soft));
+        manager.update(Some(second), 20);
+        manager.update(Some(second), 20);
+        manager.update(Some(second), 20);
+        assert!(manager.stop_deepening(start + soft.mul_f64(1.1)));
+        manager.update(Some(second), -60);
+        assert!(!manager.stop_deepening(start + soft.mul_f64(1.1)));
+
+        // But never past the hard limit
+        for _ in 0..10 {
+            manager.update(Some(first), -500);
+            manager.update(Some(second), -1000);
+        }
+        assert!(manager.stop_deepening(start + manager.hard.unwrap()));
+    }
+}
@@ -0,0 +1,324 @@
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+use crate::{
+    chess_move::{ChessMove, PROMOTION_PIECES},
+    search::{MAX_PLY, TB_WIN},
+};
+
+pub const DEFAULT_HASH_MB: usize = 16;
+pub const MAX_HASH_MB: usize = 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bound {
+    Exact,
+    // The score is at least this much, the search failed high
+    Lower,
+    // The score is at most this much, nothing beat alpha
+    Upper,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub best_move: Option<ChessMove>,
+    pub score: i32,
+    pub depth: u32,
+    pub bound: Bound,
+    age: u8,
+}
+
+// Each slot holds the key xor'd with the data alongside the data itself, so a
+// slot torn by two threads writing at once just fails to match on probe.
+struct Slot {
+    check: AtomicU64,
+    data: AtomicU64,
+}
+
+// Slots are paired into buckets: the first keeps the deepest result, the
+// second always takes the newest.
+const BUCKET_SIZE: usize = 2;
+
+pub struct TranspositionTable {
+    slots: Vec<Slot>,
+    age: AtomicU8,
+}
+
+fn pack_move(chess_move: Option<ChessMove>) -> u64 {
+    match chess_move {
+        Some(chess_move) => {
+            let promotion = chess_move
+                .promotion
+                .and_then(|promotion| PROMOTION_PIECES.iter().position(|&p| p == promotion))
+                .map_or(0, |index| index as u64 + 1);
+            1 << 15
+                | promotion << 12
+                | (chess_move.destination as u64) << 6
+                | chess_move.source as u64
+        }
+        None => 0,
+    }
+}
+
+fn unpack_move(packed: u64) -> Option<ChessMove> {
+    if packed & 1 << 15 == 0 {
+        return None;
+    }
+    let promotion = match (packed >> 12) & 0x7 {
+        0 => None,
+        index => PROMOTION_PIECES.get(index as usize - 1).copied(),
+    };
+    Some(ChessMove {
+        promotion,
+        ..ChessMove::new((packed & 0x3f) as u8, ((packed >> 6) & 0x3f) as u8)
+    })
+}
+
+fn pack(entry: &Entry) -> u64 {
+    let bound = match entry.bound {
+        Bound::Exact => 0,
+        Bound::Lower => 1,
+        Bound::Upper => 2,
+    };
+    pack_move(entry.best_move)
+        | (entry.score as i16 as u16 as u64) << 16
+        | (entry.depth.min(u8::MAX as u32) as u64) << 32
+        | bound << 40
+        | (entry.age as u64) << 48
+        // Marks the slot as used, even for an entry that's otherwise all zeros
+        | 1 << 63
+}
+
+fn unpack(data: u64) -> Entry {
+    Entry {
+        best_move: unpack_move(data & 0xffff),
+        score: (data >> 16) as u16 as i16 as i32,
+        depth: ((data >> 32) & 0xff) as u32,
+        bound: match (data >> 40) & 0x3 {
+            0 => Bound::Exact,
+            1 => Bound::Lower,
+            _ => Bound::Upper,
+        },
+        age: ((data >> 48) & 0xff) as u8,
+    }
+}
+
+// Mate and tablebase scores are stored relative to the position rather than
+// the root, so the same win found through different move orders scores the
+// same. The tablebase band sits just below the mates, so one test covers both.
+pub fn score_to_tt(score: i32, ply: u32) -> i32 {
+    if score > TB_WIN - MAX_PLY as i32 {
+        score + ply as i32
+    } else if score < -TB_WIN + MAX_PLY as i32 {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+pub fn score_from_tt(score: i32, ply: u32) -> i32 {
+    if score > TB_WIN - MAX_PLY as i32 {
+        score - ply as i32
+    } else if score < -TB_WIN + MAX_PLY as i32 {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+impl TranspositionTable {
+    pub fn new(megabytes: usize) -> Self {
+        let bytes = megabytes.clamp(1, MAX_HASH_MB) * 1024 * 1024;
+        let buckets = bytes / (std::mem::size_of::<Slot>() * BUCKET_SIZE);
+        TranspositionTable {
+            slots: (0..buckets * BUCKET_SIZE)
+                .map(|_| Slot {
+                    check: AtomicU64::new(0),
+                    data: AtomicU64::new(0),
+                })
+                .collect(),
+            age: AtomicU8::new(0),
+        }
+    }
+
+    pub fn clear(&self) {
+        for slot in &self.slots {
+            slot.check.store(0, Ordering::Relaxed);
+            slot.data.store(0, Ordering::Relaxed);
+        }
+        self.age.store(0, Ordering::Relaxed);
+    }
+
+    // Called at the start of each search so old entries can be told apart
+    pub fn new_search(&self) {
+        let age = self.age.load(Ordering::Relaxed);
+        self.age.store(age.wrapping_add(1), Ordering::Relaxed);
+    }
+
+    fn bucket(&self, key: u64) -> &[Slot] {
+        let buckets = self.slots.len() / BUCKET_SIZE;
+        let index = ((key as u128 * buckets as u128) >> 64) as usize;
+        &self.slots[index * BUCKET_SIZE..(index + 1) * BUCKET_SIZE]
+    }
+
+    fn read(slot: &Slot) -> (u64, u64) {
+        let data = slot.data.load(Ordering::Relaxed);
+        (slot.check.load(Ordering::Relaxed) ^ data, data)
+    }
+
+    pub fn probe(&self, key: u64) -> Option<Entry> {
+        self.bucket(key).iter().find_map(|slot| {
+            let (slot_key, data) = Self::read(slot);
+            if slot_key == key && data != 0 {
+                Some(unpack(data))
+            } else {
+                None
+            }
+        })
+    }
+
+    pub fn store(
+        &self,
+        key: u64,
+        best_move: Option<ChessMove>,
+        score: i32,
+        depth: u32,
+        bound: Bound,
+    ) {
+        let age = self.age.load(Ordering::Relaxed);
+        let bucket = self.bucket(key);
+
+        let (deep_key, deep_data) = Self::read(&bucket[0]);
+        let deep = unpack(deep_data);
+        let replace_deep =
+            deep_data == 0 || deep_key == key || deep.age != age || depth >= deep.depth;
+        let slot = if replace_deep { &bucket[0] } else { &bucket[1] };
+
+        // Keep the old best move rather than losing it to a result without one
+        let (slot_key, slot_data) = Self::read(slot);
+        let best_move = match best_move {
+            None if slot_key == key && slot_data != 0 => unpack(slot_data).best_move,
+            best_move => best_move,
+        };
+
+        // A deep entry that's pushed out still gets a go in the other slot
+        if replace_deep && deep_data != 0 && deep_key != key {
+            bucket[1]
+                .check
+                .store(deep_key ^ deep_data, Ordering::Relaxed);
+            bucket[1].data.store(deep_data, Ordering::Relaxed);
+        }
+
+        let data = pack(&Entry {
+            best_move,
+            score,
+            depth,
+            bound,
+            age,
+        });
+        slot.check.store(key ^ data, Ordering::Relaxed);
+        slot.data.store(data, Ordering::Relaxed);
+    }
+
+    // How full the table is in permille, from a sample of this search's entries
+    pub fn hashfull(&self) -> u32 {
+        let age = self.age.load(Ordering::Relaxed);
+        let sample = &self.slots[..self.slots.len().min(1000)];
+        let used = sample
+            .iter()
+            .filter(|slot| {
+                let data = slot.data.load(Ordering::Relaxed);
+                data != 0 && unpack(data).age == age
+            })
+            .count();
+        (used * 1000 / sample.len()) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::MATE;
+
+    #[test]
+    fn test_store_and_probe() {
+        let tt = TranspositionTable::new(1);
+        let chess_move = ChessMove {
+            promotion: Some('N'),
+            ..ChessMove::new(52, 60)
+        };
+        tt.store(
+            0x1234_5678_9abc_def0,
+            Some(chess_move),
+            -250,
+            7,
+            Bound::Lower,
+        );
+
+        let entry = tt.probe(0x1234_5678_9abc_def0).unwrap();
+        assert_eq!(entry.best_move, Some(chess_move));
+        assert_eq!(entry.score, -250);
+        assert_eq!(entry.depth, 7);
+        assert_eq!(entry.bound, Bound::Lower);
+
+        assert!(tt.probe(0x1234_5678_9abc_def1).is_none());
+        tt.clear();
+        assert!(tt.probe(0x1234_5678_9abc_def0).is_none());
+    }
+
+    #[test]
+    fn test_replacement() {
+        let tt = TranspositionTable::new(1);
+        // Same bucket, different positions
+        let (deep, shallow, newest) = (1 << 8, 2 << 8, 3 << 8);
+
+        tt.store(deep, None, 10, 8, Bound::Exact);
+        tt.store(shallow, None, 20, 2, Bound::Exact);
+        assert_eq!(tt.probe(deep).unwrap().depth, 8);
+        assert_eq!(tt.probe(shallow).unwrap().depth, 2);
+
+        // The shallow slot always takes the newest entry
+        tt.store(newest, None, 30, 1, Bound::Upper);
+        assert!(tt.probe(deep).is_some());
+        assert!(tt.probe(shallow).is_none());
+
+        // Entries from an older search give way to anything, but the deep
+        // one it pushes out moves over rather than being lost straight away
+        tt.new_search();
+        tt.store(shallow, None, 20, 1, Bound::Exact);
+        assert_eq!(tt.probe(shallow).unwrap().depth, 1);
+        assert_eq!(tt.probe(deep).unwrap().depth, 8);
+        assert!(tt.probe(newest).is_none());
+    }
+
+    #[test]
+    fn test_mate_scores_relative_to_node() {
+        let score = MATE - 5;
+        assert_eq!(score_to_tt(score, 3), MATE - 2);
+        assert_eq!(score_from_tt(score_to_tt(score, 3), 1), MATE - 3);
+        assert_eq!(score_to_tt(-MATE + 5, 3), -MATE + 2);
+        assert_eq!(score_to_tt(120, 3), 120);
+
+        // Tablebase wins too
+        assert_eq!(score_to_tt(TB_WIN - 3, 3), TB_WIN);
+        assert_eq!(score_from_tt(TB_WIN, 7), TB_WIN - 7);
+        assert_eq!(score_to_tt(-TB_WIN + 4, 4), -TB_WIN);
+        assert_eq!(score_from_tt(-TB_WIN, 2), -TB_WIN + 2);
+    }
+
+    #[test]
+    fn test_hashfull() {
+        let tt = TranspositionTable::new(1);
+        assert_eq!(tt.hashfull(), 0);
+        for key in 0..100_000u64 {
+            tt.store(
+                key.wrapping_mul(0x9e37_79b9_7f4a_7c15),
+                None,
+                0,
+                1,
+                Bound::Exact,
+            );
+        }
+        assert!(tt.hashfull() > 500);
+        tt.new_search();
+        assert_eq!(tt.hashfull(), 0);
+    }
+}
@@ -0,0 +1,326 @@
+use std::fs;
+use std::path::Path;
+use std::thread;
+
+use crate::{
+    chess_board::{ChessBoard, START_FEN},
+    engine_match::epd_to_fen,
+    eval::{evaluate_with, Tapered, Weights},
+    pgn::parse_pgn,
+    piece::Colour,
+    san::parse_san,
+    weights::WEIGHTS,
+};
+
+pub const DEFAULT_PASSES: usize = 100;
+
+// Positions from PGN games are taken from this ply on, once they're out of
+// the opening
+const FIRST_PGN_PLY: usize = 8;
+
+// A position and how the game went from there, from 1 for a white win down
+// to 0 for a black one
+pub type Labelled = (ChessBoard, f64);
+
+// Reads lines like `<fen> "1-0";`, `<fen> c9 "1/2-1/2";`, `<fen> [0.5]` or
+// `<fen> | <score> | 0.5` as datagen writes them. The result is from
+// white's side.
+fn parse_labelled(line: &str) -> Option<(String, f64)> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let label = fields.get(4..)?.join(" ");
+    let score = |number: &str| {
+        number
+            .trim()
+            .parse()
+            .ok()
+            .filter(|result| (0.0..=1.0).contains(result))
+    };
+    let result = if let Some((_, number)) = label.rsplit_once('|') {
+        score(number)?
+    } else if label.contains("1/2-1/2") {
+        0.5
+    } else if label.contains("1-0") {
+        1.0
+    } else if label.contains("0-1") {
+        0.0
+    } else {
+        let (_, rest) = label.split_once('[')?;
+        let (number, _) = rest.split_once(']')?;
+        score(number)?
+    };
+    Some((epd_to_fen(line), result))
+}
+
+// Every position of every finished game, past the opening and not in check,
+// labelled with the game's result
+fn positions_from_pgn(text: &str) -> Result<Vec<Labelled>, String> {
+    let mut positions = Vec::new();
+    for game in parse_pgn(text) {
+        let result = match game.winner() {
+            Some(Some(Colour::White)) => 1.0,
+            Some(Some(_)) => 0.0,
+            Some(None) => 0.5,
+            None => continue,
+        };
+        let mut board = ChessBoard::from_fen(game.tag("FEN").unwrap_or(START_FEN));
+        for (ply, san) in game.moves.iter().enumerate() {
+            let chess_move =
+                parse_san(&mut board, san).ok_or_else(|| format!("illegal move {}", san))?;
+            board.make_move(chess_move);
+            if ply + 1 >= FIRST_PGN_PLY && !board.is_in_check(board.side_to_move()) {
+                positions.push((board.clone(), result));
+            }
+        }
+    }
+    Ok(positions)
+}
+
+// Labelled positions from an EPD or FEN file, or the games in a PGN file
+pub fn load(path: &Path) -> Result<Vec<Labelled>, String> {
+    let text =
+        fs::read_to_string(path).map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+    let positions = if path.extension().is_some_and(|extension| extension == "pgn") {
+        positions_from_pgn(&text)?
+    } else {
+        text.lines()
+            .filter_map(parse_labelled)
+            .map(|(fen, result)| (ChessBoard::from_fen(&fen), result))
+            .collect()
+    };
+
+    if positions.is_empty() {
+        return Err(format!("no labelled positions in {}", path.display()));
+    }
+    Ok(positions)
+}
+
+// The chance of white winning that an eval suggests, where `scaling` fits
+// the evals to how games turn out
+fn sigmoid(eval: f64, scaling: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-scaling * eval / 400.0))
+}
+
+// Mean squared difference between the results and what the evals predict,
+// spread over a thread per core
+pub fn error(positions: &[Labelled], weights: &Weights, scaling: f64) -> f64 {
+    let threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+    let chunk_size = positions.len().div_ceil(threads).max(1);
+    let total: f64 = thread::scope(|scope| {
+        let chunks: Vec<_> = positions
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|(board, result)| {
+                            let eval = evaluate_with(board, weights).total() as f64;
+                            (result - sigmoid(eval, scaling)).powi(2)
+                        })
+                        .sum::<f64>()
+                })
+            })
+            .collect();
+        chunks
+            .into_iter()
+            .map(|chunk| chunk.join().expect("error thread finished"))
+            .sum()
+    });
+    total / positions.len().max(1) as f64
+}
+
+// The scaling that best fits the evals as they are, found by ternary search,
+// so that tuning only moves the weights relative to each other
+pub fn best_scaling(positions: &[Labelled], weights: &Weights) -> f64 {
+    let (mut low, mut high) = (0.0, 4.0);
+    for _ in 0..40 {
+        let third = (high - low) / 3.0;
+        if error(positions, weights, low + third) < error(positions, weights, high - third) {
+            high -= third;
+        } else {
+            low += third;
+        }
+    }
+    (low + high) / 2.0
+}
+
+// The index'th number in the weights, counting midgame and endgame apart
+fn value_mut(weights: &mut Weights, index: usize) -> Option<&mut i32> {
+    weights
+        .fields()
+        .into_iter()
+        .flat_map(|(_, values)| values.iter_mut())
+        .flat_map(|Tapered { midgame, endgame }| [midgame, endgame])
+        .nth(index)
+}
+
+// Texel's local search: nudges each weight a centipawn either way, keeping
+// any change that lowers the error, until a pass changes nothing. The
+// pawn's midgame value stays at what it is, as the unit the rest are
+// measured in. `on_pass` gets each pass's error and weights.
+pub fn tune<F>(
+    positions: &[Labelled],
+    mut weights: Weights,
+    scaling: f64,
+    passes: usize,
+    mut on_pass: F,
+) -> Weights
+where
+    F: FnMut(usize, f64, &Weights),
+{
+    let mut best = error(positions, &weights, scaling);
+    for pass in 1..=passes {
+        let mut improved = false;
+        let mut index = 1;
+        while value_mut(&mut weights, index).is_some() {
+            for delta in [1, -1] {
+                *value_mut(&mut weights, index).expect("weight exists") += delta;
+                let error = error(positions, &weights, scaling);
+                if error < best {
+                    best = error;
+                    improved = true;
+                    break;
+                }
+                *value_mut(&mut weights, index).expect("weight exists") -= delta;
+            }
+            index += 1;
+        }
+
+        on_pass(pass, best, &weights);
+        if !improved {
+            break;
+        }
+    }
+    weights
+}
+
+// The weights as weights.rs, ready to replace it
+pub fn to_source(weights: &Weights) -> String {
+    let mut weights = *weights;
+    let mut source = String::from(
+        "// Evaluation weights in centipawns, midgame then endgame. `tune` writes this\n\
+         // file.\n\
+         use crate::eval::{tapered, Weights};\n\
+         \n\
+         pub const WEIGHTS: Weights = Weights {\n",
+    );
+    let tapered = |value: &Tapered| format!("tapered({}, {})", value.midgame, value.endgame);
+    for (name, values) in weights.fields() {
+        match values {
+            [value] => source.push_str(&format!("    {}: {},\n", name, tapered(value))),
+            _ => {
+                source.push_str(&format!("    {}: [\n", name));
+                for value in values.iter() {
+                    source.push_str(&format!("        {},\n", tapered(value)));
+                }
+                source.push_str("    ],\n");
+            }
+        }
+    }
+    source.push_str("};\n");
+    source
+}
+
+// Tunes the weights for the `tune` command, writing them to the output file
+// after every pass, or to stdout at the end
+pub fn run(path: &str, output: Option<&Path>, passes: usize) -> Result<(), String> {
+    let positions = load(path.as_ref())?;
+    let scaling = best_scaling(&positions, &WEIGHTS);
+    eprintln!(
+        "{} positions, scaling {:.3}, error {:.6}",
+        positions.len(),
+        scaling,
+        error(&positions, &WEIGHTS, scaling)
+    );
+
+    let mut written = Ok(());
+    let weights = tune(
+        &positions,
+        WEIGHTS,
+        scaling,
+        passes,
+        |pass, error, weights| {
+            eprintln!("Pass {}: error {:.6}", pass, error);
+            if let Some(output) = output {
+                written = fs::write(output, to_source(weights))
+                    .map_err(|e| format!("can't write {}: {}", output.display(), e));
+            }
+        },
+    );
+    written?;
+
+    if output.is_none() {
+        print!("{}", to_source(&weights));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_labelled() {
+        assert_eq!(
+            parse_labelled("4k3/8/8/8/8/8/4P3/4K3 w - - c9 \"1/2-1/2\";"),
+            Some(("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1".to_string(), 0.5))
+        );
+        assert_eq!(
+            parse_labelled("4k3/8/8/8/8/8/4P3/4K3 b - - 3 40 [1.0]"),
+            Some(("4k3/8/8/8/8/8/4P3/4K3 b - - 3 40".to_string(), 1.0))
+        );
+        assert_eq!(
+            parse_labelled("4k3/8/8/8/8/8/4P3/4K3 w - - 0-1").map(|(_, r)| r),
+            Some(0.0)
+        );
+        assert_eq!(
+            parse_labelled("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1 | -35 | 0.0"),
+            Some(("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1".to_string(), 0.0))
+        );
+        assert_eq!(parse_labelled("4k3/8/8/8/8/8/4P3/4K3 w - - [2]"), None);
+        assert_eq!(parse_labelled("4k3/8/8/8/8/8/4P3/4K3 w - -"), None);
+
+        let positions = positions_from_pgn(
+            "1. e4 e5 2. Nf3 Nc6 3. Bc4 Nf6 4. Ng5 d5 5. exd5 Na5 1-0\n\n1. d4 *",
+        )
+        .unwrap();
+        assert_eq!(positions.len(), 3);
+        assert!(positions.iter().all(|&(_, result)| result == 1.0));
+    }
+
+    #[test]
+    fn test_to_source() {
+        // weights.rs is as tune would write it
+        assert_eq!(to_source(&WEIGHTS), include_str!("weights.rs"));
+        let mut weights = WEIGHTS;
+        *value_mut(&mut weights, 3).unwrap() = 290;
+        assert_eq!(weights.knight.endgame, 290);
+        assert!(value_mut(&mut weights, 64).is_none());
+    }
+
+    #[test]
+    fn test_tune() {
+        // White's extra knight wins, and the passed pawns are a draw
+        let positions: Vec<Labelled> = [
+            ("4k3/pp6/8/8/8/8/PP2N3/4K3 w - - 0 1", 1.0),
+            ("4k3/pp6/8/8/8/2N5/PP6/4K3 b - - 0 1", 1.0),
+            ("4k3/7p/8/8/8/8/P7/4K3 w - - 0 1", 0.5),
+            ("4k3/p7/8/8/8/8/7P/4K3 b - - 0 1", 0.5),
+        ]
+        .iter()
+        .map(|&(fen, result)| (ChessBoard::from_fen(fen), result))
+        .collect();
+
+        let scaling = best_scaling(&positions, &WEIGHTS);
+        assert!(scaling > 0.0);
+        let before = error(&positions, &WEIGHTS, scaling);
+        let mut errors = Vec::new();
+        let weights = tune(&positions, WEIGHTS, scaling, 2, |_, error, _| {
+            errors.push(error)
+        });
+        assert_eq!(errors.len(), 2);
+        assert!(errors[1] < before);
+        assert_eq!(error(&positions, &weights, scaling), errors[1]);
+        assert_eq!(weights.pawn.midgame, WEIGHTS.pawn.midgame);
+        assert!(weights.knight.endgame > WEIGHTS.knight.endgame);
+    }
+}
@@ -0,0 +1,747 @@
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::{
+    book::{Book, DEFAULT_BOOK_DEPTH, MAX_BOOK_DEPTH},
+    chess_board::START_FEN,
+    engine::{Engine, Report},
+    eval::{evaluate, evaluate_relative, Evaluation},
+    nnue::Network,
+    piece::Colour,
+    search::{Score, SearchInfo, SearchLimits, SearchParams, MAX_MULTI_PV, MAX_THREADS, TUNABLES},
+    skill::{Skill, MAX_ELO, MAX_SKILL_LEVEL, MIN_ELO},
+    syzygy::Tablebase,
+    time_manager::{DEFAULT_MOVE_OVERHEAD_MS, MAX_MOVE_OVERHEAD_MS},
+    tt::{DEFAULT_HASH_MB, MAX_HASH_MB},
+};
+
+// Speaks the Universal Chess Interface so the engine can be loaded into chess
+// GUIs. See https://www.wbec-ridderkerk.nl/html/UCIProtocol.html
+pub struct Uci<W: Write + Send + 'static> {
+    engine: Engine,
+    output: Arc<Mutex<W>>,
+    // The book is loaded whether or not it's switched on
+    own_book: bool,
+    book: Option<Arc<Book>>,
+}
+
+pub fn run() -> io::Result<()> {
+    let mut uci = Uci::new(io::stdout());
+    for line in io::stdin().lock().lines() {
+        if !uci.handle_command(&line?) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+pub fn send<W: Write>(output: &Mutex<W>, message: &str) {
+    let mut output = output.lock().expect("output lock");
+    // Nothing useful to do if the GUI has gone away
+    let _ = writeln!(output, "{}", message);
+    let _ = output.flush();
+}
+
+pub fn format_score(score: Score) -> String {
+    match score {
+        Score::Centipawns(cp) => format!("cp {}", cp),
+        Score::Mate(moves) => format!("mate {}", moves),
+    }
+}
+
+fn format_info(info: &SearchInfo) -> String {
+    let millis = info.time.as_millis().max(1) as u64;
+    let pv: Vec<String> = info.pv.iter().map(|m| m.to_string()).collect();
+    format!(
+        "info depth {} multipv {} score {} nodes {} nps {} hashfull {} tbhits {} time {} pv {}",
+        info.depth,
+        info.multipv,
+        format_score(info.score),
+        info.nodes,
+        info.nodes * 1000 / millis,
+        info.hashfull,
+        info.tbhits,
+        millis,
+        pv.join(" ")
+    )
+}
+
+fn format_evaluation(evaluation: &Evaluation) -> String {
+    format!(
+        "info string eval material {} psqt {} mobility {} pawns {} king {} bishops {} phase {} total {}",
+        evaluation.material,
+        evaluation.piece_squares,
+        evaluation.mobility,
+        evaluation.pawn_structure,
+        evaluation.king_safety,
+        evaluation.bishop_pair,
+        evaluation.phase,
+        evaluation.total()
+    )
+}
+
+fn parse_millis(value: Option<&&str>) -> Option<Duration> {
+    value
+        .and_then(|v| v.parse::<i64>().ok())
+        // GUIs can send negative times when a clock has run out
+        .map(|millis| Duration::from_millis(millis.max(0) as u64))
+}
+
+pub fn parse_go(args: &[&str]) -> SearchLimits {
+    let mut limits = SearchLimits::default();
+    let mut i = 0;
+
+    while i < args.len() {
+        let value = args.get(i + 1);
+        match args[i] {
+            "depth" => limits.depth = value.and_then(|v| v.parse().ok()),
+            "nodes" => limits.nodes = value.and_then(|v| v.parse().ok()),
+            "movetime" => limits.movetime = parse_millis(value),
+            "wtime" => limits.wtime = parse_millis(value),
+            "btime" => limits.btime = parse_millis(value),
+            "winc" => limits.winc = parse_millis(value),
+            "binc" => limits.binc = parse_millis(value),
+            "movestogo" => limits.movestogo = value.and_then(|v| v.parse().ok()),
+            "infinite" => {
+                limits.infinite = true;
+                i += 1;
+                continue;
+            }
+            _ => {
+                i += 1;
+                continue;
+            }
+        }
+        i += 2;
+    }
+
+    limits
+}
+
+// The go command for the limits, the other way round from parse_go
+pub fn format_go(limits: &SearchLimits) -> String {
+    let mut go = String::from("go");
+    let millis = |duration: Duration| duration.as_millis();
+    if let Some(depth) = limits.depth {
+        go.push_str(&format!(" depth {}", depth));
+    }
+    if let Some(nodes) = limits.nodes {
+        go.push_str(&format!(" nodes {}", nodes));
+    }
+    if let Some(movetime) = limits.movetime {
+        go.push_str(&format!(" movetime {}", millis(movetime)));
+    }
+    for (name, time) in [
+        ("wtime", limits.wtime),
+        ("btime", limits.btime),
+        ("winc", limits.winc),
+        ("binc", limits.binc),
+    ] {
+        if let Some(time) = time {
+            go.push_str(&format!(" {} {}", name, millis(time)));
+        }
+    }
+    if let Some(movestogo) = limits.movestogo {
+        go.push_str(&format!(" movestogo {}", movestogo));
+    }
+    if limits.infinite {
+        go.push_str(" infinite");
+    }
+    go
+}
+
+// Splits "name Hash value 64" into ("Hash", "64"). Names can have spaces.
+pub fn parse_setoption(args: &[&str]) -> Option<(String, String)> {
+    let name_start = args.iter().position(|&arg| arg == "name")? + 1;
+    let value_start = args.iter().position(|&arg| arg == "value");
+
+    let name = args[name_start..value_start.unwrap_or(args.len())].join(" ");
+    let value = match value_start {
+        Some(value_start) => args[value_start + 1..].join(" "),
+        None => String::new(),
+    };
+    Some((name, value))
+}
+
+impl<W: Write + Send + 'static> Uci<W> {
+    pub fn new(output: W) -> Self {
+        Uci {
+            engine: Engine::new(),
+            output: Arc::new(Mutex::new(output)),
+            own_book: false,
+            book: None,
+        }
+    }
+
+    fn send(&self, message: &str) {
+        send(&self.output, message);
+    }
+
+    // Returns false once the GUI asks us to quit
+    pub fn handle_command(&mut self, line: &str) -> bool {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let (command, args) = match tokens.split_first() {
+            Some((command, args)) => (*command, args),
+            None => return true,
+        };
+
+        match command {
+            "uci" => {
+                self.send(&format!(
+                    "id name {} {}",
+                    env!("CARGO_PKG_NAME"),
+                    env!("CARGO_PKG_VERSION")
+                ));
+                self.send("id author Dan");
+                self.send(&format!(
+                    "option name Hash type spin default {} min 1 max {}",
+                    DEFAULT_HASH_MB, MAX_HASH_MB
+                ));
+                self.send("option name Clear Hash type button");
+                self.send(&format!(
+                    "option name Threads type spin default 1 min 1 max {}",
+                    MAX_THREADS
+                ));
+                self.send(&format!(
+                    "option name MultiPV type spin default 1 min 1 max {}",
+                    MAX_MULTI_PV
+                ));
+                self.send(&format!(
+                    "option name Skill Level type spin default {0} min 0 max {0}",
+                    MAX_SKILL_LEVEL
+                ));
+                self.send("option name UCI_LimitStrength type check default false");
+                self.send(&format!(
+                    "option name UCI_Elo type spin default {1} min {0} max {1}",
+                    MIN_ELO, MAX_ELO
+                ));
+                self.send(&format!(
+                    "option name Move Overhead type spin default {} min 0 max {}",
+                    DEFAULT_MOVE_OVERHEAD_MS, MAX_MOVE_OVERHEAD_MS
+                ));
+                self.send("option name OwnBook type check default false");
+                self.send("option name Book File type string default <empty>");
+                self.send(&format!(
+                    "option name Book Depth type spin default {} min 0 max {}",
+                    DEFAULT_BOOK_DEPTH, MAX_BOOK_DEPTH
+                ));
+                self.send("option name SyzygyPath type string default <empty>");
+                self.send("option name EvalFile type string default <empty>");
+                let params = SearchParams::default();
+                for tunable in &TUNABLES {
+                    self.send(&format!(
+                        "option name {} type spin default {} min {} max {}",
+                        tunable.name,
+                        params.tunable(tunable.name).unwrap_or(0),
+                        tunable.min,
+                        tunable.max
+                    ));
+                }
+                self.send("uciok");
+            }
+            "isready" => self.send("readyok"),
+            "ucinewgame" => self.engine.new_game(),
+            "position" => self.position(args),
+            "go" => self.go(args),
+            "stop" => self.engine.stop(),
+            "setoption" => match parse_setoption(args) {
+                Some((name, value)) => self.set_option(&name, &value),
+                None => self.send("info string bad setoption"),
+            },
+            "quit" => {
+                self.engine.stop();
+                return false;
+            }
+            // Not part of UCI, but handy when driving the engine by hand
+            "d" => self.send(&format!("info string fen {}", self.engine.board().to_fen())),
+            "eval" => {
+                let board = self.engine.board();
+                self.send(&format_evaluation(&evaluate(board)));
+                if board.nnue().is_some() {
+                    let eval = match board.side_to_move() {
+                        Colour::White => evaluate_relative(board),
+                        Colour::Black => -evaluate_relative(board),
+                    };
+                    self.send(&format!("info string network eval {}", eval));
+                }
+            }
+            "debug" | "register" | "ponderhit" => {}
+            _ => self.send(&format!("info string unknown command {}", command)),
+        }
+
+        true
+    }
+
+    fn set_option(&mut self, name: &str, value: &str) {
+        match name {
+            "Hash" => match value.parse::<usize>() {
+                Ok(megabytes) if (1..=MAX_HASH_MB).contains(&megabytes) => {
+                    self.engine.set_hash_size(megabytes)
+                }
+                _ => self.send(&format!("info string bad Hash size {}", value)),
+            },
+            "Clear Hash" => self.engine.clear_hash(),
+            "Threads" => match value.parse::<usize>() {
+                Ok(threads) if (1..=MAX_THREADS).contains(&threads) => {
+                    self.engine.set_threads(threads)
+                }
+                _ => self.send(&format!("info string bad Threads {}", value)),
+            },
+            "MultiPV" => match value.parse::<usize>() {
+                Ok(lines) if (1..=MAX_MULTI_PV).contains(&lines) => self.engine.set_multi_pv(lines),
+                _ => self.send(&format!("info string bad MultiPV {}", value)),
+            },
+            "Skill Level" => match value.parse::<u32>() {
+                Ok(level) if level <= MAX_SKILL_LEVEL => {
+                    let skill = self.engine.skill();
+                    self.engine.set_skill(Skill { level, ..skill });
+                }
+                _ => self.send(&format!("info string bad Skill Level {}", value)),
+            },
+            "UCI_LimitStrength" => match value.parse::<bool>() {
+                Ok(limit_strength) => {
+                    let skill = self.engine.skill();
+                    self.engine.set_skill(Skill {
+                        limit_strength,
+                        ..skill
+                    });
+                }
+                _ => self.send(&format!("info string bad UCI_LimitStrength {}", value)),
+            },
+            "UCI_Elo" => match value.parse::<u32>() {
+                Ok(elo) if (MIN_ELO..=MAX_ELO).contains(&elo) => {
+                    let skill = self.engine.skill();
+                    self.engine.set_skill(Skill { elo, ..skill });
+                }
+                _ => self.send(&format!("info string bad UCI_Elo {}", value)),
+            },
+            "Move Overhead" => match value.parse::<u64>() {
+                Ok(millis) if millis <= MAX_MOVE_OVERHEAD_MS => {
+                    self.engine.set_move_overhead(Duration::from_millis(millis))
+                }
+                _ => self.send(&format!("info string bad Move Overhead {}", value)),
+            },
+            "OwnBook" => match value.parse::<bool>() {
+                Ok(own_book) => {
+                    self.own_book = own_book;
+                    self.update_book();
+                }
+                _ => self.send(&format!("info string bad OwnBook {}", value)),
+            },
+            "Book File" => {
+                self.book = None;
+                if !value.is_empty() && value != "<empty>" {
+                    match Book::load(Path::new(value)) {
+                        Ok(book) => self.book = Some(Arc::new(book)),
+                        Err(error) => self.send(&format!("info string {}", error)),
+                    }
+                }
+                self.update_book();
+            }
+            "Book Depth" => match value.parse::<u32>() {
+                Ok(depth) if depth <= MAX_BOOK_DEPTH => self.engine.set_book_depth(depth),
+                _ => self.send(&format!("info string bad Book Depth {}", value)),
+            },
+            "SyzygyPath" => {
+                let mut tablebase = None;
+                if !value.is_empty() && value != "<empty>" {
+                    match Tablebase::open(value) {
+                        Ok(opened) => {
+                            self.send(&format!(
+                                "info string {} tables up to {} pieces",
+                                opened.len(),
+                                opened.max_pieces()
+                            ));
+                            tablebase = Some(Arc::new(opened));
+                        }
+                        Err(error) => self.send(&format!("info string {}", error)),
+                    }
+                }
+                self.engine.set_tablebase(tablebase);
+            }
+            "EvalFile" => {
+                let mut network = None;
+                if !value.is_empty() && value != "<empty>" {
+                    match Network::load(Path::new(value)) {
+                        Ok(loaded) => {
+                            self.send(&format!("info string network {} loaded", value));
+                            network = Some(Arc::new(loaded));
+                        }
+                        Err(error) => self.send(&format!("info string {}", error)),
+                    }
+                }
+                self.engine.set_network(network);
+            }
+            _ if TUNABLES.iter().any(|tunable| tunable.name == name) => {
+                let set = value
+                    .parse()
+                    .is_ok_and(|value| self.engine.set_param(name, value));
+                if !set {
+                    self.send(&format!("info string bad {} value {}", name, value));
+                }
+            }
+            _ => self.send(&format!("info string unknown option {}", name)),
+        }
+    }
+
+    fn update_book(&mut self) {
+        let book = self.book.clone().filter(|_| self.own_book);
+        self.engine.set_book(book);
+    }
+
+    fn position(&mut self, args: &[&str]) {
+        let moves_start = args.iter().position(|&arg| arg == "moves");
+        let moves = match moves_start {
+            Some(moves_start) => &args[moves_start + 1..],
+            None => &[],
+        };
+
+        let fen = match args.first() {
+            Some(&"startpos") => START_FEN.to_string(),
+            Some(&"fen") => args[1..moves_start.unwrap_or(args.len())].join(" "),
+            _ => {
+                self.send("info string position needs startpos or fen");
+                return;
+            }
+        };
+
+        if let Err(error) = self.engine.set_position(&fen, moves) {
+            self.send(&format!("info string {}", error));
+        }
+    }
+
+    fn go(&mut self, args: &[&str]) {
+        let limits = parse_go(args);
+        let output = self.output.clone();
+
+        self.engine.go(limits, move |report| match report {
+            Report::Info(info) => send(&output, &format_info(&info)),
+            Report::BestMove(Some(best_move)) => send(&output, &format!("bestmove {}", best_move)),
+            // No legal moves, the GUI should already know the game is over
+            Report::BestMove(None) => send(&output, "bestmove 0000"),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Runs commands through a session and returns everything it printed
+    fn run_commands(commands: &[&str]) -> String {
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let mut uci = Uci {
+            engine: Engine::new(),
+            output: output.clone(),
+            own_book: false,
+            book: None,
+        };
+        for command in commands {
+            uci.handle_command(command);
+        }
+        uci.handle_command("quit");
+
+        let output = output.lock().unwrap();
+        String::from_utf8(output.clone()).unwrap()
+    }
+
+    #[test]
+    fn test_handshake() {
+        let output = run_commands(&["uci", "isready"]);
+        assert!(output.starts_with("id name chess-rust"));
+        assert!(output.contains("uciok\nreadyok\n"));
+    }
+
+    #[test]
+    fn test_position_and_go() {
+        let output = run_commands(&[
+            "position fen 4k3/8/8/3q4/8/8/8/4K3 w - - 0 1 moves e1e2 d5d2",
+            "go depth 1",
+        ]);
+        assert!(output.ends_with("bestmove e2d2\n"), "{}", output);
+    }
+
+    #[test]
+    fn test_hash_option() {
+        let output = run_commands(&["uci"]);
+        assert!(output.contains("option name Hash type spin default 16 min 1 max 1024\n"));
+
+        let output = run_commands(&[
+            "setoption name Hash value 4",
+            "setoption name Clear Hash",
+            "setoption name Hash value lots",
+            "position startpos",
+            "go depth 2",
+        ]);
+        assert!(
+            output.starts_with("info string bad Hash size lots\n"),
+            "{}",
+            output
+        );
+        assert!(output.contains(" hashfull "), "{}", output);
+    }
+
+    #[test]
+    fn test_threads_option() {
+        let output = run_commands(&["uci"]);
+        assert!(output.contains("option name Threads type spin default 1 min 1 max 64\n"));
+
+        let output = run_commands(&[
+            "setoption name Threads value 0",
+            "setoption name Threads value 2",
+            "position startpos",
+            "go depth 3",
+        ]);
+        assert!(
+            output.starts_with("info string bad Threads 0\n"),
+            "{}",
+            output
+        );
+        assert!(output.contains("bestmove "), "{}", output);
+    }
+
+    #[test]
+    fn test_skill_options() {
+        let output = run_commands(&["uci"]);
+        assert!(output.contains("option name Skill Level type spin default 20 min 0 max 20\n"));
+        assert!(output.contains("option name UCI_Elo type spin default 2850 min 1350 max 2850\n"));
+
+        let output = run_commands(&[
+            "setoption name Skill Level value 21",
+            "setoption name UCI_LimitStrength value maybe",
+            "setoption name UCI_Elo value 1000",
+            "setoption name UCI_LimitStrength value true",
+            "setoption name UCI_Elo value 1500",
+            "position startpos",
+            "go depth 8",
+        ]);
+        assert!(
+            output.starts_with(
+                "info string bad Skill Level 21\n\
+                 info string bad UCI_LimitStrength maybe\n\
+                 info string bad UCI_Elo 1000\n"
+            ),
+            "{}",
+            output
+        );
+        // Weakened to a few plies, and choosing between several lines
+        assert!(!output.contains("info depth 8 "), "{}", output);
+        assert!(output.contains(" multipv 4 "), "{}", output);
+        assert!(output.contains("bestmove "), "{}", output);
+    }
+
+    #[test]
+    fn test_move_overhead_option() {
+        let output = run_commands(&["uci"]);
+        assert!(output.contains("option name Move Overhead type spin default 30 min 0 max 5000\n"));
+
+        let output = run_commands(&[
+            "setoption name Move Overhead value 100",
+            "setoption name Move Overhead value -1",
+        ]);
+        assert_eq!(output, "info string bad Move Overhead -1\n");
+    }
+
+    #[test]
+    fn test_book_options() {
+        let output = run_commands(&["uci"]);
+        assert!(output.contains("option name OwnBook type check default false\n"));
+        assert!(output.contains("option name Book Depth type spin default 20 min 0 max 100\n"));
+
+        // A book with only 1. e4 in it
+        let path = std::env::temp_dir().join(format!("uci-book-{}.bin", std::process::id()));
+        let mut bytes = 0x463b96181691fc9c_u64.to_be_bytes().to_vec();
+        bytes.extend([0x03, 0x1c, 0, 1, 0, 0, 0, 0]);
+        std::fs::write(&path, bytes).unwrap();
+
+        let set_book = format!("setoption name Book File value {}", path.display());
+        let commands = [
+            set_book.as_str(),
+            "setoption name Book Depth value 101",
+            "setoption name OwnBook value true",
+            "position startpos",
+            "go depth 1",
+        ];
+        let output = run_commands(&commands);
+        assert_eq!(
+            output, "info string bad Book Depth 101\nbestmove e2e4\n",
+            "{}",
+            output
+        );
+
+        // Loaded but switched off, so it searches
+        let output = run_commands(&[commands[0], "position startpos", "go depth 1"]);
+        assert!(output.starts_with("info depth 1 "), "{}", output);
+        std::fs::remove_file(&path).unwrap();
+
+        let output = run_commands(&["setoption name Book File value /no/such/book.bin"]);
+        assert!(output.starts_with("info string can't read"), "{}", output);
+    }
+
+    #[test]
+    fn test_syzygy_path_option() {
+        let output = run_commands(&["uci"]);
+        assert!(output.contains("option name SyzygyPath type string default <empty>\n"));
+
+        let output = run_commands(&["setoption name SyzygyPath value /no/such/tables"]);
+        assert!(output.starts_with("info string can't read"), "{}", output);
+
+        // The tables checked in under tests/syzygy, where Qh8 and Qb7 mate
+        let set_path = concat!(
+            "setoption name SyzygyPath value ",
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/syzygy"
+        );
+        let output = run_commands(&[
+            set_path,
+            "position fen k7/8/1K6/8/8/8/8/7Q w - - 0 1",
+            "go depth 1",
+        ]);
+        assert!(output.contains(" tbhits "), "{}", output);
+        assert!(
+            output.ends_with("bestmove h1h8\n") || output.ends_with("bestmove h1b7\n"),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn test_eval_file_option() {
+        let output = run_commands(&["uci"]);
+        assert!(output.contains("option name EvalFile type string default <empty>\n"));
+
+        let output = run_commands(&["setoption name EvalFile value /no/such/network.bin"]);
+        assert!(output.starts_with("info string can't read"), "{}", output);
+
+        let path = std::env::temp_dir().join(format!("uci-network-{}.bin", std::process::id()));
+        std::fs::write(&path, crate::nnue::tests::random_network_bytes(1)).unwrap();
+        let set_network = format!("setoption name EvalFile value {}", path.display());
+        let output = run_commands(&[
+            set_network.as_str(),
+            "position startpos moves e2e4",
+            "eval",
+            "go depth 2",
+        ]);
+        let lines: Vec<&str> = output.lines().collect();
+        assert!(lines[0].ends_with(" loaded"), "{}", output);
+        assert!(
+            lines[2].starts_with("info string network eval "),
+            "{}",
+            output
+        );
+        assert!(output.contains("bestmove "), "{}", output);
+
+        // Back to the handcrafted evaluation
+        let output = run_commands(&[
+            set_network.as_str(),
+            "setoption name EvalFile value <empty>",
+            "eval",
+        ]);
+        assert!(!output.contains("network eval"), "{}", output);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_tunable_options() {
+        let output = run_commands(&["uci"]);
+        assert!(output.contains(
+            "option name NullMoveReduction type spin default 3 min 0 max 6
+"
+        ));
+
+        let output = run_commands(&[
+            "setoption name NullMoveReduction value 0",
+            "setoption name AspirationWindow value -5",
+            "setoption name LMRMinDepth value deep",
+            "position startpos",
+            "go depth 2",
+        ]);
+        assert!(
+            output.starts_with(
+                "info string bad AspirationWindow value -5\ninfo string bad LMRMinDepth value deep\n"
+            ),
+            "{}",
+            output
+        );
+        assert!(output.contains("bestmove "), "{}", output);
+    }
+
+    #[test]
+    fn test_eval_breakdown() {
+        let output = run_commands(&["position startpos", "eval"]);
+        assert_eq!(
+            output,
+            "info string eval material 0 psqt 0 mobility 0 pawns 0 king 0 bishops 0 phase 24 total 0\n"
+        );
+    }
+
+    #[test]
+    fn test_illegal_position_move() {
+        let output = run_commands(&["position startpos moves e2e5"]);
+        assert_eq!(output, "info string illegal move e2e5\n");
+    }
+
+    #[test]
+    fn test_parse_go() {
+        let limits = parse_go(&[
+            "wtime",
+            "60000",
+            "btime",
+            "-10",
+            "winc",
+            "1000",
+            "binc",
+            "1000",
+            "movestogo",
+            "20",
+        ]);
+        assert_eq!(limits.wtime, Some(Duration::from_secs(60)));
+        assert_eq!(limits.btime, Some(Duration::ZERO));
+        assert_eq!(limits.winc, Some(Duration::from_secs(1)));
+        assert_eq!(limits.movestogo, Some(20));
+
+        let limits = parse_go(&["infinite"]);
+        assert!(limits.infinite);
+
+        let limits = parse_go(&["depth", "6", "movetime", "500"]);
+        assert_eq!(limits.depth, Some(6));
+        assert_eq!(limits.movetime, Some(Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn test_format_go() {
+        let limits = SearchLimits {
+            wtime: Some(Duration::from_secs(60)),
+            btime: Some(Duration::from_millis(59500)),
+            winc: Some(Duration::from_secs(1)),
+            binc: Some(Duration::from_secs(1)),
+            ..SearchLimits::default()
+        };
+        let go = format_go(&limits);
+        assert_eq!(go, "go wtime 60000 btime 59500 winc 1000 binc 1000");
+        let args: Vec<&str> = go.split_whitespace().skip(1).collect();
+        assert_eq!(parse_go(&args), limits);
+
+        let limits = SearchLimits {
+            depth: Some(6),
+            movetime: Some(Duration::from_millis(500)),
+            ..SearchLimits::default()
+        };
+        assert_eq!(format_go(&limits), "go depth 6 movetime 500");
+    }
+
+    #[test]
+    fn test_parse_setoption() {
+        assert_eq!(
+            parse_setoption(&["name", "Skill", "Level", "value", "5"]),
+            Some(("Skill Level".to_string(), "5".to_string()))
+        );
+        assert_eq!(
+            parse_setoption(&["name", "Clear", "Hash"]),
+            Some(("Clear Hash".to_string(), String::new()))
+        );
+        assert_eq!(parse_setoption(&["Hash"]), None);
+    }
+}
@@ -1,48 +1,285 @@
 use tui::backend::Backend;
-use tui::layout::{Alignment, Constraint, Direction, Layout};
+use tui::layout::{Alignment, Constraint, Direction, Layout, Rect};
 use tui::style::{Color, Style};
 use tui::text::{Span, Spans};
 use tui::widgets::{Block, BorderType, Borders, Paragraph};
 use tui::Frame;
 use unicode_width::UnicodeWidthStr;
 
+use std::time::Instant;
+
+use crate::analysis::{format_eval, white_score, white_share};
 use crate::app::App;
 use crate::chess_board::{ChessBoard, SquareIndex, TOTAL_RANKS};
-// use crate::app::App;
+use crate::clock::ChessClock;
+use crate::material::captures_from_history;
+use crate::piece::Colour;
+use crate::piece::Piece;
+use crate::san::format_line;
+use crate::syzygy::Wdl;
+
+const TITLE_HEIGHT: u16 = 3;
+const CLOCK_WIDTH: u16 = 14;
+const EVAL_BAR_WIDTH: u16 = 4;
+const SIDE_PANEL_MIN_WIDTH: u16 = 30;
+const SIDE_PANEL_MIN_HEIGHT: u16 = 10;
+
+// Each square is 2 characters wide and 1 high, multiplied up by the scale.
+// The extra space is for the rank and file labels and the border.
+fn board_width(scale: u16) -> u16 {
+    16 * scale + 7
+}
+
+fn board_height(scale: u16, panel_height: u16) -> u16 {
+    (8 * scale + 4).max(panel_height)
+}
+
+// The space needed besides the board: the clock and eval bar beside it, and
+// the height of the side panel
+fn fits(size: &Rect, scale: u16, extra_width: u16, panel_height: u16) -> bool {
+    size.width >= board_width(scale) + extra_width + SIDE_PANEL_MIN_WIDTH
+        && size.height >= TITLE_HEIGHT + board_height(scale, panel_height)
+}
+
+// A line for the depth and speed, and one for each best line
+fn analysis_height(app: &App) -> u16 {
+    if app.analysing {
+        app.multi_pv as u16 + 3
+    } else {
+        0
+    }
+}
 
 pub fn draw<B>(rect: &mut Frame<B>, app: &App, chessboard: &ChessBoard)
 where
     B: Backend,
 {
     let size = rect.size();
-    // check_size(&size);
+    let clock_width = if app.clock.is_some() { CLOCK_WIDTH } else { 0 };
+    let bar_width = if app.analysing { EVAL_BAR_WIDTH } else { 0 };
+    let extra_width = clock_width + bar_width;
+    let panel_height = SIDE_PANEL_MIN_HEIGHT + analysis_height(app);
+
+    if !fits(&size, 1, extra_width, panel_height) {
+        rect.render_widget(draw_too_small(&size, extra_width, panel_height), size);
+        return;
+    }
+
+    let scale = if app.large_board {
+        (1..=3)
+            .rev()
+            .find(|&scale| fits(&size, scale, extra_width, panel_height))
+            .unwrap_or(1)
+    } else {
+        1
+    };
 
     // Vertical layout
     let chunks = Layout::default()
         .direction(Direction::Vertical)
-        .constraints([Constraint::Length(3), Constraint::Min(12)].as_ref())
+        .constraints(
+            [
+                Constraint::Length(TITLE_HEIGHT),
+                Constraint::Min(board_height(scale, panel_height)),
+            ]
+            .as_ref(),
+        )
         .split(size);
 
     let main_chunks = Layout::default()
         .direction(Direction::Horizontal)
-        .constraints([Constraint::Length(23), Constraint::Min(12)].as_ref())
+        .constraints(
+            [
+                Constraint::Length(board_width(scale)),
+                Constraint::Length(bar_width),
+                Constraint::Length(clock_width),
+                Constraint::Min(SIDE_PANEL_MIN_WIDTH),
+            ]
+            .as_ref(),
+        )
         .split(chunks[1]);
 
     // Add widgets
     let title = draw_title();
-    let chessboard = draw_chessboard(chessboard);
-    let user_input = draw_user_input(app);
+    let user_input = draw_user_input(app, chessboard);
+    let board = draw_chessboard(app, chessboard, scale);
     rect.render_widget(title, chunks[0]);
     // rect.render_widget(main_chunks, chunks[1]);
-    rect.render_widget(chessboard, main_chunks[0]);
-    rect.render_widget(user_input, main_chunks[1]);
+    rect.render_widget(board, main_chunks[0]);
+    if app.analysing {
+        let bar_height = main_chunks[1].height.saturating_sub(2);
+        rect.render_widget(draw_eval_bar(app, chessboard, bar_height), main_chunks[1]);
+    }
+    if let Some(clock) = &app.clock {
+        rect.render_widget(draw_clocks(clock), main_chunks[2]);
+    }
+    let side_chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            [
+                Constraint::Min(6),
+                Constraint::Length(analysis_height(app)),
+                Constraint::Length(4),
+            ]
+            .as_ref(),
+        )
+        .split(main_chunks[3]);
+    rect.render_widget(user_input, side_chunks[0]);
+    if app.analysing {
+        rect.render_widget(draw_analysis(app, chessboard), side_chunks[1]);
+    }
+    rect.render_widget(draw_captures(app), side_chunks[2]);
 
     rect.set_cursor(
-        main_chunks[1].x + app.ui_buffer.width() as u16 + 1,
-        main_chunks[1].y + 2,
+        main_chunks[3].x + app.ui_buffer.width() as u16 + 1,
+        main_chunks[3].y + 2,
     )
 }
 
+// White's share of the bar fills from the bottom, by the best line's score
+fn draw_eval_bar<'a>(app: &App, chessboard: &ChessBoard, height: u16) -> Paragraph<'a> {
+    let share = app.analysis.lines.first().map_or(0.5, |line| {
+        white_share(white_score(line.score, chessboard.side_to_move()))
+    });
+    let white_rows = (share * height as f64).round() as u16;
+    let bar_lines: Vec<Spans> = (0..height)
+        .map(|row| {
+            let colour = if row >= height - white_rows {
+                app.theme.white_pieces
+            } else {
+                app.theme.black_pieces
+            };
+            Spans::from(Span::styled("  ", Style::default().bg(colour)))
+        })
+        .collect();
+
+    Paragraph::new(bar_lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .style(Style::default().fg(Color::White))
+            .border_type(BorderType::Plain),
+    )
+}
+
+fn draw_analysis<'a>(app: &App, chessboard: &ChessBoard) -> Paragraph<'a> {
+    let analysis = &app.analysis;
+    let mut analysis_lines = vec![Spans::from(format!(
+        "Depth {}, {}k nodes/s",
+        analysis.depth,
+        analysis.nodes_per_second / 1000
+    ))];
+    for line in &analysis.lines {
+        let score = white_score(line.score, chessboard.side_to_move());
+        analysis_lines.push(Spans::from(vec![
+            Span::styled(
+                format!("{:>6} ", format_eval(score)),
+                Style::default().fg(Color::LightCyan),
+            ),
+            Span::raw(format_line(chessboard, &line.pv)),
+        ]));
+    }
+
+    Paragraph::new(analysis_lines)
+        .alignment(Alignment::Left)
+        .block(
+            Block::default()
+                .title("Analysis")
+                .borders(Borders::ALL)
+                .style(Style::default().fg(Color::White))
+                .border_type(BorderType::Plain),
+        )
+}
+
+fn draw_captures<'a>(app: &App) -> Paragraph<'a> {
+    let captures = captures_from_history(app.history());
+    let glyphs = |symbols: &[char]| -> String {
+        symbols
+            .iter()
+            .map(|&symbol| {
+                if app.ascii_pieces {
+                    symbol
+                } else {
+                    Piece::new(symbol).get_graphic()
+                }
+            })
+            .collect()
+    };
+    let advantage = |sign: i32| -> String {
+        if captures.balance * sign > 0 {
+            format!(" +{}", captures.balance.abs())
+        } else {
+            String::new()
+        }
+    };
+
+    let capture_lines = vec![
+        Spans::from(vec![
+            Span::raw("White: "),
+            Span::styled(
+                glyphs(&captures.by_white),
+                Style::default()
+                    .fg(app.theme.black_pieces)
+                    .bg(app.theme.squares.light),
+            ),
+            Span::raw(advantage(1)),
+        ]),
+        Spans::from(vec![
+            Span::raw("Black: "),
+            Span::styled(
+                glyphs(&captures.by_black),
+                Style::default()
+                    .fg(app.theme.white_pieces)
+                    .bg(app.theme.squares.dark),
+            ),
+            Span::raw(advantage(-1)),
+        ]),
+    ];
+
+    Paragraph::new(capture_lines)
+        .style(Style::default().fg(Color::LightCyan))
+        .alignment(Alignment::Left)
+        .block(
+            Block::default()
+                .title("Captured")
+                .borders(Borders::ALL)
+                .style(Style::default().fg(Color::White))
+                .border_type(BorderType::Plain),
+        )
+}
+
+fn draw_clocks<'a>(clock: &ChessClock) -> Paragraph<'a> {
+    let now = Instant::now();
+    let mut clock_lines = vec![];
+
+    // Black sits at the top of the board, so its clock goes first
+    for (name, colour) in [("Black", Colour::Black), ("White", Colour::White)] {
+        let remaining = clock.remaining(colour, now);
+        let style = if remaining.is_zero() {
+            Style::default().fg(Color::Red)
+        } else if clock.running_side() == Some(colour) {
+            Style::default().fg(Color::LightCyan)
+        } else {
+            Style::default().fg(Color::White)
+        };
+
+        clock_lines.push(Spans::from(Span::styled(name, style)));
+        clock_lines.push(Spans::from(Span::styled(
+            ChessClock::format(remaining),
+            style,
+        )));
+        clock_lines.push(Spans::from(""));
+    }
+
+    Paragraph::new(clock_lines)
+        .alignment(Alignment::Center)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .style(Style::default().fg(Color::White))
+                .border_type(BorderType::Plain),
+        )
+}
+
 fn draw_title<'a>() -> Paragraph<'a> {
     Paragraph::new("♞  Dan's Rusty Chess ♞")
         .style(Style::default().fg(Color::LightCyan))
@@ -55,12 +292,77 @@ fn draw_title<'a>() -> Paragraph<'a> {
         )
 }
 
-fn draw_user_input(app: &App) -> Paragraph {
+fn draw_user_input<'a>(app: &'a App, chessboard: &ChessBoard) -> Paragraph<'a> {
+    let side_to_move = match chessboard.side_to_move() {
+        Colour::White => "White",
+        Colour::Black => "Black",
+    };
     let mut ui_texts = vec![
-        Spans::from("Enter square to show moves: ".to_string()),
+        Spans::from(format!(
+            "{} to move. Enter square to show moves, then a highlit square to move (or undo, annotate): ",
+            side_to_move
+        )),
         Spans::from(app.ui_buffer.to_string()),
     ];
 
+    if let Some(result) = &app.result {
+        ui_texts.push(Spans::from(result.describe()));
+    }
+
+    if app.thinking {
+        ui_texts.push(Spans::from("Engine thinking..."));
+    }
+
+    if let Some(status) = &app.annotation_status {
+        ui_texts.extend(status.lines().map(Spans::from));
+    }
+
+    if let Some(error) = &app.engine_error {
+        ui_texts.push(Spans::from(format!("Engine error: {}", error)));
+    }
+
+    if let Some(book) = &app.book {
+        let moves = book.moves(chessboard);
+        let total: u32 = moves.iter().map(|&(_, weight)| weight as u32).sum();
+        if !moves.is_empty() {
+            let moves: Vec<String> = moves
+                .iter()
+                .map(|(chess_move, weight)| {
+                    format!("{} ({}%)", chess_move, *weight as u32 * 100 / total.max(1))
+                })
+                .collect();
+            ui_texts.push(Spans::from(format!("Book: {}", moves.join(", "))));
+        }
+    }
+
+    if let Some(tablebase) = &app.tablebase {
+        let mut board = chessboard.clone();
+        if let Some(wdl) = tablebase.probe_wdl(&mut board) {
+            let winner = match wdl {
+                Wdl::Win | Wdl::CursedWin => side_to_move,
+                _ if chessboard.side_to_move() == Colour::White => "Black",
+                _ => "White",
+            };
+            let dtz = tablebase.probe_dtz(&mut board).unwrap_or(0).abs();
+            ui_texts.push(Spans::from(match wdl {
+                Wdl::Win | Wdl::Loss => format!("Tablebase: {} wins, DTZ {}", winner, dtz),
+                Wdl::CursedWin | Wdl::BlessedLoss => format!(
+                    "Tablebase: {} wins but for the fifty move rule, DTZ {}",
+                    winner, dtz
+                ),
+                Wdl::Draw => "Tablebase: draw".to_string(),
+            }));
+        }
+    }
+
+    if let Some(last_move) = app.get_last_move() {
+        ui_texts.push(Spans::from(format!(
+            "Last move: {}{}",
+            ChessBoard::square_to_notation(last_move.source).unwrap(),
+            ChessBoard::square_to_notation(last_move.destination).unwrap()
+        )));
+    }
+
     if let Some(square) = app.get_selected_square() {
         let notationed_moves: Vec<String> = app
             .available_moves()
@@ -86,63 +388,95 @@ fn draw_user_input(app: &App) -> Paragraph {
         )
 }
 
-fn draw_chessboard(chessboard: &ChessBoard) -> Paragraph {
+fn draw_too_small<'a>(size: &Rect, extra_width: u16, panel_height: u16) -> Paragraph<'a> {
+    let text = vec![
+        Spans::from("Terminal too small"),
+        Spans::from(format!(
+            "Need {}x{}, got {}x{}",
+            board_width(1) + extra_width + SIDE_PANEL_MIN_WIDTH,
+            TITLE_HEIGHT + board_height(1, panel_height),
+            size.width,
+            size.height
+        )),
+    ];
+
+    Paragraph::new(text)
+        .style(Style::default().fg(Color::LightCyan))
+        .alignment(Alignment::Center)
+}
+
+fn file_labels<'a>(scale: usize) -> Spans<'a> {
+    let mut labels = "  ".to_string();
+    for file in "abcdefgh".chars() {
+        labels.push_str(&" ".repeat(scale - 1));
+        labels.push(file);
+        labels.push_str(&" ".repeat(scale));
+    }
+    Spans::from(labels)
+}
+
+fn draw_chessboard<'a>(app: &App, chessboard: &ChessBoard, scale: u16) -> Paragraph<'a> {
+    let scale = scale as usize;
     let mut board_lines: Vec<Spans> = Vec::new();
 
-    board_lines.push(Spans::from(vec![Span::raw("  a b c d e f g h")]));
+    board_lines.push(file_labels(scale));
 
     for rank in (1..=TOTAL_RANKS).rev() {
-        let mut board_line: Vec<Span> = Vec::new();
-        board_line.push(Span::raw(format!("{} ", rank)));
+        // Pieces and rank labels go on the middle row of each square
+        for row in 0..scale {
+            let is_middle = row == scale / 2;
+            let rank_label = if is_middle {
+                rank.to_string()
+            } else {
+                " ".to_string()
+            };
 
-        for (i, piece) in chessboard
-            .get_pieces_on_rank(rank.into())
-            .iter()
-            .enumerate()
-        {
-            let piece_symbol;
-            let square_colour;
-            let mut piece_colour = Color::White;
-
-            if let Some(piece) = piece {
-                piece_symbol = format!("{} ", piece.get_graphic());
-                piece_colour = if piece.is_black() {
-                    Color::Black
-                } else {
-                    Color::White
+            let mut board_line: Vec<Span> = Vec::new();
+            board_line.push(Span::raw(format!("{} ", rank_label)));
+
+            for (i, piece) in chessboard
+                .get_pieces_on_rank(rank.into())
+                .iter()
+                .enumerate()
+            {
+                let mut piece_colour = app.theme.white_pieces;
+
+                let piece_symbol = match piece {
+                    Some(piece) if is_middle => {
+                        piece_colour = app.theme.piece_colour(piece.is_black());
+                        if app.ascii_pieces {
+                            piece.get_ascii()
+                        } else {
+                            piece.get_graphic()
+                        }
+                    }
+                    _ => ' ',
                 };
-            } else {
-                piece_symbol = "  ".to_string();
-            }
+                let cell = format!(
+                    "{}{}{}",
+                    " ".repeat(scale - 1),
+                    piece_symbol,
+                    " ".repeat(scale)
+                );
 
-            let square_index: SquareIndex =
-                ChessBoard::square_from_file_and_rank(i as u8, rank - 1).unwrap();
-            if (i + (rank as usize)) % 2 == 0 {
-                square_colour = if chessboard.is_highlit(square_index) {
-                    Color::Rgb(255, 189, 123)
-                } else {
-                    Color::White
-                    // Color::Rgb(168, 123, 80)
-                }
-            } else {
-                square_colour = if chessboard.is_highlit(square_index) {
-                    Color::Rgb(240, 179, 64)
-                } else {
-                    // Color::Rgb(100, 70, 25)
-                    Color::Black
-                }
+                let square_index: SquareIndex =
+                    ChessBoard::square_from_file_and_rank(i as u8, rank - 1).unwrap();
+                let is_light = (i + (rank as usize)).is_multiple_of(2);
+                let square_colour = app
+                    .theme
+                    .square_colour(is_light, app.get_highlight(chessboard, square_index));
+
+                board_line.push(Span::styled(
+                    cell,
+                    Style::default().fg(piece_colour).bg(square_colour),
+                ));
             }
+            board_line.push(Span::raw(format!(" {}", rank_label)));
 
-            board_line.push(Span::styled(
-                piece_symbol,
-                Style::default().fg(piece_colour).bg(square_colour),
-            ));
+            board_lines.push(Spans::from(board_line));
         }
-        board_line.push(Span::raw(format!(" {}", rank)));
-
-        board_lines.push(Spans::from(board_line));
     }
-    board_lines.push(Spans::from(vec![Span::raw("  a b c d e f g h")]));
+    board_lines.push(file_labels(scale));
 
     let chessboard_ui = Paragraph::new(board_lines)
         .alignment(Alignment::Left)
@@ -155,12 +489,3 @@ fn draw_chessboard(chessboard: &ChessBoard) -> Paragraph {
 
     chessboard_ui
 }
-
-// fn check_size(rect: &Rect) {
-//     if rect.width < 52 {
-//         panic!("Require width >= 52, (got {})", rect.width);
-//     }
-//     if rect.height < 10 {
-//         panic!("Require height >= 28, (got {})", rect.height);
-//     }
-// }
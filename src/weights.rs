@@ -0,0 +1,31 @@
+// Evaluation weights in centipawns, midgame then endgame. `tune` writes this
+// file.
+use crate::eval::{tapered, Weights};
+
+pub const WEIGHTS: Weights = Weights {
+    pawn: tapered(100, 120),
+    knight: tapered(320, 300),
+    bishop: tapered(330, 320),
+    rook: tapered(500, 550),
+    queen: tapered(900, 950),
+    knight_mobility: tapered(4, 4),
+    bishop_mobility: tapered(5, 5),
+    rook_mobility: tapered(2, 4),
+    queen_mobility: tapered(1, 2),
+    doubled_pawn: tapered(-10, -20),
+    isolated_pawn: tapered(-15, -20),
+    passed_pawn: [
+        tapered(0, 0),
+        tapered(5, 10),
+        tapered(10, 20),
+        tapered(20, 40),
+        tapered(35, 70),
+        tapered(60, 120),
+        tapered(100, 200),
+        tapered(0, 0),
+    ],
+    pawn_shield: tapered(10, 0),
+    open_file_by_king: tapered(-15, 0),
+    king_attack: tapered(-8, 0),
+    bishop_pair: tapered(30, 50),
+};
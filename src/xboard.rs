@@ -0,0 +1,441 @@
+use std::io::{self, BufRead, Write};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::{
+    chess_move::ChessMove,
+    engine::{Engine, Report},
+    piece::Colour,
+    search::{Score, SearchInfo, SearchLimits},
+    uci::send,
+};
+
+// Speaks the Chess Engine Communication Protocol (xboard/WinBoard), version 2.
+// See https://www.gnu.org/software/xboard/engine-intf.html
+pub struct Xboard<W: Write + Send + 'static> {
+    engine: Engine,
+    output: Arc<Mutex<W>>,
+    // The side the engine plays, None in force mode
+    engine_colour: Option<Colour>,
+    post: bool,
+    // Moves per time control, base time and increment from `level`
+    moves_per_session: Option<u32>,
+    increment: Duration,
+    engine_time: Option<Duration>,
+    opponent_time: Option<Duration>,
+    move_time: Option<Duration>,
+    depth: Option<u32>,
+    // Set by the search thread, played on the board before the next command
+    pending_move: Arc<Mutex<Option<ChessMove>>>,
+}
+
+pub fn run() -> io::Result<()> {
+    let mut xboard = Xboard::new(io::stdout());
+    for line in io::stdin().lock().lines() {
+        if !xboard.handle_command(&line?) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+// Thinking output is "ply score time nodes pv" with time in centiseconds.
+// Mates are shown as 100000 + moves, the usual convention for xboard GUIs.
+fn format_thinking(info: &SearchInfo) -> String {
+    let score = match info.score {
+        Score::Centipawns(cp) => cp,
+        Score::Mate(moves) if moves > 0 => 100000 + moves,
+        Score::Mate(moves) => -100000 + moves,
+    };
+    let pv: Vec<String> = info.pv.iter().map(|m| m.to_string()).collect();
+    format!(
+        "{} {} {} {} {}",
+        info.depth,
+        score,
+        info.time.as_millis() / 10,
+        info.nodes,
+        pv.join(" ")
+    )
+}
+
+// Base time is minutes, or minutes:seconds
+fn parse_base_time(base: &str) -> Option<Duration> {
+    match base.split_once(':') {
+        Some((minutes, seconds)) => Some(Duration::from_secs(
+            minutes.parse::<u64>().ok()? * 60 + seconds.parse::<u64>().ok()?,
+        )),
+        None => Duration::try_from_secs_f64(base.parse::<f64>().ok()? * 60.0).ok(),
+    }
+}
+
+fn parse_centiseconds(value: Option<&&str>) -> Option<Duration> {
+    value
+        .and_then(|v| v.parse::<i64>().ok())
+        .map(|cs| Duration::from_millis(cs.max(0) as u64 * 10))
+}
+
+impl<W: Write + Send + 'static> Xboard<W> {
+    pub fn new(output: W) -> Self {
+        Xboard {
+            engine: Engine::new(),
+            output: Arc::new(Mutex::new(output)),
+            engine_colour: Some(Colour::Black),
+            post: false,
+            moves_per_session: None,
+            increment: Duration::ZERO,
+            engine_time: None,
+            opponent_time: None,
+            move_time: None,
+            depth: None,
+            pending_move: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    fn send(&self, message: &str) {
+        send(&self.output, message);
+    }
+
+    // Returns false once the GUI asks us to quit
+    pub fn handle_command(&mut self, line: &str) -> bool {
+        self.play_pending_move();
+
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let (command, args) = match tokens.split_first() {
+            Some((command, args)) => (*command, args),
+            None => return true,
+        };
+
+        match command {
+            "protover" => self.send(&format!(
+                "feature myname=\"{} {}\" ping=1 setboard=1 usermove=1 time=1 draw=0 \
+                 sigint=0 sigterm=0 reuse=1 analyze=0 colors=0 done=1",
+                env!("CARGO_PKG_NAME"),
+                env!("CARGO_PKG_VERSION")
+            )),
+            "new" => {
+                self.engine.new_game();
+                self.engine_colour = Some(Colour::Black);
+                self.move_time = None;
+                self.depth = None;
+            }
+            "force" => {
+                self.engine.stop();
+                self.discard_pending_move();
+                self.engine_colour = None;
+            }
+            "go" => {
+                self.engine_colour = Some(self.engine.board().side_to_move());
+                self.think();
+            }
+            "usermove" => match args.first() {
+                Some(notation) => self.user_move(notation),
+                None => self.send("Error (no move given): usermove"),
+            },
+            "setboard" => {
+                let fen = args.join(" ");
+                if self.engine.set_position(&fen, &[]).is_err() {
+                    self.send(&format!("tellusererror Illegal position: {}", fen));
+                }
+            }
+            "time" => self.engine_time = parse_centiseconds(args.first()),
+            "otim" => self.opponent_time = parse_centiseconds(args.first()),
+            "level" => self.level(args),
+            "st" => {
+                self.move_time = args
+                    .first()
+                    .and_then(|v| v.parse::<f64>().ok())
+                    .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+            }
+            "sd" => self.depth = args.first().and_then(|v| v.parse().ok()),
+            "undo" => {
+                self.engine.stop();
+                self.discard_pending_move();
+                self.engine.undo();
+            }
+            "remove" => {
+                self.engine.stop();
+                self.discard_pending_move();
+                self.engine.undo();
+                self.engine.undo();
+            }
+            "result" => {
+                self.engine.stop();
+                self.discard_pending_move();
+                self.engine_colour = None;
+            }
+            "?" => {
+                // Move now
+                self.engine.stop();
+                self.play_pending_move();
+            }
+            "post" => self.post = true,
+            "nopost" => self.post = false,
+            "ping" => self.send(&format!("pong {}", args.first().unwrap_or(&""))),
+            "quit" => {
+                self.engine.stop();
+                return false;
+            }
+            "xboard" | "accepted" | "rejected" | "random" | "hard" | "easy" | "computer"
+            | "name" | "rating" | "ics" | "white" | "black" => {}
+            _ => self.send(&format!("Error (unknown command): {}", command)),
+        }
+
+        true
+    }
+
+    fn level(&mut self, args: &[&str]) {
+        let moves_per_session = args.first().and_then(|v| v.parse::<u32>().ok());
+        let base = args.get(1).and_then(|v| parse_base_time(v));
+        let increment = args
+            .get(2)
+            .and_then(|v| v.parse::<f64>().ok())
+            .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok());
+
+        match (moves_per_session, base, increment) {
+            (Some(moves_per_session), Some(base), Some(increment)) => {
+                self.moves_per_session = Some(moves_per_session).filter(|&moves| moves > 0);
+                self.increment = increment;
+                self.engine_time = Some(base);
+                self.opponent_time = Some(base);
+                self.move_time = None;
+            }
+            _ => self.send(&format!("Error (bad level): level {}", args.join(" "))),
+        }
+    }
+
+    fn user_move(&mut self, notation: &str) {
+        // Any move we've already announced has to be on the board first
+        self.engine.stop();
+        self.play_pending_move();
+
+        if self.engine.play(notation).is_err() {
+            self.send(&format!("Illegal move: {}", notation));
+            return;
+        }
+        if self.announce_game_over() {
+            return;
+        }
+
+        if self.engine_colour == Some(self.engine.board().side_to_move()) {
+            self.think();
+        }
+    }
+
+    fn limits(&self) -> SearchLimits {
+        let mut limits = SearchLimits {
+            depth: self.depth,
+            movetime: self.move_time,
+            ..SearchLimits::default()
+        };
+
+        if self.move_time.is_none() {
+            let (our_time, our_increment) = (self.engine_time, Some(self.increment));
+            let moves_played = self.engine.board().fullmove_number() - 1;
+            limits.movestogo = self
+                .moves_per_session
+                .map(|moves| moves - moves_played % moves);
+
+            match self.engine.board().side_to_move() {
+                Colour::White => {
+                    limits.wtime = our_time;
+                    limits.winc = our_increment;
+                    limits.btime = self.opponent_time;
+                    limits.binc = our_increment;
+                }
+                Colour::Black => {
+                    limits.btime = our_time;
+                    limits.binc = our_increment;
+                    limits.wtime = self.opponent_time;
+                    limits.winc = our_increment;
+                }
+            }
+        }
+
+        limits
+    }
+
+    fn think(&mut self) {
+        let limits = self.limits();
+        let output = self.output.clone();
+        let pending_move = self.pending_move.clone();
+        let post = self.post;
+
+        self.engine.go(limits, move |report| match report {
+            Report::Info(info) => {
+                // xboard only knows about one line
+                if post && info.multipv == 1 {
+                    send(&output, &format_thinking(&info));
+                }
+            }
+            Report::BestMove(Some(best_move)) => {
+                // Record the move before telling the GUI, so it's on our
+                // board by the time the reply comes in
+                *pending_move.lock().expect("pending move lock") = Some(best_move);
+                send(&output, &format!("move {}", best_move));
+            }
+            Report::BestMove(None) => {}
+        });
+    }
+
+    fn play_pending_move(&mut self) {
+        let pending_move = self.pending_move.lock().expect("pending move lock").take();
+        if let Some(best_move) = pending_move {
+            self.engine.stop();
+            self.engine
+                .play(&best_move.to_string())
+                .expect("engine plays legal moves");
+            self.announce_game_over();
+        }
+    }
+
+    fn discard_pending_move(&mut self) {
+        self.pending_move.lock().expect("pending move lock").take();
+    }
+
+    // Claims the result at mate or stalemate, true if the game is over
+    fn announce_game_over(&mut self) -> bool {
+        let mut board = self.engine.board().clone();
+        if !board.legal_moves().is_empty() {
+            return false;
+        }
+
+        let side_to_move = board.side_to_move();
+        if board.is_in_check(side_to_move) {
+            match side_to_move {
+                Colour::White => self.send("0-1 {Black mates}"),
+                Colour::Black => self.send("1-0 {White mates}"),
+            }
+        } else {
+            self.send("1/2-1/2 {Stalemate}");
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chess_board::START_FEN;
+
+    fn run_commands(commands: &[&str]) -> String {
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let mut xboard = Xboard::new(Vec::new());
+        xboard.output = output.clone();
+
+        for command in commands {
+            xboard.handle_command(command);
+            // Let any search finish so the output is predictable
+            xboard.engine.stop();
+        }
+        xboard.handle_command("quit");
+
+        let output = output.lock().unwrap();
+        String::from_utf8(output.clone()).unwrap()
+    }
+
+    #[test]
+    fn test_feature_negotiation() {
+        let output = run_commands(&["xboard", "protover 2", "ping 7"]);
+        assert!(output.starts_with("feature myname=\"chess-rust"));
+        assert!(output.contains("usermove=1"));
+        assert!(output.ends_with("done=1\npong 7\n"));
+    }
+
+    #[test]
+    fn test_engine_replies_to_user_moves() {
+        let output = run_commands(&["new", "usermove e2e4", "usermove e2e4"]);
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 2, "{}", output);
+        assert!(lines[0].starts_with("move "));
+        assert_eq!(lines[1], "Illegal move: e2e4");
+    }
+
+    #[test]
+    fn test_force_setboard_and_go() {
+        let output = run_commands(&[
+            "new",
+            "force",
+            "setboard 4k3/8/8/3q4/4P3/8/8/4K3 w - - 0 1",
+            "post",
+            "go",
+        ]);
+        let lines: Vec<&str> = output.lines().collect();
+        assert!(lines[0].starts_with("1 "), "{}", output);
+        assert_eq!(lines.last(), Some(&"move e4d5"));
+    }
+
+    #[test]
+    fn test_setboard_rejects_bad_positions() {
+        let output = run_commands(&[
+            "new",
+            "force",
+            "setboard 4k3/8/8/8/8/8/8/4K3 w - - 0 1",
+            "setboard 8/8/8/8/8/8/8/4K3 w - - 0 1",
+            "setboard nonsense",
+        ]);
+        assert_eq!(
+            output,
+            "tellusererror Illegal position: 8/8/8/8/8/8/8/4K3 w - - 0 1\n\
+             tellusererror Illegal position: nonsense\n"
+        );
+    }
+
+    #[test]
+    fn test_user_move_ends_the_game() {
+        // The engine plays black and is mated, then stalemated
+        let output = run_commands(&[
+            "new",
+            "setboard k7/8/1K6/8/8/8/8/7Q w - - 0 1",
+            "usermove h1h8",
+        ]);
+        assert_eq!(output, "1-0 {White mates}\n");
+
+        let output = run_commands(&[
+            "new",
+            "setboard k7/2Q5/8/8/8/8/8/7K w - - 0 1",
+            "usermove h1g1",
+        ]);
+        assert_eq!(output, "1/2-1/2 {Stalemate}\n");
+    }
+
+    #[test]
+    fn test_force_mode_does_not_move() {
+        let output = run_commands(&["new", "force", "usermove e2e4", "usermove e7e5"]);
+        assert_eq!(output, "");
+    }
+
+    #[test]
+    fn test_undo_and_remove() {
+        let mut xboard = Xboard::new(Vec::new());
+        for command in ["new", "force", "usermove e2e4", "usermove e7e5", "undo"] {
+            xboard.handle_command(command);
+        }
+        assert_eq!(
+            xboard.engine.board().to_fen(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+        );
+        xboard.handle_command("usermove e7e5");
+        xboard.handle_command("remove");
+        assert_eq!(xboard.engine.board().to_fen(), START_FEN);
+    }
+
+    #[test]
+    fn test_level_and_time() {
+        let mut xboard = Xboard::new(Vec::new());
+        xboard.handle_command("level 40 5:30 2");
+        xboard.handle_command("time 6000");
+        xboard.handle_command("otim 5000");
+
+        // White to move, so the engine's clock is white's
+        let limits = xboard.limits();
+        assert_eq!(limits.wtime, Some(Duration::from_secs(60)));
+        assert_eq!(limits.btime, Some(Duration::from_secs(50)));
+        assert_eq!(limits.winc, Some(Duration::from_secs(2)));
+        assert_eq!(limits.movestogo, Some(40));
+
+        xboard.handle_command("st 5");
+        let limits = xboard.limits();
+        assert_eq!(limits.movetime, Some(Duration::from_secs(5)));
+        assert_eq!(limits.wtime, None);
+    }
+}
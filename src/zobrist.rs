@@ -0,0 +1,60 @@
+use crate::{
+    chess_board::{CastlingRights, SquareIndex, TOTAL_SQUARES},
+    piece::Colour,
+};
+
+const PIECES: &str = "PNBRQKpnbrqk";
+const PIECE_KEYS: usize = 12 * TOTAL_SQUARES as usize;
+const CASTLING_KEYS: usize = 4;
+const EN_PASSANT_KEYS: usize = 8;
+const TOTAL_KEYS: usize = PIECE_KEYS + CASTLING_KEYS + EN_PASSANT_KEYS + 1;
+
+// Random numbers from a xorshift generator, worked out at compile time so
+// hashes are the same on every run
+const KEYS: [u64; TOTAL_KEYS] = {
+    let mut keys = [0; TOTAL_KEYS];
+    let mut state: u64 = 0x9e37_79b9_7f4a_7c15;
+    let mut i = 0;
+    while i < TOTAL_KEYS {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        keys[i] = state;
+        i += 1;
+    }
+    keys
+};
+
+pub fn piece_key(symbol: char, square: SquareIndex) -> u64 {
+    match PIECES.find(symbol) {
+        Some(piece) => KEYS[piece * TOTAL_SQUARES as usize + square as usize],
+        None => 0,
+    }
+}
+
+pub fn castling_key(rights: CastlingRights) -> u64 {
+    [
+        rights.white_king_side,
+        rights.white_queen_side,
+        rights.black_king_side,
+        rights.black_queen_side,
+    ]
+    .iter()
+    .enumerate()
+    .filter(|(_, allowed)| **allowed)
+    .fold(0, |key, (i, _)| key ^ KEYS[PIECE_KEYS + i])
+}
+
+pub fn en_passant_key(square: Option<SquareIndex>) -> u64 {
+    match square {
+        Some(square) => KEYS[PIECE_KEYS + CASTLING_KEYS + (square % 8) as usize],
+        None => 0,
+    }
+}
+
+pub fn side_key(colour: Colour) -> u64 {
+    match colour {
+        Colour::White => 0,
+        Colour::Black => KEYS[TOTAL_KEYS - 1],
+    }
+}